@@ -0,0 +1,46 @@
+//! Times parsing and handling requests on the hot paths, straight through
+//! [`Handler`] so neither sockets nor hyprland are part of the measurement.
+
+use hypr_workspace_manager::server::{
+    simulation::{Handler, Simulation, Step},
+    types::Request,
+};
+use serde_json::json;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 100_000;
+
+async fn bench(handler: &mut Handler, request: &str) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let parsed: Request = serde_json::from_str(black_box(request)).unwrap();
+        handler.handle(parsed).await.unwrap();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let mut simulation = Simulation::default();
+        let bind = json!({"bind": {"name": "mail", "register": 1, "force": true}});
+        simulation.step(Step::Request(bind)).await.unwrap();
+        let mut handler = simulation.handler().unwrap();
+
+        for request in [
+            r#"{"goto":{"register":1}}"#,
+            r#"{"moveto":{"register":1}}"#,
+            r#"{"bind":{"name":"mail","register":2}}"#,
+        ] {
+            let per_request = bench(&mut handler, request).await;
+            println!("{request:<45} {per_request:>10.2?}");
+        }
+    });
+}
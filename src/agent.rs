@@ -0,0 +1,51 @@
+//! The named pipe `agent` reads commands from, so keybinds only have to write a line.
+
+use anyhow::{anyhow, Context, Result};
+use std::{
+    ffi::CString,
+    io,
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    net::unix::pipe,
+};
+
+pub const FIFO: &str = "ws-mgr.fifo";
+
+/// `ws-mgr.fifo` in the runtime directory of the user.
+pub fn default_fifo() -> Result<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(Path::new(&dir).join(FIFO)),
+        _ => Err(anyhow!(
+            "XDG_RUNTIME_DIR isn't set, pass the pipe with --fifo"
+        )),
+    }
+}
+
+/// The lines written to the named pipe at `path`, which is created unless it exists.
+///
+/// The pipe is also opened for writing, so it never reports an end when the last writer
+/// closes it and the lines never run out.
+pub async fn listen(path: &Path) -> Result<Lines<BufReader<pipe::Receiver>>> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => {}
+        Ok(_) => return Err(anyhow!("{} exists and isn't a named pipe", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let c_path = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: `c_path` is a valid nul terminated string.
+            if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("can't create {}", path.display()));
+            }
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    let receiver = pipe::OpenOptions::new()
+        .read_write(true)
+        .open_receiver(path)
+        .with_context(|| format!("can't open {}", path.display()))?;
+    Ok(BufReader::new(receiver).lines())
+}
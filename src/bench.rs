@@ -0,0 +1,94 @@
+//! `bench goto`, timing a key press the way a keybind makes it, against the running server.
+
+use crate::{
+    quick,
+    server::types::{Register, Request},
+};
+use anyhow::{anyhow, Result};
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// Latencies of the same request measured `iterations` times, each way it can be sent.
+#[derive(Debug)]
+pub struct Report {
+    request: String,
+    /// Starting the binary with the fast path arguments until it exits, like a keybind.
+    process: Vec<Duration>,
+    /// Sending the encoded request from here until hyprland acknowledged it.
+    request_only: Vec<Duration>,
+}
+
+/// Go to `register` `iterations` times each way, through the server at `socket`.
+///
+/// Blocks for the whole run, which sends everything the way the fast path does.
+pub fn goto(socket: &Path, register: Register, iterations: usize) -> Result<Report> {
+    let request = Request::Goto { register };
+    let msg = quick::encode(&request);
+    let exe = std::env::current_exe()?;
+
+    let mut process = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let status = Command::new(&exe)
+            .arg(format!("g{register}"))
+            .env("WS_MGR_SOCKET", socket)
+            .stdout(Stdio::null())
+            .status()?;
+        process.push(start.elapsed());
+        if !status.success() {
+            return Err(anyhow!("going to register {register} failed"));
+        }
+    }
+
+    let mut request_only = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        quick::send(socket, &msg)?;
+        request_only.push(start.elapsed());
+    }
+
+    Ok(Report {
+        request: String::from_utf8_lossy(&msg)
+            .lines()
+            .collect::<Vec<_>>()
+            .join(" "),
+        process,
+        request_only,
+    })
+}
+
+/// The sample below which `quantile` of the sorted `samples` are.
+fn percentile(samples: &[Duration], quantile: f64) -> Duration {
+    let index = ((samples.len() - 1) as f64 * quantile).round() as usize;
+    samples[index]
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}, {} iterations", self.request, self.process.len())?;
+        writeln!(
+            f,
+            "{:<10}{:>10}{:>10}{:>10}{:>10}",
+            "", "p50", "p90", "p99", "max"
+        )?;
+        for (name, samples) in [("process", &self.process), ("request", &self.request_only)] {
+            let mut samples = samples.clone();
+            samples.sort();
+            write!(f, "{name:<10}")?;
+            for quantile in [0.5, 0.9, 0.99, 1.0] {
+                let sample = percentile(&samples, quantile);
+                write!(
+                    f,
+                    "{:>10}",
+                    format!("{:.2}ms", sample.as_secs_f64() * 1000.0)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -1,41 +1,557 @@
 use crate::{
-    path_builder::PathBuilder,
+    agent, bench,
+    config::Config,
+    generate,
+    listen::Format,
+    logging::Logging,
+    man,
+    path_builder::Paths,
+    schema,
     server::{
-        types::{Request, Workspace as WorkspaceRef},
+        simulation::Simulation,
+        types::{
+            DynamicRule, Failure, ImportMode, MasterOrientation, OwnedReadResponse,
+            ParseWorkspaceError, PrefixAction, Register, Request, Snapshot, Update,
+            Workspace as WorkspaceRef, WorkspaceSettings,
+        },
         Server,
     },
-    socket::Socket,
+    socket::{Framing, Socket},
+    status::Template,
+};
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeSet,
+    fmt::{Debug, Display, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    process::Command,
+    task,
 };
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::{convert::Infallible, fmt::Debug, str::FromStr, sync::Arc};
-use tokio::io::{self, AsyncWriteExt};
+
+/// What the exit codes mean, which scripts can rely on.
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  1  any other failure
+  2  invalid arguments
+  3  no server is running, or it didn't accept the connection in time
+  4  the register is unbound, or the workspace unknown
+  5  hyprland failed to carry the request out
+  6  the state changed meanwhile, like the register bind-if expected";
 
 #[derive(Debug, Parser)]
+#[clap(after_help = EXIT_CODES)]
 pub struct Cli {
+    #[clap(flatten)]
+    paths: Paths,
+    #[clap(flatten)]
+    logging: Logging,
+    #[clap(flatten)]
+    output: Output,
     #[clap(subcommand)]
     operation: Operation,
 }
 
+/// How much the cli prints besides errors.
+#[derive(Debug, Clone, Copy, Default, Args)]
+#[clap(about = None, long_about = None, next_help_heading = "Output")]
+struct Output {
+    /// Print nothing but errors, so nothing piles up wherever keybinds print to
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also print every message sent to and received from the server on stderr, including
+    /// hyprlands reply to the batch
+    #[clap(short, long, global = true)]
+    verbose: bool,
+}
+
+/// The [`Output`] flags, set once before anything is printed.
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+fn output() -> Output {
+    OUTPUT.get().copied().unwrap_or_default()
+}
+
 #[derive(Debug, Subcommand)]
 enum Operation {
-    Server,
-    Create { name: String },
-    Bind { name: String, register: u8 },
-    Unbind { register: u8 },
-    Goto { register: u8 },
-    Moveto { register: u8 },
-    Read { workspace: Option<Workspace> },
+    Server {
+        /// Take the socket and all subscriptions over from the running server, which then exits
+        #[clap(long)]
+        takeover: bool,
+        /// Detach from the terminal once the server is up, logging to server.log in the state
+        /// dir unless --log-file is given
+        ///
+        /// The pid of the server is written to the lock file next to the socket.
+        #[clap(long)]
+        daemon: bool,
+    },
+    /// Start the server unless it is running, and apply the declared state from the config
+    ///
+    /// Meant as the single `exec-once` line in hyprland.conf, it returns once the server is up.
+    Init,
+    Create {
+        name: String,
+        /// Template from the config to take the settings and commands from
+        #[clap(long)]
+        template: Option<String>,
+    },
+    /// Bind workspaces to registers, given as pairs like `dev 1 mail 2`
+    Bind {
+        #[clap(required = true, num_args = 2.., value_names = ["NAME", "REGISTER"])]
+        bindings: Vec<String>,
+        /// Rebind locked registers, and bind workspaces that neither the server nor hyprland
+        /// know yet
+        #[clap(long)]
+        force: bool,
+        /// Unbind the registers again after this long, like `90s`, `30m`, `2h` or `1d`
+        #[clap(long)]
+        ttl: Option<Ttl>,
+    },
+    /// Bind a workspace to a register, only if the register still points to `--expected`, or
+    /// is unbound without it
+    ///
+    /// Fails with exit code 6 if it doesn't, so scripts can read, decide and bind without
+    /// racing each other.
+    BindIf {
+        name: String,
+        register: Register,
+        #[clap(long)]
+        expected: Option<String>,
+        /// Rebind a locked register, and bind a workspace that neither the server nor hyprland
+        /// know yet
+        #[clap(long)]
+        force: bool,
+    },
+    /// Let a register follow the focus instead of a fixed workspace
+    BindDynamic {
+        register: Register,
+        #[clap(value_enum)]
+        rule: DynamicRule,
+        /// Rebind the register even if it is locked
+        #[clap(long)]
+        force: bool,
+    },
+    /// Unbind registers, given one by one or as ranges like `1-5`
+    Unbind {
+        #[clap(required = true)]
+        registers: Vec<Registers>,
+        /// Unbind locked registers as well
+        #[clap(long)]
+        force: bool,
+    },
+    /// Lock registers, so binding or unbinding them fails unless forced
+    Lock {
+        #[clap(required = true)]
+        registers: Vec<Registers>,
+    },
+    Unlock {
+        #[clap(required = true)]
+        registers: Vec<Registers>,
+    },
+    /// Pin the workspace of a register to a monitor, moving it back whenever it is moved away
+    PinMonitor {
+        register: Register,
+        /// Monitor to pin it to, the focused one if not given
+        monitor: Option<String>,
+    },
+    UnpinMonitor {
+        register: Register,
+    },
+    /// Replace the settings of a workspace, which are applied as hyprland workspace rules
+    Configure {
+        name: String,
+        /// Monitor the workspace opens on
+        #[clap(long)]
+        monitor: Option<String>,
+        /// Gaps between windows, in pixels
+        #[clap(long)]
+        gaps_in: Option<u32>,
+        /// Gaps between windows and monitor edges, in pixels
+        #[clap(long)]
+        gaps_out: Option<u32>,
+        /// Keep the workspace open without windows, and out of garbage collection
+        #[clap(long)]
+        persistent: Option<bool>,
+        /// Layout to tile the workspace with, like dwindle or master
+        #[clap(long)]
+        layout: Option<String>,
+        /// Side the master layout puts the master area on
+        #[clap(long, value_enum)]
+        master_orientation: Option<MasterOrientation>,
+        /// Directory of the project the workspace belongs to
+        #[clap(long)]
+        cwd: Option<String>,
+        /// Make the workspace a scratchpad, a special workspace that goto toggles
+        #[clap(long)]
+        special: Option<bool>,
+        /// Icon for bars to show in place of the name
+        #[clap(long)]
+        icon: Option<String>,
+        /// Label for bars to show in place of the name
+        #[clap(long)]
+        label: Option<String>,
+        /// Context the workspace belongs to, bars only show it while switched to that context
+        #[clap(long)]
+        context: Option<String>,
+        /// Mark the workspace as asking for attention, until it is focused
+        #[clap(long)]
+        urgent: Option<bool>,
+        /// Window rule for the windows on the workspace, like `float, class:^(mpv)$`, repeatable
+        #[clap(long = "windowrule")]
+        windowrules: Vec<String>,
+    },
+    /// Move every window from one workspace to another
+    ///
+    /// Workspaces are given as registers or names, `reg:7` and `name:7` tell which one is meant.
+    Merge {
+        from: Workspace,
+        to: Workspace,
+        /// Remove the emptied workspace along with its registers
+        #[clap(long)]
+        delete: bool,
+    },
+    Goto {
+        register: Register,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Go to the register of the nth most recently focused workspace with one, 2 for the one
+    /// before the current, like alt-tab
+    GotoMru {
+        n: usize,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    Moveto {
+        register: Register,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Send hyprland dispatchers in one batch, each quoted with its arguments like 'movefocus l'
+    Dispatch {
+        #[clap(required = true)]
+        dispatchers: Vec<String>,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Go to a workspace picked by hyprland, like e+1, m-1, r+1, previous or empty
+    HyprGoto {
+        selector: String,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Move the focused window to a workspace picked by hyprland, like e+1 or previous
+    HyprMoveto {
+        selector: String,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Bring the workspace of a register to the focused monitor and go to it
+    Fetch {
+        register: Register,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Move the focused window to the workspace of a register and bring that to the focused
+    /// monitor
+    MovetoHere {
+        register: Register,
+        /// Print what would be sent to hyprland instead of sending it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Have the next goto do something else with its register, for two-key sequences
+    Prefix {
+        #[clap(value_enum)]
+        action: PrefixAction,
+    },
+    /// Go to the workspace of a register, telling whether it is unbound instead of failing
+    GotoOrBind {
+        register: Register,
+        /// Bind an unbound register to the focused workspace
+        #[clap(long)]
+        bind: bool,
+    },
+    /// Print the workspaces, or only the one given as a register or name
+    ///
+    /// `reg:7` and `name:7` tell which one is meant.
+    Read {
+        workspace: Option<Workspace>,
+        /// Print every workspace and register as its own line as soon as it arrives
+        #[clap(long)]
+        stream: bool,
+        /// Only list the registers in a range like `1-9`, and the workspaces they point to
+        #[clap(long, conflicts_with = "workspace")]
+        registers: Option<Registers>,
+    },
+    /// Remove every empty workspace no register points to
+    Gc,
+    Undo,
+    /// Apply the declared state from the config right away, without waiting for the watcher
+    Reload,
+    History {
+        limit: Option<usize>,
+    },
+    Export,
+    Import {
+        /// Snapshot to import, read from stdin if omitted
+        file: Option<PathBuf>,
+        /// Replace the current state instead of merging into it
+        #[clap(long)]
+        replace: bool,
+    },
+    /// Show what changed since a snapshot was exported, like bindings added or removed
+    Diff {
+        /// Snapshot exported before, read from stdin if omitted
+        file: Option<PathBuf>,
+    },
+    /// Run a simulation script against an in-memory server and print the transcript
+    Simulate {
+        script: PathBuf,
+    },
+    /// Stream every change to the server state, starting with a snapshot
+    Subscribe,
+    Stats,
+    /// Print counters in the prometheus text exposition format
+    Metrics,
+    /// Print the last log records of the server
+    Logs {
+        lines: Option<usize>,
+    },
+    /// Check whether the server is up
+    Ping,
+    /// Print the whole state as one json line, and again on every change
+    Listen {
+        #[clap(long, value_enum, default_value_t)]
+        format: Format,
+        /// Subscribe again if the server drops the subscription, starting over from a snapshot
+        #[clap(long)]
+        reconnect: bool,
+    },
+    State {
+        #[clap(subcommand)]
+        command: StateCommand,
+    },
+    Project {
+        #[clap(subcommand)]
+        command: ProjectCommand,
+    },
+    /// Contexts like work and personal, which bars only show the workspaces of
+    Context {
+        #[clap(subcommand)]
+        command: ContextCommand,
+    },
+    /// Named groups of workspaces to cycle through
+    Group {
+        #[clap(subcommand)]
+        command: GroupCommand,
+    },
+    /// Print hyprland.conf keybinds for goto and moveto on every register
+    GenerateBinds {
+        /// Number of registers to bind, defaults to the config
+        #[clap(long)]
+        count: Option<Register>,
+        /// Modifier to bind with, defaults to the config
+        #[clap(long = "mod")]
+        modifier: Option<String>,
+    },
+    /// Print a hyprland.conf submap with a key for every bound register
+    ///
+    /// Keep it in sync by running this from the on_bind and on_unbind hooks with --output,
+    /// followed by `hyprctl reload`.
+    GenerateSubmap {
+        /// Name of the submap, defaults to the config
+        #[clap(long)]
+        name: Option<String>,
+        /// Write the submap to this file instead of printing it
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the state as a single line for status bars, like '{registers:|} {active:[*]}'
+    ///
+    /// Placeholders are registers, bindings and workspaces, with the separator to put between
+    /// entries after a `:`, and active and active_register, with a pattern like `[*]` to put
+    /// the value in. `{{` and `}}` are literal braces.
+    Format {
+        template: String,
+    },
+    /// Stay resident and run the commands written to a named pipe, one per line
+    ///
+    /// Keybinds then don't start a process each, like `echo goto 3 > $XDG_RUNTIME_DIR/ws-mgr.fifo`.
+    Agent {
+        /// The named pipe to read from, created if missing [default: ws-mgr.fifo in $XDG_RUNTIME_DIR]
+        #[clap(long)]
+        fifo: Option<PathBuf>,
+    },
+    /// Print a json description of the cli and the protocol spoken on the socket
+    Schema,
+    /// Print the manual of every command and of the protocol spoken on the socket, as a man
+    /// page
+    ///
+    /// Packagers can install it with `ws-mgr man > ws-mgr.1`, `man -l <(ws-mgr man)` reads it
+    /// right away.
+    Man {
+        /// Print plain text instead, the help of every command at once
+        #[clap(long)]
+        plain: bool,
+    },
+    /// Time requests against the running server, which they are actually sent to
+    Bench {
+        #[clap(subcommand)]
+        command: BenchCommand,
+    },
+    /// Commands defined in the `[alias]` section of the config
+    #[clap(external_subcommand)]
+    Alias(Vec<String>),
+}
+
+/// The operation an alias expands to.
+#[derive(Debug, Parser)]
+#[clap(no_binary_name = true)]
+struct Expansion {
+    #[clap(subcommand)]
+    operation: Operation,
+}
+
+#[derive(Debug, Subcommand)]
+enum StateCommand {
+    /// Show how the state of another machine differs from the local one
+    Diff {
+        /// Exported snapshot, or a host to run `export` on via ssh if no such file exists
+        #[clap(long)]
+        remote: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BenchCommand {
+    /// Time going to a register, once started like a keybind and once as just the request
+    Goto {
+        #[clap(default_value_t = 1)]
+        register: Register,
+        #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ContextCommand {
+    /// Hide the workspaces of other contexts from bars and groups, or of none if not given
+    Switch { context: Option<String> },
+}
+
+#[derive(Debug, Subcommand)]
+enum GroupCommand {
+    /// Group workspaces, creating those that don't exist
+    Create {
+        group: String,
+        #[clap(required = true)]
+        members: Vec<String>,
+    },
+    Remove {
+        group: String,
+    },
+    /// Go to the member of a group after the focused one
+    Goto {
+        group: String,
+    },
+    /// Print the members of every group, or of just one
+    List {
+        group: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ProjectCommand {
+    /// Go to the workspace of a project directory, creating it if needed
+    Open {
+        path: PathBuf,
+        /// Also bind the workspace to this register
+        #[clap(long)]
+        register: Option<Register>,
+        /// Open the configured terminal in the project directory
+        #[clap(long)]
+        terminal: bool,
+    },
+}
+
+/// A single register or an inclusive range of them, like `1-5`.
+#[derive(Debug, Clone)]
+struct Registers(RangeInclusive<Register>);
+
+impl FromStr for Registers {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parse = |register: &str| {
+            register
+                .parse()
+                .map_err(|_| format!("invalid register {register:?}"))
+        };
+        let range = match s.split_once('-') {
+            Some((start, end)) => parse(start)?..=parse(end)?,
+            None => parse(s)?..=parse(s)?,
+        };
+        if range.is_empty() {
+            return Err(format!("range {s} is empty"));
+        }
+        Ok(Self(range))
+    }
+}
+
+/// A duration in seconds, given with a unit like `90s`, `30m`, `2h` or `1d`.
+#[derive(Debug, Clone, Copy)]
+struct Ttl(u32);
+
+impl FromStr for Ttl {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("invalid duration {s:?}, expected something like 30m or 2h");
+        let (amount, unit) = s
+            .split_at_checked(s.len().saturating_sub(1))
+            .ok_or_else(invalid)?;
+        let unit = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount: u32 = amount.parse().map_err(|_| invalid())?;
+        amount
+            .checked_mul(unit)
+            .filter(|seconds| *seconds > 0)
+            .map(Self)
+            .ok_or_else(invalid)
+    }
 }
 
 #[derive(Debug, Clone)]
 enum Workspace {
     Workspace(String),
-    Register(u8),
+    Register(Register),
 }
 
 impl Workspace {
-    fn as_workspace_ref(&self) -> WorkspaceRef {
+    fn as_workspace_ref(&self) -> WorkspaceRef<'_> {
         match self {
             Workspace::Workspace(name) => WorkspaceRef::Workspace(name),
             Workspace::Register(register) => WorkspaceRef::Register(*register),
@@ -44,50 +560,781 @@ impl Workspace {
 }
 
 impl FromStr for Workspace {
-    type Err = Infallible;
+    type Err = ParseWorkspaceError;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Ok(s.parse()
-            .map(Workspace::Register)
-            .unwrap_or_else(|_| Workspace::Workspace(s.to_owned())))
+        Ok(match WorkspaceRef::parse(s)? {
+            WorkspaceRef::Workspace(name) => Workspace::Workspace(name.to_owned()),
+            WorkspaceRef::Register(register) => Workspace::Register(register),
+        })
     }
 }
 
 impl Cli {
+    /// Where the server logs to if it is started with `--daemon`, `None` for anything else.
+    pub fn daemon_log(&self) -> Result<Option<PathBuf>> {
+        if !matches!(self.operation, Operation::Server { daemon: true, .. }) {
+            return Ok(None);
+        }
+        Ok(Some(match self.logging.file() {
+            Some(file) => file.to_owned(),
+            None => self.paths.state_dir()?.join(Self::DAEMON_LOG),
+        }))
+    }
+
+    const DAEMON_LOG: &'static str = "server.log";
+
     pub async fn run(self) -> Result<()> {
-        match self.operation {
-            Operation::Server => Arc::new(Server::default()).run().await,
-            Operation::Create { name } => write_to_socket(Request::Create { name: &name }).await,
-            Operation::Bind { name, register } => {
-                write_to_socket(Request::Bind {
-                    name: &name,
-                    register,
-                })
+        match self.daemon_log()? {
+            Some(log) => {
+                if let Some(dir) = log.parent() {
+                    fs::create_dir_all(dir).await?;
+                }
+                self.logging.clone().with_file(log).init()?;
+            }
+            None => self.logging.init()?,
+        }
+        OUTPUT.get_or_init(|| self.output);
+        execute(&self.paths, &self.logging, self.operation).await
+    }
+}
+
+/// Carry `operation` out, expanding it first if it is an alias.
+async fn execute(paths: &Paths, logging: &Logging, operation: Operation) -> Result<()> {
+    let operation = match operation {
+        Operation::Alias(args) => expand_alias(paths, &args).await?,
+        operation => operation,
+    };
+    match operation {
+        Operation::Server { takeover, .. } => {
+            let config = Config::load(paths).await?;
+            // Hyprland may still be starting when the server is started from exec-once.
+            paths.wait_for_hypr(config.hypr.startup_timeout()).await?;
+            paths.verify()?;
+            if takeover {
+                // The log may only be opened once the old server stopped writing it.
+                let handed = Server::request_handoff(paths).await?;
+                Arc::new(Server::open(config, paths).await?)
+                    .take_over(paths, handed)
+                    .await
+            } else {
+                Arc::new(Server::open(config, paths).await?)
+                    .run(paths)
+                    .await
+            }
+        }
+        Operation::Init => {
+            if connect(paths).await.is_err() {
+                let started = start_server(paths, logging).await;
+                // Another init may have started it meanwhile.
+                if started.is_err() && connect(paths).await.is_err() {
+                    return started;
+                }
+            }
+            write_to_socket(paths, Request::Reload).await
+        }
+        Operation::Create { name, template } => match &template {
+            Some(template) => {
+                write_to_socket(
+                    paths,
+                    Request::CreateFrom {
+                        name: &name,
+                        template,
+                    },
+                )
                 .await
             }
-            Operation::Unbind { register } => write_to_socket(Request::Unbind { register }).await,
-            Operation::Goto { register } => write_to_socket(Request::Goto { register }).await,
-            Operation::Moveto { register } => write_to_socket(Request::Moveto { register }).await,
-            Operation::Read { workspace } => {
-                write_to_socket(Request::Read {
-                    workspace: workspace.as_ref().map(Workspace::as_workspace_ref),
+            None => write_to_socket(paths, Request::Create { name: &name }).await,
+        },
+        Operation::Bind {
+            bindings,
+            force,
+            ttl,
+        } => {
+            if bindings.len() % 2 != 0 {
+                return Err(usage_error(
+                    "expected pairs of workspace names and registers",
+                ));
+            }
+            let requests = bindings
+                .chunks(2)
+                .map(|binding| {
+                    let register = binding[1]
+                        .parse()
+                        .map_err(|_| usage_error(format!("invalid register {:?}", binding[1])))?;
+                    Ok(Request::Bind {
+                        name: &binding[0],
+                        register,
+                        force,
+                        ttl: ttl.map(|ttl| ttl.0),
+                    })
                 })
-                .await
+                .collect::<Result<_>>()?;
+            write_to_socket(paths, batch(requests)).await
+        }
+        Operation::BindIf {
+            name,
+            register,
+            expected,
+            force,
+        } => {
+            write_to_socket(
+                paths,
+                Request::BindIf {
+                    name: &name,
+                    register,
+                    expected: expected.as_deref(),
+                    force,
+                },
+            )
+            .await
+        }
+        Operation::BindDynamic {
+            register,
+            rule,
+            force,
+        } => {
+            write_to_socket(
+                paths,
+                Request::BindDynamic {
+                    register,
+                    rule,
+                    force,
+                },
+            )
+            .await
+        }
+        Operation::Unbind { registers, force } => {
+            let requests = registers
+                .iter()
+                .flat_map(|registers| registers.0.clone())
+                .map(|register| Request::Unbind { register, force })
+                .collect();
+            write_to_socket(paths, batch(requests)).await
+        }
+        Operation::Lock { registers } => {
+            let requests = registers
+                .iter()
+                .flat_map(|registers| registers.0.clone())
+                .map(|register| Request::Lock { register })
+                .collect();
+            write_to_socket(paths, batch(requests)).await
+        }
+        Operation::Unlock { registers } => {
+            let requests = registers
+                .iter()
+                .flat_map(|registers| registers.0.clone())
+                .map(|register| Request::Unlock { register })
+                .collect();
+            write_to_socket(paths, batch(requests)).await
+        }
+        Operation::PinMonitor { register, monitor } => {
+            write_to_socket(
+                paths,
+                Request::PinMonitor {
+                    register,
+                    monitor: monitor.as_deref(),
+                },
+            )
+            .await
+        }
+        Operation::UnpinMonitor { register } => {
+            write_to_socket(paths, Request::UnpinMonitor { register }).await
+        }
+        Operation::Configure {
+            name,
+            monitor,
+            gaps_in,
+            gaps_out,
+            persistent,
+            layout,
+            master_orientation,
+            cwd,
+            special,
+            icon,
+            label,
+            context,
+            urgent,
+            windowrules,
+        } => {
+            let settings = WorkspaceSettings {
+                monitor,
+                gaps_in,
+                gaps_out,
+                persistent,
+                layout,
+                master_orientation,
+                cwd,
+                special,
+                icon,
+                label,
+                context,
+                urgent,
+                windowrules,
+            };
+            write_to_socket(
+                paths,
+                Request::Configure {
+                    name: &name,
+                    settings,
+                },
+            )
+            .await
+        }
+        Operation::Merge { from, to, delete } => {
+            write_to_socket(
+                paths,
+                Request::Merge {
+                    from: from.as_workspace_ref(),
+                    to: to.as_workspace_ref(),
+                    delete,
+                },
+            )
+            .await
+        }
+        Operation::Goto { register, dry_run } => {
+            dispatch(paths, Request::Goto { register }, dry_run).await
+        }
+        Operation::GotoMru { n, dry_run } => dispatch(paths, Request::GotoMru { n }, dry_run).await,
+        Operation::Moveto { register, dry_run } => {
+            dispatch(paths, Request::Moveto { register }, dry_run).await
+        }
+        Operation::Dispatch {
+            dispatchers,
+            dry_run,
+        } => {
+            let requests = dispatchers
+                .iter()
+                .map(|raw| Request::Dispatch { raw })
+                .collect();
+            dispatch(paths, batch(requests), dry_run).await
+        }
+        Operation::HyprGoto { selector, dry_run } => {
+            dispatch(
+                paths,
+                Request::HyprGoto {
+                    selector: &selector,
+                },
+                dry_run,
+            )
+            .await
+        }
+        Operation::HyprMoveto { selector, dry_run } => {
+            dispatch(
+                paths,
+                Request::HyprMoveto {
+                    selector: &selector,
+                },
+                dry_run,
+            )
+            .await
+        }
+        Operation::Fetch { register, dry_run } => {
+            dispatch(paths, Request::Fetch { register }, dry_run).await
+        }
+        Operation::MovetoHere { register, dry_run } => {
+            dispatch(paths, Request::MovetoHere { register }, dry_run).await
+        }
+        Operation::Prefix { action } => write_to_socket(paths, Request::Prefix { action }).await,
+        Operation::GotoOrBind { register, bind } => {
+            write_to_socket(paths, Request::GotoOrBind { register, bind }).await
+        }
+        Operation::Read {
+            workspace,
+            stream,
+            registers,
+        } => {
+            write_to_socket(
+                paths,
+                Request::Read {
+                    workspace: workspace.as_ref().map(Workspace::as_workspace_ref),
+                    stream,
+                    registers: registers.map(|registers| registers.0),
+                },
+            )
+            .await
+        }
+        Operation::Gc => write_to_socket(paths, Request::Gc).await,
+        Operation::Undo => write_to_socket(paths, Request::Undo).await,
+        Operation::Reload => write_to_socket(paths, Request::Reload).await,
+        Operation::History { limit } => write_to_socket(paths, Request::History { limit }).await,
+        Operation::Export => write_to_socket(paths, Request::Export).await,
+        Operation::Import { file, replace } => {
+            let state = read_snapshot(file.as_deref()).await?;
+            write_to_socket(
+                paths,
+                Request::Import {
+                    mode: if replace {
+                        ImportMode::Replace
+                    } else {
+                        ImportMode::Merge
+                    },
+                    state,
+                },
+            )
+            .await
+        }
+        Operation::Diff { file } => {
+            let exported = read_snapshot(file.as_deref()).await?;
+            let current: Snapshot = query(paths, Request::Export).await?;
+            write_changes(&exported, &current).await
+        }
+        Operation::Simulate { script } => {
+            let script = fs::read_to_string(script).await?;
+            let transcript = Simulation::default().run(&script).await?;
+
+            write_to_stdout(transcript.as_bytes()).await
+        }
+        Operation::Subscribe => {
+            let mut socket = connect(paths).await?;
+            send(&mut socket, &Request::Subscribe)?;
+            socket.flush().await?;
+
+            while let Some(update) = receive(&mut socket).await? {
+                let mut line = update.to_vec();
+                line.push(b'\n');
+                write_to_stdout(&line).await?;
+            }
+            Ok(())
+        }
+        Operation::Stats => write_to_socket(paths, Request::Stats).await,
+        Operation::Metrics => write_to_socket(paths, Request::Metrics).await,
+        Operation::Logs { lines } => write_to_socket(paths, Request::Logs { lines }).await,
+        Operation::Ping => write_to_socket(paths, Request::Ping).await,
+        Operation::Listen { format, reconnect } => loop {
+            let mut socket = connect(paths).await?;
+            send(&mut socket, &Request::Subscribe)?;
+            socket.flush().await?;
+
+            // Exports don't carry what hyprland reported urgent, so keep it from the last snapshot.
+            let mut urgent = BTreeSet::new();
+            while let Some(update) = receive(&mut socket).await? {
+                let state = match serde_json::from_slice(update)? {
+                    Update::Snapshot { state, .. } => {
+                        urgent.clone_from(&state.urgent);
+                        state
+                    }
+                    // Records can't always be applied locally (think undo), so just refetch.
+                    Update::Record(_) => Snapshot {
+                        urgent: urgent.clone(),
+                        ..query(paths, Request::Export).await?
+                    },
+                };
+
+                let mut line = format.render(&state)?;
+                line.push('\n');
+                write_to_stdout(line.as_bytes()).await?;
+            }
+            if !reconnect {
+                return Ok(());
+            }
+        },
+        Operation::Context {
+            command: ContextCommand::Switch { context },
+        } => {
+            write_to_socket(
+                paths,
+                Request::SwitchContext {
+                    context: context.as_deref(),
+                },
+            )
+            .await
+        }
+        Operation::Group { command } => {
+            let request = match &command {
+                GroupCommand::Create { group, members } => Request::GroupCreate {
+                    group,
+                    members: members.iter().map(String::as_str).collect(),
+                },
+                GroupCommand::Remove { group } => Request::GroupRemove { group },
+                GroupCommand::Goto { group } => Request::GroupGoto { group },
+                GroupCommand::List { group } => Request::GroupRead {
+                    group: group.as_deref(),
+                },
+            };
+            write_to_socket(paths, request).await
+        }
+        Operation::State {
+            command: StateCommand::Diff { remote },
+        } => {
+            let remote = if Path::new(&remote).exists() {
+                fs::read_to_string(&remote).await?
+            } else {
+                let output = Command::new("ssh")
+                    .arg(&remote)
+                    .arg(format!("{} export", invoked_as()))
+                    .output()
+                    .await?;
+                if !output.status.success() {
+                    return Err(anyhow!(
+                        "exporting state from {remote} failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                String::from_utf8(output.stdout)?
+            };
+            let remote: Snapshot = serde_json::from_str(&remote)?;
+            remote.check_version()?;
+            let local: Snapshot = query(paths, Request::Export).await?;
+            write_changes(&local, &remote).await
+        }
+        Operation::Project {
+            command:
+                ProjectCommand::Open {
+                    path,
+                    register,
+                    terminal,
+                },
+        } => {
+            let path = fs::canonicalize(&path).await?;
+            let path = path
+                .to_str()
+                .ok_or_else(|| anyhow!("{} isn't valid utf-8", path.display()))?;
+            write_to_socket(
+                paths,
+                Request::OpenProject {
+                    path,
+                    register,
+                    terminal,
+                },
+            )
+            .await
+        }
+        Operation::GenerateBinds { count, modifier } => {
+            let mut config = Config::load(paths).await?.binds;
+            config.count = count.unwrap_or(config.count);
+            config.modifier = modifier.unwrap_or(config.modifier);
+            let cmd = match &config.command {
+                Some(cmd) => cmd.clone(),
+                None => invoked_as(),
+            };
+
+            let mut out = String::new();
+            generate::binds(&config, &cmd, &mut out)?;
+
+            write_to_stdout(out.as_bytes()).await
+        }
+        Operation::GenerateSubmap { name, output } => {
+            let config = Config::load(paths).await?;
+            let mut submap = config.submap;
+            submap.name = name.unwrap_or(submap.name);
+            let cmd = match &config.binds.command {
+                Some(cmd) => cmd.clone(),
+                None => invoked_as(),
+            };
+            let state: Snapshot = query(paths, Request::Export).await?;
+
+            let mut out = String::new();
+            generate::submap(&submap, &state.registers, &cmd, &mut out);
+
+            match output {
+                Some(output) => fs::write(&output, out)
+                    .await
+                    .with_context(|| format!("failed to write {}", output.display())),
+                None => write_to_stdout(out.as_bytes()).await,
+            }
+        }
+        Operation::Format { template } => {
+            let template = Template::parse(&template)?;
+            let request = Request::Read {
+                workspace: None,
+                stream: false,
+                registers: None,
+            };
+            let state: OwnedReadResponse = query(paths, request).await?;
+            write_to_stdout(format!("{}\n", template.render(&state)).as_bytes()).await
+        }
+        Operation::Schema => {
+            let schema = json!({
+                "cli": describe(&Cli::command()),
+                "protocol": schema::protocol(),
+            });
+            write_to_stdout(format!("{schema:#}\n").as_bytes()).await
+        }
+        Operation::Man { plain } => {
+            let style = match plain {
+                true => man::Style::Plain,
+                false => man::Style::Roff,
+            };
+            let page = man::render(&Cli::command(), &schema::protocol(), style);
+            write_to_stdout(page.as_bytes()).await
+        }
+        Operation::Agent { fifo } => {
+            let fifo = match fifo {
+                Some(fifo) => fifo,
+                None => agent::default_fifo()?,
+            };
+            let mut commands = agent::listen(&fifo).await?;
+            while let Some(line) = commands.next_line().await? {
+                let res = match agent_operation(&line) {
+                    Ok(Some(operation)) => Box::pin(execute(paths, logging, operation)).await,
+                    Ok(None) => Ok(()),
+                    Err(err) => Err(err),
+                };
+                // A failing command doesn't stop the agent.
+                if let Err(err) = res {
+                    eprintln!("Error: {line:?} failed: {err:#}");
+                }
+            }
+            Ok(())
+        }
+        Operation::Bench {
+            command:
+                BenchCommand::Goto {
+                    register,
+                    iterations,
+                },
+        } => {
+            let socket = paths.socket()?;
+            let report =
+                task::spawn_blocking(move || bench::goto(&socket, register, iterations as usize))
+                    .await??;
+            write_to_stdout(report.to_string().as_bytes()).await
+        }
+        Operation::Alias(_) => unreachable!("aliases are expanded before"),
+    }
+}
+
+/// Describe the subcommands of `command` and the arguments they take.
+fn describe(command: &clap::Command) -> Value {
+    let args: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .map(|arg| {
+            json!({
+                "name": arg.get_id().as_str(),
+                "long": arg.get_long(),
+                "help": arg.get_help().map(ToString::to_string),
+                "required": arg.is_required_set(),
+                "takes_value": arg.get_action().takes_values(),
+            })
+        })
+        .collect();
+    let subcommands: Map<_, _> = command
+        .get_subcommands()
+        .map(|subcommand| (subcommand.get_name().to_owned(), describe(subcommand)))
+        .collect();
+
+    json!({
+        "about": command.get_about().map(ToString::to_string),
+        "args": args,
+        "subcommands": subcommands,
+    })
+}
+
+/// Split `line` into words on whitespace, like a shell would.
+///
+/// Whitespace inside `"double quotes"` doesn't split, and a backslash takes the next character
+/// literally, both inside quotes and outside.
+pub fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quoted = false;
+
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("{line:?} ends in the middle of an escape"))?;
+                word.get_or_insert_with(String::new).push(escaped);
             }
+            '"' => {
+                quoted = !quoted;
+                // `""` is an empty word rather than none.
+                word.get_or_insert_with(String::new);
+            }
+            char if char.is_whitespace() && !quoted => words.extend(word.take()),
+            char => word.get_or_insert_with(String::new).push(char),
         }
     }
+    if quoted {
+        return Err(anyhow!("{line:?} has an unterminated quote"));
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// The operation `line` written to the agent asks for, `None` if it is blank.
+fn agent_operation(line: &str) -> Result<Option<Operation>> {
+    let words = split_words(line)?;
+    if words.is_empty() {
+        return Ok(None);
+    }
+    let operation = Expansion::try_parse_from(&words)?.operation;
+    match operation {
+        // These take over the agent instead of being done with.
+        Operation::Server { .. }
+        | Operation::Agent { .. }
+        | Operation::Subscribe
+        | Operation::Listen { .. } => Err(anyhow!("{} can't be run by the agent", words[0])),
+        operation => Ok(Some(operation)),
+    }
+}
+
+/// Expand the alias `args[0]` as configured, with the rest of `args` appended.
+async fn expand_alias(paths: &Paths, args: &[String]) -> Result<Operation> {
+    let (name, rest) = args
+        .split_first()
+        .expect("external subcommands come with their name");
+    let alias = Config::load(paths).await?.alias;
+    let expansion = alias
+        .get(name)
+        .ok_or_else(|| usage_error(format!("unknown command {name:?}")))?;
+
+    let words = split_words(expansion).with_context(|| format!("invalid alias {name:?}"))?;
+    let args = words.iter().chain(rest);
+    match Expansion::try_parse_from(args)?.operation {
+        Operation::Alias(args) => Err(anyhow!(
+            "alias {name:?} expands to the unknown command {:?}, aliases don't nest",
+            args[0]
+        )),
+        operation => Ok(operation),
+    }
+}
+
+async fn connect(paths: &Paths) -> Result<Socket> {
+    Ok(Socket::connect(&paths.socket()?)
+        .await?
+        .with_framing(Framing::LengthPrefixed))
+}
+
+/// Start `server --daemon` with the same paths and logging, returning once it is up.
+async fn start_server(paths: &Paths, logging: &Logging) -> Result<()> {
+    let status = Command::new(std::env::current_exe()?)
+        .args(paths.args())
+        .args(logging.args())
+        .args(["server", "--daemon"])
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(anyhow!("starting the server failed"));
+    }
+    Ok(())
+}
+
+async fn write_to_socket(paths: &Paths, request: Request<'_>) -> Result<()> {
+    dispatch(paths, request, false).await
+}
+
+/// Combine `requests` into a single [`Request::Batch`], unless there is only one.
+fn batch(mut requests: Vec<Request<'_>>) -> Request<'_> {
+    match requests.len() {
+        1 => requests.remove(0),
+        _ => Request::Batch(requests),
+    }
+}
+
+/// A mistake in the arguments clap can't tell, which is reported and exits the same way.
+fn usage_error(message: impl Display) -> anyhow::Error {
+    Cli::command()
+        .error(clap::error::ErrorKind::ValueValidation, message)
+        .into()
 }
 
-async fn write_to_socket(request: Request<'_>) -> Result<()> {
-    let mut hypr_dir = PathBuilder::hypr_basepath()?;
-    let mut socket = Socket::connect(hypr_dir.with_filename(Server::SOCKET)).await?;
+/// Send `request`, followed by either sending its dispatches to hyprland or, with `dry_run`,
+/// printing them.
+async fn dispatch(paths: &Paths, request: Request<'_>, dry_run: bool) -> Result<()> {
+    let mut socket = connect(paths).await?;
 
-    socket.write_msg(&request)?;
-    socket.write_msg(&Request::Flush)?;
+    send(&mut socket, &request)?;
+    send(
+        &mut socket,
+        &if dry_run {
+            Request::Preview
+        } else {
+            Request::Flush
+        },
+    )?;
     socket.flush().await?;
     socket.inner.shutdown().await?;
 
-    let out = socket.read_all().await?;
+    // An error takes the place of the response, but flushing may still have added hyprlands reply.
+    let mut error = None;
+    let mut line = Vec::new();
+    while let Some(msg) = receive(&mut socket).await? {
+        match serde_json::from_slice::<Failure>(msg) {
+            Ok(err) => error = error.or(Some(err)),
+            Err(_) => {
+                // Printed right away, so streamed responses show up as they arrive.
+                line.clear();
+                line.extend_from_slice(msg);
+                line.push(b'\n');
+                write_to_stdout(&line).await?;
+            }
+        }
+    }
+    socket.ensure_ended()?;
+
+    error.map_or(Ok(()), |err| Err(err.into()))
+}
+
+/// Send `request` and parse the response as `T`, falling back to a [`Failure`].
+async fn query<T: DeserializeOwned>(paths: &Paths, request: Request<'_>) -> Result<T> {
+    let mut socket = connect(paths).await?;
+
+    send(&mut socket, &request)?;
+    socket.flush().await?;
+    socket.inner.shutdown().await?;
+
+    let out = receive(&mut socket).await?.unwrap_or_default().to_vec();
+    // Anything after the response is skipped, but it only counts once the end arrived.
+    while receive(&mut socket).await?.is_some() {}
+    socket.ensure_ended()?;
+    serde_json::from_slice(&out).map_err(|_| match serde_json::from_slice::<Failure>(&out) {
+        Ok(err) => err.into(),
+        Err(_) => anyhow!("{}", String::from_utf8_lossy(&out).trim()),
+    })
+}
+
+/// The snapshot in `file`, or on stdin without one.
+async fn read_snapshot(file: Option<&Path>) -> Result<Snapshot> {
+    let state = match file {
+        Some(file) => fs::read_to_string(file).await?,
+        None => {
+            let mut state = String::new();
+            io::stdin().read_to_string(&mut state).await?;
+            state
+        }
+    };
+    let state: Snapshot = serde_json::from_str(&state)?;
+    state.check_version()?;
+    Ok(state)
+}
+
+/// Print what would have to change to turn `from` into `to`, a change per line.
+async fn write_changes(from: &Snapshot, to: &Snapshot) -> Result<()> {
+    let mut out = String::new();
+    for change in from.diff(to) {
+        writeln!(out, "{change}").expect("writing to string doesn't fail");
+    }
+    write_to_stdout(out.as_bytes()).await
+}
+
+/// Queue `msg` to be sent to the server, showing it with `--verbose`.
+fn send<T: Serialize>(socket: &mut Socket, msg: &T) -> Result<()> {
+    if output().verbose {
+        eprintln!("> {}", serde_json::to_string(msg)?);
+    }
+    socket.write_msg(msg)
+}
+
+/// The next message from the server, shown with `--verbose`.
+async fn receive(socket: &mut Socket) -> Result<Option<&[u8]>> {
+    let msg = socket.next_msg().await?;
+    if let (Some(msg), true) = (msg, output().verbose) {
+        eprintln!("< {}", String::from_utf8_lossy(msg));
+    }
+    Ok(msg)
+}
+
+/// Print `out`, unless `--quiet`.
+async fn write_to_stdout(out: &[u8]) -> Result<()> {
+    if output().quiet {
+        return Ok(());
+    }
     let mut stdout = io::stdout();
 
     stdout.write_all(out).await?;
@@ -95,3 +1342,15 @@ async fn write_to_socket(request: Request<'_>) -> Result<()> {
 
     Ok(())
 }
+
+/// File name of the binary the cli was started as.
+fn invoked_as() -> String {
+    std::env::args_os()
+        .next()
+        .as_deref()
+        .and_then(|arg| Path::new(arg).file_name())
+        .map_or_else(
+            || env!("CARGO_PKG_NAME").to_owned(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+}
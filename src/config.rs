@@ -0,0 +1,366 @@
+use crate::{
+    path_builder::Paths,
+    server::types::{LagPolicy, Register, WorkspaceSettings},
+    socket::MAX_MSG_LEN,
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{de::Error, Deserialize, Deserializer};
+use std::{collections::BTreeMap, io::ErrorKind, path::Path, time::Duration};
+use tokio::fs;
+
+/// Contents of `config.toml` in the config directory.
+///
+/// Every field has a default, so a missing file or section behaves like an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Show desktop notifications when registers get bound, unbound or are used while unbound.
+    pub notifications: bool,
+    /// Record every mutating request in `audit.jsonl` in the state directory.
+    pub audit: bool,
+    /// Seconds between garbage collections of empty unbound workspaces, never if unset.
+    pub gc_interval: Option<u64>,
+    /// Remove the workspace of a binding that expired as well, if it is empty and unbound.
+    pub remove_expired: bool,
+    /// Milliseconds a `prefix` waits for the goto completing it, 1500 if unset.
+    pub prefix_timeout: Option<u64>,
+    pub binds: BindsConfig,
+    pub submap: SubmapConfig,
+    pub subscriptions: SubscriptionConfig,
+    pub connections: ConnectionsConfig,
+    pub hypr: HyprConfig,
+    pub hooks: HooksConfig,
+    pub declare: DeclareConfig,
+    /// Settings of every workspace that leaves them unset, see [`WorkspaceSettings::inherit`].
+    pub defaults: WorkspaceSettings,
+    /// Where workspaces go depending on the monitors connected, by name of the profile.
+    pub profiles: BTreeMap<String, MonitorProfile>,
+    /// Templates to create workspaces from, by name.
+    pub templates: BTreeMap<String, TemplateConfig>,
+    pub projects: ProjectsConfig,
+    /// Registers workspaces get bound to when hyprland creates them, see [`AutoBindRule`].
+    pub auto_bind: Vec<AutoBindRule>,
+    /// Cli commands standing in for others, like `p = "goto 1"`.
+    pub alias: BTreeMap<String, String>,
+    /// What bars show for a register in place of its number, like `1 = "●"`.
+    #[serde(deserialize_with = "register_map")]
+    pub glyphs: BTreeMap<Register, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BindsConfig {
+    pub count: Register,
+    pub modifier: String,
+    /// Command used to invoke ws-mgr, defaults to the name the cli was started with.
+    pub command: Option<String>,
+    /// Lines emitted for every register, see [`crate::generate::binds`] for placeholders.
+    pub templates: Vec<String>,
+}
+
+impl Default for BindsConfig {
+    fn default() -> Self {
+        Self {
+            count: 9,
+            modifier: "SUPER".to_owned(),
+            command: None,
+            templates: vec![
+                "bind = {mod}, {key}, exec, {cmd} goto {register}".to_owned(),
+                "bind = {mod} SHIFT, {key}, exec, {cmd} moveto {register}".to_owned(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SubmapConfig {
+    /// Name of the submap, entered with a bind like `bind = SUPER, w, submap, ws-mgr`.
+    pub name: String,
+    /// Lines emitted for every bound register, see [`crate::generate::submap`] for
+    /// placeholders.
+    pub templates: Vec<String>,
+}
+
+impl Default for SubmapConfig {
+    fn default() -> Self {
+        Self {
+            name: "ws-mgr".to_owned(),
+            templates: vec![
+                "# {key}: {workspace}".to_owned(),
+                "bind = , {key}, exec, {cmd} goto {register}".to_owned(),
+                "bind = , {key}, submap, reset".to_owned(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionConfig {
+    /// Records queued per subscriber before the lag policy kicks in, at least 1.
+    #[serde(deserialize_with = "capacity")]
+    pub capacity: usize,
+    pub policy: LagPolicy,
+    /// Milliseconds to wait for more records once one got published. A burst of records is
+    /// then sent as a single snapshot, `0` sends every record right away.
+    pub debounce: u64,
+}
+
+impl SubscriptionConfig {
+    pub fn debounce(&self) -> Option<Duration> {
+        (self.debounce > 0).then(|| Duration::from_millis(self.debounce))
+    }
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            policy: LagPolicy::default(),
+            debounce: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConnectionsConfig {
+    /// Clients connected at the same time, further connections get rejected.
+    pub max: usize,
+    /// Bookkeeping requests like `read` served at the same time, further ones wait so a bar
+    /// polling hard can't hold up keybinds.
+    pub max_bookkeeping: usize,
+    /// Seconds a client may stay connected without starting a request.
+    pub idle_timeout: u64,
+    /// Seconds a client may take to finish sending a request once it started.
+    pub read_timeout: u64,
+    /// Bytes a single request may take, a client sending a longer one is answered with a
+    /// protocol error and disconnected.
+    pub max_message: usize,
+    /// Seconds a client may take to read what it was sent, a subscriber that stopped reading
+    /// gets dropped after that.
+    pub write_timeout: u64,
+    /// Also listen on `ws-mgr-ro.sock` next to the socket, which only accepts requests
+    /// observing the state, for status bars and scripts that shouldn't drive the compositor.
+    pub read_only: bool,
+    /// Permission bits of the socket, like `0o660` to let the group in.
+    pub mode: u32,
+    pub read_only_mode: u32,
+    /// Users besides the one running the server allowed to connect to either socket,
+    /// everyone with access to the socket file gets in if unset.
+    pub allowed_uids: Option<Vec<u32>>,
+}
+
+impl Default for ConnectionsConfig {
+    fn default() -> Self {
+        Self {
+            max: 64,
+            max_bookkeeping: 2,
+            idle_timeout: 300,
+            read_timeout: 5,
+            max_message: MAX_MSG_LEN,
+            write_timeout: 30,
+            read_only: false,
+            mode: 0o600,
+            read_only_mode: 0o600,
+            allowed_uids: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HyprConfig {
+    /// Bytes of dispatches a connection may queue before they get sent without waiting for
+    /// the client to flush.
+    pub max_batch: usize,
+    /// Send dispatches right after the request queueing them instead of batching them.
+    pub auto_flush: bool,
+    /// Seconds the server waits on startup for hyprland to create its sockets, for when it is
+    /// started before hyprland is done starting itself.
+    pub startup_timeout: u64,
+}
+
+impl HyprConfig {
+    pub fn startup_timeout(&self) -> Duration {
+        Duration::from_secs(self.startup_timeout)
+    }
+}
+
+impl Default for HyprConfig {
+    fn default() -> Self {
+        Self {
+            max_batch: 4096,
+            auto_flush: false,
+            startup_timeout: 30,
+        }
+    }
+}
+
+/// Settings and commands a workspace created from the template starts out with.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct TemplateConfig {
+    #[serde(flatten)]
+    pub settings: WorkspaceSettings,
+    /// Shell commands run with their windows opening on the new workspace.
+    pub exec: Vec<String>,
+}
+
+/// Monitors for workspaces while exactly the `monitors` of the profile are connected, like
+/// `docked` or `laptop-only`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MonitorProfile {
+    /// Names of the monitors, in any order.
+    pub monitors: Vec<String>,
+    /// Monitor for the workspace each register points to.
+    #[serde(deserialize_with = "register_map")]
+    pub registers: BTreeMap<Register, String>,
+    /// Monitor by workspace name, over the one given for its register.
+    pub workspaces: BTreeMap<String, String>,
+}
+
+/// How `project open` turns directories into workspaces.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProjectsConfig {
+    /// Name of the workspace of a project, `{dir}` is replaced by the name of its directory
+    /// and `{parent}` by the name of the directory containing it.
+    pub name: String,
+    /// Terminal opened in the project directory with `project open --terminal`.
+    pub terminal: Option<String>,
+}
+
+impl Default for ProjectsConfig {
+    fn default() -> Self {
+        Self {
+            name: "{dir}".to_owned(),
+            terminal: None,
+        }
+    }
+}
+
+impl ProjectsConfig {
+    /// Name of the workspace for the project at `path`, `None` if `path` has no directory name.
+    pub fn workspace_name(&self, path: &Path) -> Option<String> {
+        let name_of = |path: Option<&Path>| {
+            path.and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+        };
+        let dir = name_of(Some(path))?;
+        let parent = name_of(path.parent()).unwrap_or_default();
+
+        Some(
+            self.name
+                .replace("{dir}", &dir)
+                .replace("{parent}", &parent),
+        )
+    }
+}
+
+/// Binds `register` to workspaces hyprland creates, if their name matches and the register
+/// isn't bound yet.
+///
+/// Rules are written like `match = "name:^mail", register = 8`, with a regex after `name:`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutoBindRule {
+    #[serde(rename = "match", deserialize_with = "name_regex")]
+    pub name: Regex,
+    pub register: Register,
+}
+
+/// Shell commands run on server events.
+///
+/// Besides `WS_MGR_EVENT` naming the hook, commands get `WS_MGR_WORKSPACE` and, depending on
+/// the event, `WS_MGR_REGISTER` or `WS_MGR_REGISTERS` (all registers bound to the workspace).
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_bind: Option<String>,
+    pub on_unbind: Option<String>,
+    pub on_goto: Option<String>,
+    pub on_create: Option<String>,
+    pub on_workspace_destroyed: Option<String>,
+}
+
+/// State the server continuously reconciles towards, like home-manager does with dotfiles.
+///
+/// Declared workspaces always exist and declared registers can't be rebound.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DeclareConfig {
+    pub workspaces: Vec<String>,
+    #[serde(deserialize_with = "register_map")]
+    pub registers: BTreeMap<Register, String>,
+    /// Allow binding registers that aren't declared, otherwise they get unbound.
+    pub adhoc: bool,
+}
+
+impl Default for DeclareConfig {
+    fn default() -> Self {
+        Self {
+            workspaces: Vec::new(),
+            registers: BTreeMap::new(),
+            adhoc: true,
+        }
+    }
+}
+
+impl Config {
+    pub const FILE: &'static str = "config.toml";
+
+    /// Load the config from its configured path, or the defaults if there is none.
+    pub async fn load(paths: &Paths) -> Result<Self> {
+        Self::load_from(&paths.config()?).await
+    }
+
+    pub async fn load_from(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path).await {
+            Ok(config) => toml::from_str(&config)
+                .with_context(|| format!("invalid config {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Deserialize a table keyed by registers, toml only has string keys.
+fn register_map<'de, D, V>(deserializer: D) -> std::result::Result<BTreeMap<Register, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    BTreeMap::<String, V>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(register, value)| match register.parse() {
+            Ok(register) => Ok((register, value)),
+            Err(_) => Err(D::Error::custom(format!("invalid register {register:?}"))),
+        })
+        .collect()
+}
+
+/// Deserialize a queue capacity, which can't be 0 as nothing would fit.
+fn capacity<'de, D>(deserializer: D) -> std::result::Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match usize::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("capacity must be at least 1")),
+        capacity => Ok(capacity),
+    }
+}
+
+/// Deserialize a `name:<regex>` match of an [`AutoBindRule`].
+fn name_regex<'de, D>(deserializer: D) -> std::result::Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rule = String::deserialize(deserializer)?;
+    let pattern = rule
+        .strip_prefix("name:")
+        .ok_or_else(|| D::Error::custom(format!("{rule:?} doesn't start with `name:`")))?;
+    Regex::new(pattern).map_err(D::Error::custom)
+}
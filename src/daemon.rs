@@ -0,0 +1,81 @@
+//! Detaching `server --daemon` from the terminal and session it was started from.
+
+use anyhow::{anyhow, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd},
+    path::Path,
+    process,
+    sync::Mutex,
+};
+
+/// Write end of the pipe the original process waits on, see [`ready`].
+static READY: Mutex<Option<File>> = Mutex::new(None);
+
+/// Double fork into a daemon, only returning in the daemon.
+///
+/// Has to be called before any threads are spawned, so before the runtime is started. The
+/// original process stays around until the daemon called [`ready`] or died, and exits with
+/// whether it got ready, pointing to `log` if not.
+pub fn detach(log: &Path) -> Result<()> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for both ends of the pipe. Commands the server runs don't get
+    // to keep it open.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // SAFETY: both fds were just opened and are owned by nothing else.
+    let (mut waiting, ready) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    if fork()? != 0 {
+        drop(ready);
+        let mut byte = [0];
+        let started = matches!(waiting.read(&mut byte), Ok(1));
+        if !started {
+            eprintln!("Error: the server failed to start, see {}", log.display());
+        }
+        process::exit(if started { 0 } else { 1 });
+    }
+    drop(waiting);
+
+    // A new session without a controlling terminal, the second fork makes sure the daemon
+    // never acquires one again.
+    // SAFETY: setsid has no preconditions.
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    if fork()? != 0 {
+        process::exit(0);
+    }
+
+    std::env::set_current_dir("/")?;
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: `null` is open, dup2 atomically replaces the standard fd.
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    *READY.lock().expect("lock poisoned") = Some(ready);
+    Ok(())
+}
+
+/// Let the original process exit, once the daemon is up. Does nothing if not detached.
+pub fn ready() {
+    if let Some(mut ready) = READY.lock().expect("lock poisoned").take() {
+        let _ = ready.write_all(&[1]);
+    }
+}
+
+fn fork() -> Result<libc::pid_t> {
+    // SAFETY: only called while the process is single threaded.
+    match unsafe { libc::fork() } {
+        -1 => Err(anyhow!("fork failed: {}", io::Error::last_os_error())),
+        pid => Ok(pid),
+    }
+}
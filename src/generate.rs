@@ -0,0 +1,81 @@
+use crate::{
+    config::{BindsConfig, SubmapConfig},
+    server::types::Register,
+};
+use anyhow::{anyhow, Result};
+use std::{collections::BTreeMap, fmt::Write, sync::Arc};
+
+/// Render `hyprland.conf` keybinds for registers `1..=count`.
+///
+/// For every register each template is emitted once, with these placeholders substituted:
+/// - `{mod}`: the configured modifier
+/// - `{key}`: the number key selecting the register, register 10 is bound to `0`
+/// - `{register}`: the register itself
+/// - `{cmd}`: the command invoking ws-mgr
+pub fn binds(config: &BindsConfig, cmd: &str, out: &mut String) -> Result<()> {
+    if config.count > 10 {
+        return Err(anyhow!(
+            "can't generate binds for {} registers, there are only 10 number keys",
+            config.count
+        ));
+    }
+
+    for register in 1..=config.count {
+        for template in &config.templates {
+            let line = template
+                .replace("{mod}", &config.modifier)
+                .replace("{key}", &(register % 10).to_string())
+                .replace("{register}", &register.to_string())
+                .replace("{cmd}", cmd);
+            writeln!(out, "{line}").expect("writing to string doesn't fail");
+        }
+    }
+
+    Ok(())
+}
+
+/// Keys of a submap, registers 1 to 9 keep their number key and the others get a letter.
+const LETTERS: &str = "abcdefghijklmnopqrstuvwxyz";
+
+/// Render a `hyprland.conf` submap with a key for every bound register in `registers`.
+///
+/// For every register each template is emitted once, with these placeholders substituted:
+/// - `{key}`: the key selecting the register
+/// - `{register}`: the register itself
+/// - `{workspace}`: the workspace the register is bound to
+/// - `{cmd}`: the command invoking ws-mgr
+///
+/// Registers left without a key once the letters run out are skipped with a comment.
+pub fn submap(
+    config: &SubmapConfig,
+    registers: &BTreeMap<Register, Arc<str>>,
+    cmd: &str,
+    out: &mut String,
+) {
+    let mut letters = LETTERS.chars();
+
+    writeln!(out, "submap = {}", config.name).expect("writing to string doesn't fail");
+    for (register, workspace) in registers {
+        let key = match register {
+            1..=9 => register.to_string(),
+            _ => match letters.next() {
+                Some(letter) => letter.to_string(),
+                None => {
+                    writeln!(out, "# no key left for register {register}")
+                        .expect("writing to string doesn't fail");
+                    continue;
+                }
+            },
+        };
+        for template in &config.templates {
+            let line = template
+                .replace("{key}", &key)
+                .replace("{register}", &register.to_string())
+                .replace("{workspace}", workspace)
+                .replace("{cmd}", cmd);
+            writeln!(out, "{line}").expect("writing to string doesn't fail");
+        }
+    }
+    writeln!(out, "bind = , escape, submap, reset").expect("writing to string doesn't fail");
+    writeln!(out, "submap = reset").expect("writing to string doesn't fail");
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
+use serde::de::DeserializeOwned;
 use std::{
     fmt::{self, Display, Formatter, Write},
+    future::Future,
     path::{Path, PathBuf},
     str::from_utf8,
 };
@@ -10,10 +12,88 @@ use tokio::{
 };
 use tracing::{debug, instrument};
 
+pub use self::{
+    dispatcher::Dispatcher,
+    event::{HyprEvent, HyprEvents},
+    query::{ArrayStream, Client, MonitorInfo, WorkspaceInfo, WorkspaceRef},
+};
+
+mod dispatcher;
+mod event;
+mod query;
+
+/// Send `batch` to the hyprland socket at `socket_path`, reading its reply into `reply`.
+#[instrument(name = "hypr", skip(batch, reply))]
+async fn send_batch(socket_path: &Path, batch: &str, reply: Option<&mut Vec<u8>>) -> Result<()> {
+    let mut socket = UnixStream::connect(socket_path).await?;
+    socket.write_all(batch.as_bytes()).await?;
+    debug!(request = batch, "request");
+    socket.flush().await?;
+    if let Some(reply) = reply {
+        socket.read_to_end(reply).await?;
+        debug!(reply = ?from_utf8(reply), "reply");
+    }
+    Ok(())
+}
+
+/// The operations the server needs from a compositor.
+///
+/// Dispatches are only queued up and have to be sent with [`Compositor::flush`]. Hyprland is
+/// the only backend so far, others would translate their IPC into these, and their events
+/// into [`HyprEvent`]s.
+pub trait Compositor: Send {
+    type Events: EventStream;
+
+    fn go_to(&mut self, workspace: Workspace);
+    fn move_to(&mut self, workspace: Workspace);
+    /// Move the window at `address` to `workspace`, without following it.
+    fn move_window(&mut self, workspace: Workspace, address: &str);
+    /// Move `workspace` with all its windows to the monitor named `monitor`.
+    fn move_workspace_to_monitor(&mut self, workspace: Workspace, monitor: &str);
+    /// Run the shell command `command`, opening its windows on `workspace`.
+    fn exec(&mut self, workspace: Workspace, command: &str);
+    /// Set the config keyword `key` to `value`, until hyprland reloads its config.
+    fn keyword(&mut self, key: &str, value: &str);
+    /// Queue `dispatcher` with its arguments as is, like `movefocus l`.
+    fn dispatch(&mut self, dispatcher: &str);
+    /// The windows open on the workspace named `workspace`, queried right away.
+    fn clients_on(&mut self, workspace: &str) -> impl Future<Output = Result<Vec<Client>>> + Send;
+    /// Every window hyprland has open, queried right away.
+    fn list_clients(&mut self) -> impl Future<Output = Result<Vec<Client>>> + Send;
+    /// The workspace hyprland currently focuses, queried right away.
+    fn active_workspace(&mut self) -> impl Future<Output = Result<WorkspaceInfo>> + Send;
+    /// The workspaces hyprland currently has open, queried right away.
+    fn list_workspaces(&mut self) -> impl Future<Output = Result<Vec<WorkspaceInfo>>> + Send;
+    /// The monitors connected right now, queried right away.
+    fn list_monitors(&mut self) -> impl Future<Output = Result<Vec<MonitorInfo>>> + Send;
+    fn flush(&mut self, reply: Option<&mut Vec<u8>>) -> impl Future<Output = Result<()>> + Send;
+    /// Start receiving the events of the compositor, from now on.
+    fn subscribe_events(&mut self) -> impl Future<Output = Result<Self::Events>> + Send;
+    /// Drop the queued dispatches, returning the batch that would have been sent.
+    fn discard(&mut self) -> Option<String>;
+    /// The commands queued so far, in the order they will be sent.
+    fn queued(&self) -> Vec<String>;
+
+    /// Bytes of commands queued so far.
+    fn queued_len(&self) -> usize {
+        self.queued().iter().map(|command| command.len() + 1).sum()
+    }
+}
+
+/// Events of a compositor as they happen, see [`Compositor::subscribe_events`].
+pub trait EventStream: Send {
+    /// The next event, `None` once the compositor closed the stream.
+    fn next_event(&mut self) -> impl Future<Output = Result<Option<HyprEvent>>> + Send;
+}
+
 #[derive(Debug)]
 pub struct Hypr {
     buffer: String,
     socket_path: PathBuf,
+    /// Sends the batches if set, instead of sending them right away.
+    dispatcher: Option<Dispatcher>,
+    /// Whether the batch switches workspaces, which the dispatcher sends ahead of others.
+    navigation: bool,
 }
 
 const BATCH: &str = "[[BATCH]]";
@@ -23,11 +103,37 @@ impl Hypr {
         Self {
             buffer: String::from(BATCH),
             socket_path: socket_path.into(),
+            dispatcher: None,
+            navigation: false,
+        }
+    }
+
+    /// Like [`Hypr::new`], but handing batches to `dispatcher` to send, and querying the
+    /// socket it sends to.
+    pub fn dispatched(dispatcher: Dispatcher) -> Self {
+        Self {
+            socket_path: dispatcher.socket_path(),
+            dispatcher: Some(dispatcher),
+            ..Self::new(Path::new(""))
+        }
+    }
+
+    /// The socket to query, which follows the dispatcher when it is retargeted.
+    fn socket_path(&self) -> PathBuf {
+        match &self.dispatcher {
+            Some(dispatcher) => dispatcher.socket_path(),
+            None => self.socket_path.clone(),
         }
     }
 
     pub fn clear(&mut self) {
-        self.buffer.truncate(BATCH.len())
+        self.buffer.truncate(BATCH.len());
+        self.navigation = false;
+    }
+
+    /// The batch as it would currently be sent, or `None` if there is nothing to send.
+    pub fn batch(&self) -> Option<&str> {
+        (BATCH.len() < self.buffer.len()).then_some(self.buffer.as_str())
     }
 
     /// Flush current buffer to socket and clear the buffer afterwards.
@@ -35,24 +141,51 @@ impl Hypr {
     /// Only actually sends, if the buffer contains messages to be sent.
     /// If an error occurs while sending, the buffer is not flushed!
     pub async fn flush(&mut self, reply: Option<&mut Vec<u8>>) -> Result<()> {
-        if BATCH.len() < self.buffer.len() {
-            self.send(reply).await?;
+        if self.batch().is_some() {
+            match &self.dispatcher {
+                Some(dispatcher) => {
+                    let commands = &self.buffer[BATCH.len()..];
+                    dispatcher.send(commands, self.navigation, reply).await?
+                }
+                None => self.send(reply).await?,
+            }
             self.clear();
         }
         Ok(())
     }
 
-    #[instrument(name = "hypr", skip(self, reply))]
     pub async fn send(&self, reply: Option<&mut Vec<u8>>) -> Result<()> {
-        let mut socket = UnixStream::connect(&self.socket_path).await?;
-        socket.write_all(self.buffer.as_bytes()).await?;
-        debug!(request = &self.buffer, "request");
+        send_batch(&self.socket_path(), &self.buffer, reply).await
+    }
+
+    /// Send the query `j/{command}` and stream the json array hyprland replies with.
+    #[instrument(name = "hypr query", skip(self))]
+    pub async fn query_array<T: DeserializeOwned>(&self, command: &str) -> Result<ArrayStream<T>> {
+        let mut socket = UnixStream::connect(self.socket_path()).await?;
+        socket.write_all(format!("j/{command}").as_bytes()).await?;
         socket.flush().await?;
-        if let Some(reply) = reply {
-            socket.read_to_end(reply).await?;
-            debug!(reply = ?from_utf8(reply), "reply");
-        }
-        Ok(())
+
+        Ok(ArrayStream::new(socket))
+    }
+
+    /// Send the query `j/{command}` and parse the json object hyprland replies with.
+    #[instrument(name = "hypr query", skip(self))]
+    pub async fn query<T: DeserializeOwned>(&self, command: &str) -> Result<T> {
+        let mut socket = UnixStream::connect(self.socket_path()).await?;
+        socket.write_all(format!("j/{command}").as_bytes()).await?;
+        socket.flush().await?;
+
+        let mut reply = Vec::new();
+        socket.read_to_end(&mut reply).await?;
+        serde_json::from_slice(&reply).map_err(Into::into)
+    }
+
+    pub async fn clients(&self) -> Result<ArrayStream<Client>> {
+        self.query_array("clients").await
+    }
+
+    pub async fn workspaces(&self) -> Result<ArrayStream<WorkspaceInfo>> {
+        self.query_array("workspaces").await
     }
 }
 
@@ -69,6 +202,43 @@ pub enum Workspace<'a> {
     Special(Option<&'a str>),
 }
 
+impl<'a> Workspace<'a> {
+    /// Parse a workspace selector the way hyprland spells it, like `3`, `e+1`, `m-1` or
+    /// `name:mail`, see [`Display`] for all of them.
+    ///
+    /// Names containing `;` are refused, they would cut the batch short.
+    pub fn parse(s: &'a str) -> Option<Self> {
+        let relative = |rel: &str| match rel.starts_with(['+', '-']) {
+            true => rel.parse().ok(),
+            false => None,
+        };
+        let workspace = match s {
+            "previous" => Workspace::Previous,
+            "empty" => Workspace::Empty,
+            "special" => Workspace::Special(None),
+            _ if s.contains(';') => return None,
+            _ => {
+                if let Some(name) = s.strip_prefix("name:") {
+                    Workspace::Name(name)
+                } else if let Some(name) = s.strip_prefix("special:") {
+                    Workspace::Special(Some(name))
+                } else if let Some(rel) = s.strip_prefix('m') {
+                    Workspace::RelativeMonitor(relative(rel)?)
+                } else if let Some(rel) = s.strip_prefix('r') {
+                    Workspace::RelativeMonitorEmpty(relative(rel)?)
+                } else if let Some(rel) = s.strip_prefix('e') {
+                    Workspace::RelativeOpen(relative(rel)?)
+                } else if let Some(rel) = relative(s) {
+                    Workspace::RelativeId(rel)
+                } else {
+                    Workspace::Id(s.parse().ok().filter(|id| *id > 0)?)
+                }
+            }
+        };
+        Some(workspace)
+    }
+}
+
 impl Display for Workspace<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -87,13 +257,133 @@ impl Display for Workspace<'_> {
 }
 
 impl Hypr {
+    /// Go to `workspace`, special workspaces are toggled instead, as hyprland can't go there.
     pub fn go_to(&mut self, workspace: Workspace) {
-        write!(self.buffer, "/dispatch workspace {workspace};")
-            .expect("writing to string doesn't fail");
+        self.navigation = true;
+        match workspace {
+            Workspace::Special(name) => write!(
+                self.buffer,
+                "/dispatch togglespecialworkspace {};",
+                name.unwrap_or_default()
+            ),
+            workspace => write!(self.buffer, "/dispatch workspace {workspace};"),
+        }
+        .expect("writing to string doesn't fail");
     }
 
     pub fn move_to(&mut self, workspace: Workspace) {
+        self.navigation = true;
         write!(self.buffer, "/dispatch movetoworkspacesilent {workspace};")
             .expect("writing to string doesn't fail");
     }
+
+    pub fn move_window(&mut self, workspace: Workspace, address: &str) {
+        write!(
+            self.buffer,
+            "/dispatch movetoworkspacesilent {workspace},address:{address};"
+        )
+        .expect("writing to string doesn't fail");
+    }
+
+    pub fn move_workspace_to_monitor(&mut self, workspace: Workspace, monitor: &str) {
+        self.navigation = true;
+        write!(
+            self.buffer,
+            "/dispatch moveworkspacetomonitor {workspace} {monitor};"
+        )
+        .expect("writing to string doesn't fail");
+    }
+
+    pub fn exec(&mut self, workspace: Workspace, command: &str) {
+        write!(
+            self.buffer,
+            "/dispatch exec [workspace {workspace} silent] {command};"
+        )
+        .expect("writing to string doesn't fail");
+    }
+
+    pub fn keyword(&mut self, key: &str, value: &str) {
+        write!(self.buffer, "/keyword {key} {value};").expect("writing to string doesn't fail");
+    }
+
+    pub fn dispatch(&mut self, dispatcher: &str) {
+        write!(self.buffer, "/dispatch {dispatcher};").expect("writing to string doesn't fail");
+    }
+}
+
+impl Compositor for Hypr {
+    type Events = HyprEvents;
+
+    fn go_to(&mut self, workspace: Workspace) {
+        Hypr::go_to(self, workspace)
+    }
+
+    fn move_to(&mut self, workspace: Workspace) {
+        Hypr::move_to(self, workspace)
+    }
+
+    fn move_window(&mut self, workspace: Workspace, address: &str) {
+        Hypr::move_window(self, workspace, address)
+    }
+
+    fn move_workspace_to_monitor(&mut self, workspace: Workspace, monitor: &str) {
+        Hypr::move_workspace_to_monitor(self, workspace, monitor)
+    }
+
+    fn exec(&mut self, workspace: Workspace, command: &str) {
+        Hypr::exec(self, workspace, command)
+    }
+
+    fn keyword(&mut self, key: &str, value: &str) {
+        Hypr::keyword(self, key, value)
+    }
+
+    fn dispatch(&mut self, dispatcher: &str) {
+        Hypr::dispatch(self, dispatcher)
+    }
+
+    async fn clients_on(&mut self, workspace: &str) -> Result<Vec<Client>> {
+        self.clients()
+            .await?
+            .filter(|client| client.workspace.name == workspace)
+            .await
+    }
+
+    async fn list_clients(&mut self) -> Result<Vec<Client>> {
+        self.clients().await?.collect().await
+    }
+
+    async fn active_workspace(&mut self) -> Result<WorkspaceInfo> {
+        self.query("activeworkspace").await
+    }
+
+    async fn list_workspaces(&mut self) -> Result<Vec<WorkspaceInfo>> {
+        self.workspaces().await?.collect().await
+    }
+
+    async fn list_monitors(&mut self) -> Result<Vec<MonitorInfo>> {
+        self.query_array("monitors").await?.collect().await
+    }
+
+    async fn flush(&mut self, reply: Option<&mut Vec<u8>>) -> Result<()> {
+        Hypr::flush(self, reply).await
+    }
+
+    async fn subscribe_events(&mut self) -> Result<HyprEvents> {
+        // The event socket lives next to the one taking requests.
+        HyprEvents::connect(&self.socket_path().with_file_name(".socket2.sock")).await
+    }
+
+    fn discard(&mut self) -> Option<String> {
+        let batch = self.batch().map(str::to_owned);
+        self.clear();
+        batch
+    }
+
+    fn queued(&self) -> Vec<String> {
+        self.buffer[BATCH.len()..]
+            .split_terminator(';')
+            .map(str::to_owned)
+            .collect()
+    }
 }
@@ -0,0 +1,111 @@
+use super::{send_batch, BATCH};
+use anyhow::{anyhow, Result};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, Instrument};
+
+/// Handle to the task sending every batch to hyprland, see [`Dispatcher::spawn`].
+#[derive(Debug, Clone)]
+pub struct Dispatcher {
+    jobs: mpsc::UnboundedSender<Job>,
+    /// Changes once hyprland restarted in another runtime dir, see [`Dispatcher::retarget`].
+    socket_path: Arc<RwLock<PathBuf>>,
+}
+
+#[derive(Debug)]
+struct Job {
+    /// Commands of the batch, each terminated by `;`.
+    commands: String,
+    navigation: bool,
+    reply: bool,
+    done: oneshot::Sender<Result<Vec<u8>, String>>,
+}
+
+impl Dispatcher {
+    /// Spawn the task sending batches to the hyprland socket at `socket_path`.
+    ///
+    /// Batches queued while one is being sent are merged into a single one, unless they wait
+    /// for the reply of hyprland, which can't be told apart once merged. Navigating batches
+    /// skip ahead of the others queued, each connection has only one batch queued at a time
+    /// so no connection sees its batches reordered.
+    pub fn spawn(socket_path: &Path) -> Self {
+        let (jobs, rx) = mpsc::unbounded_channel();
+        let socket_path = Arc::new(RwLock::new(socket_path.to_owned()));
+        tokio::spawn(
+            run(Arc::clone(&socket_path), rx).instrument(tracing::info_span!("dispatcher")),
+        );
+        Self { jobs, socket_path }
+    }
+
+    /// The hyprland socket batches are currently sent to.
+    pub fn socket_path(&self) -> PathBuf {
+        self.socket_path.read().expect("lock poisoned").clone()
+    }
+
+    /// Send batches to the socket at `socket_path` from now on, for every handle.
+    pub fn retarget(&self, socket_path: &Path) {
+        *self.socket_path.write().expect("lock poisoned") = socket_path.to_owned();
+    }
+
+    /// Have the task send `commands`, waiting until hyprland took them.
+    ///
+    /// `navigation` tells whether the commands switch workspaces, see [`Dispatcher::spawn`].
+    pub async fn send(
+        &self,
+        commands: &str,
+        navigation: bool,
+        reply: Option<&mut Vec<u8>>,
+    ) -> Result<()> {
+        let (done, res) = oneshot::channel();
+        let job = Job {
+            commands: commands.to_owned(),
+            navigation,
+            reply: reply.is_some(),
+            done,
+        };
+        self.jobs
+            .send(job)
+            .map_err(|_| anyhow!("the dispatcher stopped"))?;
+
+        let bytes = res
+            .await
+            .map_err(|_| anyhow!("the dispatcher dropped the batch"))?
+            .map_err(|err| anyhow!(err))?;
+        if let Some(reply) = reply {
+            reply.extend_from_slice(&bytes);
+        }
+        Ok(())
+    }
+}
+
+async fn run(socket_path: Arc<RwLock<PathBuf>>, mut rx: mpsc::UnboundedReceiver<Job>) {
+    let mut jobs = Vec::new();
+    while rx.recv_many(&mut jobs, usize::MAX).await > 0 {
+        jobs.sort_by_key(|job: &Job| !job.navigation);
+        let mut queued = jobs.drain(..).peekable();
+        while let Some(job) = queued.next() {
+            let mut batch = format!("{BATCH}{}", job.commands);
+            let mut done = vec![job.done];
+            if !job.reply {
+                while let Some(next) = queued.next_if(|next| !next.reply) {
+                    batch.push_str(&next.commands);
+                    done.push(next.done);
+                }
+            }
+            if 1 < done.len() {
+                debug!(batches = done.len(), "merged batches");
+            }
+
+            let mut reply = Vec::new();
+            let socket_path = socket_path.read().expect("lock poisoned").clone();
+            let res = send_batch(&socket_path, &batch, job.reply.then_some(&mut reply)).await;
+            let res = res.map(|()| reply).map_err(|err| format!("{err:#}"));
+            for done in done {
+                let _ = done.send(res.clone());
+            }
+        }
+    }
+}
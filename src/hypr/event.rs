@@ -0,0 +1,143 @@
+use super::EventStream;
+use anyhow::Result;
+use std::{path::Path, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    net::UnixStream,
+};
+use tracing::warn;
+
+/// An event as emitted by hyprland on `.socket2.sock`, one per line in the form `name>>data`.
+///
+/// Only the events the server actually cares about are parsed, everything else is kept as
+/// [`HyprEvent::Other`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HyprEvent {
+    Workspace {
+        name: Arc<str>,
+    },
+    CreateWorkspace {
+        name: Arc<str>,
+    },
+    DestroyWorkspace {
+        name: Arc<str>,
+    },
+    FocusedMon {
+        monitor: Arc<str>,
+        workspace: Arc<str>,
+    },
+    /// The workspace `name` got moved to `monitor`.
+    MoveWorkspace {
+        name: Arc<str>,
+        monitor: Arc<str>,
+    },
+    MonitorAdded {
+        name: Arc<str>,
+    },
+    MonitorRemoved {
+        name: Arc<str>,
+    },
+    /// Addresses of window events are given without `0x`.
+    OpenWindow {
+        address: Arc<str>,
+        workspace: Arc<str>,
+    },
+    CloseWindow {
+        address: Arc<str>,
+    },
+    MoveWindow {
+        address: Arc<str>,
+        workspace: Arc<str>,
+    },
+    /// The window at `address` asks for attention, the address is given without `0x`.
+    Urgent {
+        address: Arc<str>,
+    },
+    /// Hyprland reloaded its config, dropping the rules set with `keyword`.
+    ConfigReloaded,
+    Other {
+        name: Arc<str>,
+        data: Arc<str>,
+    },
+}
+
+impl HyprEvent {
+    pub fn parse(line: &str) -> Option<Self> {
+        let (name, data) = line.split_once(">>")?;
+        let event = match name {
+            "workspace" => HyprEvent::Workspace { name: data.into() },
+            "createworkspace" => HyprEvent::CreateWorkspace { name: data.into() },
+            "destroyworkspace" => HyprEvent::DestroyWorkspace { name: data.into() },
+            "focusedmon" => {
+                let (monitor, workspace) = data.split_once(',')?;
+                HyprEvent::FocusedMon {
+                    monitor: monitor.into(),
+                    workspace: workspace.into(),
+                }
+            }
+            "moveworkspace" => {
+                let (name, monitor) = data.rsplit_once(',')?;
+                HyprEvent::MoveWorkspace {
+                    name: name.into(),
+                    monitor: monitor.into(),
+                }
+            }
+            "monitoradded" => HyprEvent::MonitorAdded { name: data.into() },
+            "monitorremoved" => HyprEvent::MonitorRemoved { name: data.into() },
+            "openwindow" => {
+                let (address, rest) = data.split_once(',')?;
+                let (workspace, _class_and_title) = rest.split_once(',')?;
+                HyprEvent::OpenWindow {
+                    address: address.into(),
+                    workspace: workspace.into(),
+                }
+            }
+            "closewindow" => HyprEvent::CloseWindow {
+                address: data.into(),
+            },
+            "movewindow" => {
+                let (address, workspace) = data.split_once(',')?;
+                HyprEvent::MoveWindow {
+                    address: address.into(),
+                    workspace: workspace.into(),
+                }
+            }
+            "urgent" => HyprEvent::Urgent {
+                address: data.into(),
+            },
+            "configreloaded" => HyprEvent::ConfigReloaded,
+            _ => HyprEvent::Other {
+                name: name.into(),
+                data: data.into(),
+            },
+        };
+        Some(event)
+    }
+}
+
+/// The events hyprland emits on its event socket.
+#[derive(Debug)]
+pub struct HyprEvents {
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl HyprEvents {
+    pub async fn connect(event_path: &Path) -> Result<Self> {
+        let socket = UnixStream::connect(event_path).await?;
+        Ok(Self {
+            lines: BufReader::new(socket).lines(),
+        })
+    }
+}
+
+impl EventStream for HyprEvents {
+    async fn next_event(&mut self) -> Result<Option<HyprEvent>> {
+        while let Some(line) = self.lines.next_line().await? {
+            match HyprEvent::parse(&line) {
+                Some(event) => return Ok(Some(event)),
+                None => warn!(line, "malformed event"),
+            }
+        }
+        Ok(None)
+    }
+}
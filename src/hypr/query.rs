@@ -0,0 +1,270 @@
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::marker::PhantomData;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::UnixStream,
+};
+
+/// A window as listed by `j/clients`, only with the fields the server uses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Client {
+    pub address: String,
+    pub workspace: WorkspaceRef,
+    pub class: String,
+    pub title: String,
+}
+
+/// A workspace as listed by `j/workspaces`, only with the fields the server uses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkspaceInfo {
+    pub id: i32,
+    pub name: String,
+    pub monitor: String,
+    pub windows: u32,
+}
+
+/// A monitor as listed by `j/monitors`, only with the fields the server uses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MonitorInfo {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkspaceRef {
+    pub id: i32,
+    pub name: String,
+}
+
+/// Incrementally parses a json array read from `reader`, one element at a time.
+///
+/// Only the element currently being parsed is buffered, so huge replies like `j/clients` on
+/// window heavy sessions never have to be held in memory completely. Dropping the stream
+/// early stops reading the rest of the reply.
+pub struct ArrayStream<T, R = UnixStream> {
+    reader: R,
+    buf: Vec<u8>,
+    /// Bytes at the start of `buf` that were already scanned.
+    scanned: usize,
+    state: State,
+    _item: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Waiting for the opening `[`.
+    Start,
+    /// Between two elements, skipping whitespace and commas.
+    Between,
+    /// Inside an element starting at offset `start` of the buffer.
+    Element {
+        start: usize,
+        depth: usize,
+        in_string: bool,
+        escaped: bool,
+    },
+    Done,
+}
+
+impl<T: DeserializeOwned, R: AsyncRead + Unpin> ArrayStream<T, R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::with_capacity(8 * 1024),
+            scanned: 0,
+            state: State::Start,
+            _item: PhantomData,
+        }
+    }
+
+    /// Parse the next element, returns `None` once the array is closed.
+    pub async fn next(&mut self) -> Result<Option<T>> {
+        loop {
+            while self.scanned < self.buf.len() {
+                let byte = self.buf[self.scanned];
+                match &mut self.state {
+                    State::Start => match byte {
+                        b'[' => self.state = State::Between,
+                        byte if byte.is_ascii_whitespace() => {}
+                        _ => return Err(anyhow!("expected hyprland to reply with a json array")),
+                    },
+                    State::Between => match byte {
+                        b']' => self.state = State::Done,
+                        b',' => {}
+                        byte if byte.is_ascii_whitespace() => {}
+                        _ => {
+                            self.state = State::Element {
+                                start: self.scanned,
+                                depth: 0,
+                                in_string: false,
+                                escaped: false,
+                            };
+                            // Scan the first byte again as part of the element.
+                            continue;
+                        }
+                    },
+                    State::Element {
+                        start,
+                        depth,
+                        in_string,
+                        escaped,
+                    } => {
+                        let start = *start;
+                        if *in_string {
+                            match byte {
+                                _ if *escaped => *escaped = false,
+                                b'\\' => *escaped = true,
+                                b'"' => *in_string = false,
+                                _ => {}
+                            }
+                        } else {
+                            match byte {
+                                b'"' => *in_string = true,
+                                b'{' | b'[' => *depth += 1,
+                                // A scalar ended by the closing bracket of the array.
+                                b'}' | b']' if *depth == 0 => {
+                                    return self.emit(start, self.scanned)
+                                }
+                                b'}' | b']' => {
+                                    *depth -= 1;
+                                    if *depth == 0 {
+                                        return self.emit(start, self.scanned + 1);
+                                    }
+                                }
+                                b',' if *depth == 0 => return self.emit(start, self.scanned),
+                                _ => {}
+                            }
+                        }
+                    }
+                    State::Done => return Ok(None),
+                }
+                self.scanned += 1;
+            }
+
+            if let State::Done = self.state {
+                return Ok(None);
+            }
+            if self.reader.read_buf(&mut self.buf).await? == 0 {
+                return Err(anyhow!(
+                    "hyprland reply ended in the middle of a json array"
+                ));
+            }
+        }
+    }
+
+    /// Deserialize `buf[start..end]` and drop everything up to `end` from the buffer.
+    fn emit(&mut self, start: usize, end: usize) -> Result<Option<T>> {
+        let element = serde_json::from_slice(&self.buf[start..end])?;
+        self.buf.drain(..end);
+        // Scanning always stopped right at `end`.
+        self.scanned = 0;
+        self.state = State::Between;
+
+        Ok(Some(element))
+    }
+
+    /// Collect all elements.
+    pub async fn collect(self) -> Result<Vec<T>> {
+        self.filter(|_| true).await
+    }
+
+    /// Collect all elements matching `filter`, dropping the rest right after parsing them.
+    pub async fn filter(mut self, mut filter: impl FnMut(&T) -> bool) -> Result<Vec<T>> {
+        let mut elements = Vec::new();
+        while let Some(element) = self.next().await? {
+            if filter(&element) {
+                elements.push(element);
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Find the first element matching `filter`, without reading the rest of the reply.
+    pub async fn find(mut self, mut filter: impl FnMut(&T) -> bool) -> Result<Option<T>> {
+        while let Some(element) = self.next().await? {
+            if filter(&element) {
+                return Ok(Some(element));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArrayStream;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+    use tokio::io::{self, AsyncWriteExt};
+
+    fn stream<T: DeserializeOwned>(reply: &'static str) -> ArrayStream<T, &'static [u8]> {
+        ArrayStream::new(reply.as_bytes())
+    }
+
+    /// A stream whose reader hands out `reply` a byte at a time, so every element is split
+    /// across reads.
+    fn trickle<T: DeserializeOwned>(reply: &'static str) -> ArrayStream<T, io::DuplexStream> {
+        let (mut writer, reader) = io::duplex(1);
+        tokio::spawn(async move { writer.write_all(reply.as_bytes()).await });
+        ArrayStream::new(reader)
+    }
+
+    #[tokio::test]
+    async fn escaped_quotes_and_backslashes() {
+        let reply = r#"[{"title": "say \"]}\" \\"}, "\\\"", "\\"]"#;
+        let expected = [
+            json!({"title": r#"say "]}" \"#}),
+            json!(r#"\""#),
+            json!(r"\"),
+        ];
+        assert_eq!(stream::<Value>(reply).collect().await.unwrap(), expected);
+        assert_eq!(trickle::<Value>(reply).collect().await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn nested_arrays_and_objects() {
+        let reply = r#"[{"a": [1, {"b": []}], "c": {}}, [[], [{}]]]"#;
+        let expected = [json!({"a": [1, {"b": []}], "c": {}}), json!([[], [{}]])];
+        assert_eq!(stream::<Value>(reply).collect().await.unwrap(), expected);
+        assert_eq!(trickle::<Value>(reply).collect().await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn scalar_elements() {
+        let reply = "[ 1,-2.5 , true,null,\"x\" ,3]";
+        let expected = [
+            json!(1),
+            json!(-2.5),
+            json!(true),
+            json!(null),
+            json!("x"),
+            json!(3),
+        ];
+        assert_eq!(stream::<Value>(reply).collect().await.unwrap(), expected);
+        assert_eq!(trickle::<Value>(reply).collect().await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn empty_array() {
+        for reply in ["[]", " [ \n ] "] {
+            assert!(stream::<Value>(reply).collect().await.unwrap().is_empty());
+            assert!(trickle::<Value>(reply).collect().await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_match() {
+        // The reply is cut off after the match, which only fails if it is read any further.
+        let found = stream::<u32>("[1, 2, 3, [").find(|n| *n == 2).await;
+        assert_eq!(found.unwrap(), Some(2));
+        let found = trickle::<u32>("[1, 2, 3, [").find(|n| *n == 2).await;
+        assert_eq!(found.unwrap(), Some(2));
+        assert!(stream::<u32>("[1, 2, 3, [").collect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_what_isnt_an_array() {
+        assert!(stream::<Value>("{}").collect().await.is_err());
+        assert!(stream::<Value>("[1, 2").collect().await.is_err());
+    }
+}
@@ -1,5 +1,18 @@
+pub mod agent;
+pub mod bench;
 pub mod cli;
+pub mod config;
+pub mod daemon;
+pub mod generate;
 pub mod hypr;
+pub mod listen;
+pub mod logging;
+pub mod man;
 pub mod path_builder;
+pub mod quick;
+pub mod schema;
 pub mod server;
 mod socket;
+pub mod status;
+
+pub use socket::Unreachable;
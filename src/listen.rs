@@ -0,0 +1,137 @@
+use crate::server::types::{Register, Snapshot};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Output formats of `listen`, every one printing the whole state as a single json line.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    /// The snapshot as returned by `export`.
+    #[default]
+    Json,
+    /// Lists instead of maps, so eww's `for` can iterate them directly.
+    Eww,
+    /// A waybar custom module, classed `urgent` while a bound workspace asks for attention.
+    Waybar,
+}
+
+impl Format {
+    pub fn render(self, state: &Snapshot) -> serde_json::Result<String> {
+        match self {
+            Format::Json => serde_json::to_string(state),
+            Format::Eww => serde_json::to_string(&EwwState::new(state)),
+            Format::Waybar => serde_json::to_string(&WaybarState::new(state)),
+        }
+    }
+}
+
+/// Stable schema of `listen --format eww`.
+#[derive(Debug, Serialize)]
+pub struct EwwState<'a> {
+    pub workspaces: Vec<EwwWorkspace<'a>>,
+    pub registers: Vec<EwwRegister<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EwwWorkspace<'a> {
+    pub name: &'a str,
+    pub icon: Option<&'a str>,
+    pub label: Option<&'a str>,
+    pub urgent: bool,
+    pub registers: Vec<Register>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EwwRegister<'a> {
+    pub register: Register,
+    /// What to show in place of the register, its number unless the config gives a glyph.
+    pub glyph: String,
+    pub workspace: &'a str,
+    pub icon: Option<&'a str>,
+    pub label: Option<&'a str>,
+    pub urgent: bool,
+}
+
+impl<'a> EwwState<'a> {
+    pub fn new(state: &'a Snapshot) -> Self {
+        Self {
+            workspaces: state
+                .workspaces
+                .iter()
+                .map(|(name, settings)| EwwWorkspace {
+                    name,
+                    icon: settings.icon.as_deref(),
+                    label: settings.label.as_deref(),
+                    urgent: state.is_urgent(name),
+                    registers: state
+                        .registers
+                        .iter()
+                        .filter(|(_, register_pointee)| *register_pointee == name)
+                        .map(|(register, _)| *register)
+                        .collect(),
+                })
+                .collect(),
+            registers: state
+                .registers
+                .iter()
+                .map(|(register, workspace)| {
+                    let settings = state.workspaces.get(workspace);
+                    EwwRegister {
+                        register: *register,
+                        glyph: glyph(state, *register),
+                        workspace,
+                        icon: settings.and_then(|settings| settings.icon.as_deref()),
+                        label: settings.and_then(|settings| settings.label.as_deref()),
+                        urgent: state.is_urgent(workspace),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The glyph the config gives `register`, or its number.
+fn glyph(state: &Snapshot, register: Register) -> String {
+    match state.glyphs.get(&register) {
+        Some(glyph) => glyph.clone(),
+        None => register.to_string(),
+    }
+}
+
+/// Stable schema of `listen --format waybar`, see waybar's `custom` module with
+/// `return-type` set to `json`.
+#[derive(Debug, Serialize)]
+pub struct WaybarState {
+    /// The glyphs of the bound registers, urgent ones marked with a `!`.
+    pub text: String,
+    /// A `register: workspace` line per binding.
+    pub tooltip: String,
+    pub class: Vec<&'static str>,
+}
+
+impl WaybarState {
+    pub fn new(state: &Snapshot) -> Self {
+        let mut text = Vec::new();
+        let mut tooltip = Vec::new();
+        let mut urgent = false;
+        for (register, workspace) in &state.registers {
+            let marker = match state.is_urgent(workspace) {
+                true => {
+                    urgent = true;
+                    "!"
+                }
+                false => "",
+            };
+            text.push(format!("{}{marker}", glyph(state, *register)));
+            tooltip.push(format!("{register}: {workspace}{marker}"));
+        }
+
+        Self {
+            text: text.join(" "),
+            tooltip: tooltip.join("\n"),
+            class: match urgent {
+                true => vec!["urgent"],
+                false => Vec::new(),
+            },
+        }
+    }
+}
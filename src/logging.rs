@@ -0,0 +1,305 @@
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{
+    field::{RecordFields, Visit},
+    fmt::{
+        format::{self, DefaultFields, FmtSpan},
+        writer::BoxMakeWriter,
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+    },
+    layer::{Layer, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Registry,
+};
+use tracing_tree::HierarchicalLayer;
+
+/// Log files are rotated once they grow past this many bytes.
+const MAX_FILE_SIZE: u64 = 8 * 1024 * 1024;
+/// Rotated log files kept around as `{file}.1` up to `{file}.{KEPT_FILES}`.
+const KEPT_FILES: usize = 3;
+
+/// How much gets logged to where, in which format.
+#[derive(Debug, Clone, Default, Args)]
+#[clap(about = None, long_about = None, next_help_heading = "Logging")]
+pub struct Logging {
+    /// What to log, like `warn` or `info,hypr_workspace_manager::server=debug` [default: $RUST_LOG or info]
+    #[clap(long, global = true)]
+    log_level: Option<String>,
+    #[clap(long, global = true, value_enum, default_value_t)]
+    log_format: LogFormat,
+    /// Append logs to this file instead of stderr, rotating it once it grows past 8 MiB
+    #[clap(long, global = true)]
+    log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum LogFormat {
+    /// Indented by span, for reading logs in a terminal
+    #[default]
+    Tree,
+    /// A line per event and span transition, for the journal
+    Flat,
+    /// A json object per event
+    Json,
+}
+
+impl Logging {
+    /// The file logs are appended to, if not logging to stderr.
+    pub fn file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+
+    /// Log to `file` instead of wherever was configured.
+    pub fn with_file(self, file: PathBuf) -> Self {
+        Self {
+            log_file: Some(file),
+            ..self
+        }
+    }
+
+    /// The flags configuring this, so a process started from the cli logs the same way.
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        if let Some(level) = &self.log_level {
+            args.extend(["--log-level".into(), level.into()]);
+        }
+        if let Some(format) = self.log_format.to_possible_value() {
+            args.extend(["--log-format".into(), format.get_name().into()]);
+        }
+        if let Some(file) = &self.log_file {
+            args.extend(["--log-file".into(), file.into()]);
+        }
+        args
+    }
+
+    /// Install the global subscriber.
+    pub fn init(&self) -> Result<()> {
+        let filter = match &self.log_level {
+            Some(level) => EnvFilter::try_new(level)?,
+            None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        };
+        let (writer, ansi) = match &self.log_file {
+            Some(path) => (
+                BoxMakeWriter::new(Mutex::new(RotatingFile::open(path)?)),
+                false,
+            ),
+            None => (BoxMakeWriter::new(io::stderr), io::stderr().is_terminal()),
+        };
+
+        let output: Box<dyn Layer<_> + Send + Sync> = match self.log_format {
+            LogFormat::Tree => Box::new(
+                HierarchicalLayer::new(4)
+                    .with_writer(writer)
+                    .with_ansi(ansi),
+            ),
+            LogFormat::Flat => Box::new(
+                tracing_subscriber::fmt::layer()
+                    .with_span_events(FmtSpan::FULL)
+                    .with_writer(writer)
+                    .with_ansi(ansi),
+            ),
+            LogFormat::Json => Box::new(
+                tracing_subscriber::fmt::layer()
+                    // Also keeps escape codes out of the span fields.
+                    .with_ansi(false)
+                    .event_format(Json)
+                    .with_writer(writer),
+            ),
+        };
+
+        Registry::default()
+            .with(filter)
+            .with(output)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .fmt_fields(PlainFields::default())
+                    .with_writer(|| RecentWriter)
+                    .with_ansi(false),
+            )
+            .try_init()?;
+        Ok(())
+    }
+}
+
+/// The last `lines` log records, oldest first.
+///
+/// The server keeps the last [`RECENT_RECORDS`] records in memory, so they can be looked at
+/// even if its output went nowhere.
+pub fn recent(lines: usize) -> String {
+    let recent = RECENT.lock().expect("lock poisoned");
+    recent
+        .iter()
+        .skip(recent.len().saturating_sub(lines))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Records kept for [`recent`].
+pub const RECENT_RECORDS: usize = 1000;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Appends every record written to it to [`RECENT`], the fmt layer writes each in one go.
+struct RecentWriter;
+
+impl Write for RecentWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut recent = RECENT.lock().expect("lock poisoned");
+        if RECENT_RECORDS <= recent.len() {
+            recent.pop_front();
+        }
+        recent.push_back(String::from_utf8_lossy(buf).into_owned());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Plain [`DefaultFields`] under a type of its own.
+///
+/// Fmt layers store the fields of each span keyed by the type of their field formatter, so
+/// sharing it with the colored output would get escape codes into the recent records.
+#[derive(Default)]
+struct PlainFields(DefaultFields);
+
+impl<'w> FormatFields<'w> for PlainFields {
+    fn format_fields<R: RecordFields>(&self, writer: format::Writer<'w>, fields: R) -> fmt::Result {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+/// Formats every event as a json object with its fields and the spans it happened in.
+struct Json;
+
+impl<S, N> FormatEvent<S, N> for Json
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+
+        let spans: Vec<_> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let extensions = span.extensions();
+                let fields = extensions
+                    .get::<FormattedFields<N>>()
+                    .map(|fields| fields.fields.as_str())
+                    .unwrap_or_default();
+                serde_json::json!({ "name": span.name(), "fields": fields })
+            })
+            .collect();
+
+        let metadata = event.metadata();
+        let line = serde_json::json!({
+            "time": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis() as u64),
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "spans": spans,
+            "fields": fields.0,
+        });
+        writeln!(writer, "{line}")
+    }
+}
+
+#[derive(Default)]
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl Visit for JsonFields {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{value:?}").into());
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+}
+
+/// Appends to a file, shifting it to `{path}.1` and older files one further once it is full.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_owned(),
+            size: file.metadata()?.len(),
+            file,
+        })
+    }
+
+    fn rotated(&self, nr: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{nr}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for nr in (1..KEPT_FILES).rev() {
+            match fs::rename(self.rotated(nr), self.rotated(nr + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if MAX_FILE_SIZE < self.size + buf.len() as u64 && 0 < self.size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,26 +1,44 @@
 use clap::Parser;
-use hypr_workspace_manager::cli::Cli;
+use hypr_workspace_manager::{cli::Cli, daemon, quick, server::types::Failure, Unreachable};
+use std::process::ExitCode;
 
-#[allow(dead_code)]
-fn tracing_flat() {
-    use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_span_events(FmtSpan::FULL)
-        .init();
-}
+fn main() -> ExitCode {
+    // Keybinds don't wait for clap and the runtime to start.
+    let args: Vec<_> = std::env::args_os().collect();
+    if let Some(res) = quick::run(&args) {
+        return exit_code(res);
+    }
 
-#[allow(dead_code)]
-fn tracing_tree() {
-    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Registry};
-    use tracing_tree::HierarchicalLayer;
-    Registry::default().with(HierarchicalLayer::new(4)).init();
-}
+    let cli = Cli::parse_from(args);
+    // Forking is only sound before the runtime starts its threads.
+    let daemon_log = cli.daemon_log();
+    let detached = match &daemon_log {
+        Ok(Some(log)) => daemon::detach(log),
+        _ => Ok(()),
+    };
+    let res = detached.and_then(|()| tokio::runtime::Runtime::new()?.block_on(cli.run()));
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_tree();
-    Cli::parse().run().await?;
+    // Stderr of a daemon goes nowhere.
+    if let (Err(err), Ok(Some(_))) = (&res, &daemon_log) {
+        tracing::error!("{err:?}");
+    }
+    exit_code(res)
+}
 
-    Ok(())
+fn exit_code(res: anyhow::Result<()>) -> ExitCode {
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            if let Some(usage) = err.downcast_ref::<clap::Error>() {
+                let _ = usage.print();
+                return ExitCode::from(usage.exit_code() as u8);
+            }
+            eprintln!("Error: {err:?}");
+            let code = match err.downcast_ref::<Unreachable>() {
+                Some(unreachable) => unreachable.exit_code(),
+                None => err.downcast_ref::<Failure>().map_or(1, Failure::exit_code),
+            };
+            ExitCode::from(code)
+        }
+    }
 }
@@ -0,0 +1,272 @@
+//! `man`, the manual of the cli and the socket protocol, rendered from what clap and the
+//! schema know about them so it never goes out of date.
+
+use clap::{Arg, ArgAction, Command};
+use serde_json::Value;
+use std::fmt::Write;
+
+/// What the manual is rendered as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// A man page, for `man -l` or to be installed into section 1.
+    Roff,
+    /// Plain text, like `--help` of every subcommand at once.
+    Plain,
+}
+
+/// The manual of `command` and every subcommand of it, followed by the `protocol` as
+/// described by [`crate::schema::protocol`].
+pub fn render(command: &Command, protocol: &Value, style: Style) -> String {
+    let name = command.get_name();
+    let mut page = Page {
+        style,
+        out: String::new(),
+        indent: 0,
+    };
+    if style == Style::Roff {
+        page.raw(&format!(
+            ".TH {} 1 \"\" \"{name} {}\"",
+            name.to_uppercase(),
+            env!("CARGO_PKG_VERSION")
+        ));
+    }
+
+    page.section("name");
+    page.paragraph(&format!(
+        "{name} - keep hyprland workspaces in numbered registers"
+    ));
+    page.section("synopsis");
+    page.paragraph(&format!("{name} [OPTIONS] <COMMAND>"));
+    page.section("options");
+    args(&mut page, command);
+    page.section("commands");
+    for subcommand in command.get_subcommands() {
+        commands(&mut page, subcommand, subcommand.get_name());
+    }
+
+    if let Some(after_help) = command.get_after_help() {
+        page.section("exit status");
+        for line in after_help.to_string().lines().skip(1) {
+            if let Some((code, meaning)) = line.trim().split_once(char::is_whitespace) {
+                page.item(code, meaning.trim());
+            }
+        }
+    }
+
+    page.section("protocol");
+    page.paragraph(&format!(
+        "Requests are sent to the socket as {}. Every request is answered in order, \
+         pipelined ones are wrapped as {} and answered with {}.",
+        text(&protocol["framing"]),
+        protocol["pipelined"]["request"],
+        protocol["pipelined"]["reply"],
+    ));
+    page.subsection("requests");
+    if let Value::Object(requests) = &protocol["requests"] {
+        for (name, request) in requests {
+            let signature = match (&request["fields"], &request["list"]) {
+                (Value::Object(fields), _) => {
+                    let fields: Vec<_> = fields
+                        .iter()
+                        .map(|(field, ty)| format!("{field}: {}", text(ty)))
+                        .collect();
+                    format!("{name} {{{}}}", fields.join(", "))
+                }
+                (_, Value::String(list)) => format!("{name} [{list}]"),
+                _ => name.clone(),
+            };
+            let mut about = format!("Answered with {}.", text(&request["response"]));
+            if request["mutating"] == true {
+                about.push_str(" Changes the state.");
+            }
+            if request["read_only"] == true {
+                about.push_str(" Allowed on the read-only socket.");
+            }
+            write!(about, " Example: {}", request["example"]).expect("strings can be written");
+            page.item(&signature, &about);
+        }
+    }
+    page.subsection("errors");
+    page.paragraph(
+        "A failing request is answered with an object of the kind of error as `error`, the \
+         fields of that kind and the `request_id` the server logged it under.",
+    );
+    if let Value::Object(errors) = &protocol["errors"] {
+        for (kind, fields) in errors {
+            let fields: Vec<_> = fields.as_array().into_iter().flatten().map(text).collect();
+            page.item(kind, &fields.join(", "));
+        }
+    }
+
+    page.out
+}
+
+/// `command` under its full name `path`, followed by the subcommands it has.
+fn commands(page: &mut Page, command: &Command, path: &str) {
+    if command.is_hide_set() {
+        return;
+    }
+    page.subsection(path);
+    if let Some(about) = command.get_long_about().or(command.get_about()) {
+        page.paragraph(&about.to_string());
+    }
+    args(page, command);
+    for subcommand in command.get_subcommands() {
+        let path = format!("{path} {}", subcommand.get_name());
+        commands(page, subcommand, &path);
+    }
+}
+
+fn args(page: &mut Page, command: &Command) {
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        let mut help = arg
+            .get_long_help()
+            .or(arg.get_help())
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        // Flags have a value too, which is never written out.
+        let values: Vec<_> = match arg.get_action().takes_values() {
+            true => arg.get_possible_values(),
+            false => Vec::new(),
+        };
+        let values: Vec<_> = values
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_owned())
+            .collect();
+        if !values.is_empty() {
+            write!(help, " [possible values: {}]", values.join(", "))
+                .expect("strings can be written");
+        }
+        page.item(&term(arg), help.trim());
+    }
+}
+
+/// How `arg` is written on the command line, like `-q, --quiet` or `<REGISTER>`.
+fn term(arg: &Arg) -> String {
+    let value = match arg.get_value_names() {
+        Some(names) => names
+            .iter()
+            .map(|name| format!("<{name}>"))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+    };
+    if arg.is_positional() {
+        return match arg.get_action() {
+            ArgAction::Append => format!("{value}..."),
+            _ => value,
+        };
+    }
+
+    let mut term = String::new();
+    if let Some(short) = arg.get_short() {
+        write!(term, "-{short}, ").expect("strings can be written");
+    }
+    if let Some(long) = arg.get_long() {
+        write!(term, "--{long}").expect("strings can be written");
+    }
+    if arg.get_action().takes_values() {
+        write!(term, " {value}").expect("strings can be written");
+    }
+    term
+}
+
+/// `value` as text, strings without their quotes.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// The manual as it is being written, in either [`Style`].
+struct Page {
+    style: Style,
+    out: String,
+    /// How far plain text is indented, deeper inside subsections.
+    indent: usize,
+}
+
+impl Page {
+    fn raw(&mut self, line: &str) {
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn section(&mut self, title: &str) {
+        match self.style {
+            Style::Roff => self.raw(&format!(".SH {}", title.to_uppercase())),
+            Style::Plain => {
+                if !self.out.is_empty() {
+                    self.raw("");
+                }
+                self.raw(&title.to_uppercase());
+            }
+        }
+        self.indent = 4;
+    }
+
+    fn subsection(&mut self, title: &str) {
+        match self.style {
+            Style::Roff => self.raw(&format!(".SS {}", escape(title))),
+            Style::Plain => {
+                self.raw("");
+                self.raw(&format!("    {title}"));
+            }
+        }
+        self.indent = 8;
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        match self.style {
+            Style::Roff => {
+                self.raw(".PP");
+                self.raw(&escape(text));
+            }
+            Style::Plain => self.lines(text, self.indent),
+        }
+    }
+
+    /// A `term` like an option or request, described by `text`.
+    fn item(&mut self, term: &str, text: &str) {
+        match self.style {
+            Style::Roff => {
+                self.raw(".TP");
+                self.raw(&format!("\\fB{}\\fR", escape(term)));
+                self.raw(&escape(text));
+            }
+            Style::Plain => {
+                self.lines(term, self.indent);
+                self.lines(text, self.indent + 4);
+            }
+        }
+    }
+
+    fn lines(&mut self, text: &str, indent: usize) {
+        for line in text.lines() {
+            match line.is_empty() {
+                true => self.raw(""),
+                false => self.raw(&format!("{:indent$}{line}", "")),
+            }
+        }
+    }
+}
+
+/// `text` with everything roff would take as markup escaped.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    escaped
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                ".sp".to_owned()
+            } else if line.starts_with(['.', '\'']) {
+                format!("\\&{line}")
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
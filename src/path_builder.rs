@@ -1,9 +1,15 @@
+use crate::{config::Config, server::Server};
 use anyhow::{anyhow, Result};
+use clap::Args;
 use std::{
     env::VarError,
+    ffi::OsString,
     fmt::{Display, Write},
     path::{Path, PathBuf},
+    time::Duration,
 };
+use tokio::time::{self, Instant};
+use tracing::info;
 
 pub struct PathBuilder {
     path: PathBuf,
@@ -25,6 +31,11 @@ impl PathBuilder {
         self.path.push(name);
         &self.path
     }
+
+    pub fn into_basepath(mut self) -> PathBuf {
+        self.path.pop();
+        self.path
+    }
 }
 
 impl PathBuilder {
@@ -44,7 +55,230 @@ impl PathBuilder {
         };
 
         Ok(PathBuilder::from_basepath(format_args!(
-            "/run/user/1000/hypr/{instance}"
+            "{}/{instance}",
+            Self::HYPR_RUNTIME_DIR
         )))
     }
+
+    const HYPR_RUNTIME_DIR: &'static str = "/run/user/1000/hypr";
+
+    /// Runtime directory of the hyprland instance started last, which after a restart isn't
+    /// the one `HYPRLAND_INSTANCE_SIGNATURE` names anymore.
+    pub fn latest_hypr_basepath() -> Option<Self> {
+        let instances = std::fs::read_dir(Self::HYPR_RUNTIME_DIR).ok()?;
+        let latest = instances
+            .flatten()
+            .filter(|instance| instance.path().join(".socket2.sock").exists())
+            .filter_map(|instance| Some((instance.metadata().ok()?.modified().ok()?, instance)))
+            .max_by_key(|(modified, _)| *modified)?;
+        Some(PathBuilder::from_basepath(latest.1.path().display()))
+    }
+
+    pub fn state_basepath() -> Result<Self> {
+        Self::xdg_basepath("XDG_STATE_HOME", ".local/state")
+    }
+
+    pub fn config_basepath() -> Result<Self> {
+        Self::xdg_basepath("XDG_CONFIG_HOME", ".config")
+    }
+
+    /// Our directory inside the xdg base directory `var`, falling back to `$HOME/{fallback}`.
+    fn xdg_basepath(var: &str, fallback: &str) -> Result<Self> {
+        let base = match std::env::var_os(var) {
+            Some(base) if !base.is_empty() => PathBuf::from(base),
+            _ => match std::env::var_os("HOME") {
+                Some(home) => Path::new(&home).join(fallback),
+                None => {
+                    return Err(anyhow!("neither {var} nor HOME are set"));
+                }
+            },
+        };
+
+        Ok(PathBuilder::from_basepath(
+            base.join("hypr-workspace-manager").display(),
+        ))
+    }
+}
+
+/// Every path used by the server and the cli, guessed from the environment unless given.
+///
+/// With `--explicit-paths` all of them have to be given and nothing is guessed, which is what
+/// packaging for NixOS or home-manager wants.
+#[derive(Debug, Clone, Default, Args)]
+#[clap(about = None, long_about = None, next_help_heading = "Paths")]
+pub struct Paths {
+    /// Config file [default: $XDG_CONFIG_HOME/hypr-workspace-manager/config.toml]
+    #[clap(long = "config", global = true)]
+    config: Option<PathBuf>,
+    /// Directory the event log is stored in [default: $XDG_STATE_HOME/hypr-workspace-manager]
+    #[clap(long, global = true)]
+    state_dir: Option<PathBuf>,
+    /// Runtime directory of the hyprland instance [default: from $HYPRLAND_INSTANCE_SIGNATURE]
+    #[clap(long, global = true)]
+    hypr_dir: Option<PathBuf>,
+    /// Socket the server listens on [default: ws-mgr.sock in the hyprland runtime directory]
+    ///
+    /// Servers with their own socket and state dir can manage the same hyprland instance side
+    /// by side.
+    #[clap(long, global = true, env = "WS_MGR_SOCKET")]
+    socket: Option<PathBuf>,
+    /// Don't guess any path from the environment, requires all other path flags
+    #[clap(
+        long,
+        global = true,
+        requires_all = ["config", "state_dir", "hypr_dir", "socket"]
+    )]
+    explicit_paths: bool,
+}
+
+impl Paths {
+    pub fn config(&self) -> Result<PathBuf> {
+        self.given_or(&self.config, || {
+            Ok(PathBuilder::config_basepath()?
+                .with_filename(Config::FILE)
+                .to_owned())
+        })
+    }
+
+    pub fn state_dir(&self) -> Result<PathBuf> {
+        self.given_or(&self.state_dir, || {
+            Ok(PathBuilder::state_basepath()?.into_basepath())
+        })
+    }
+
+    pub fn hypr_dir(&self) -> Result<PathBuf> {
+        self.given_or(&self.hypr_dir, || {
+            Ok(PathBuilder::hypr_basepath()?.into_basepath())
+        })
+    }
+
+    pub fn socket(&self) -> Result<PathBuf> {
+        self.given_or(&self.socket, || Ok(self.hypr_dir()?.join(Server::SOCKET)))
+    }
+
+    /// The read-only socket, which always lives next to the socket.
+    ///
+    /// It is named after the socket, `work.sock` comes with `work-ro.sock`, so servers sharing
+    /// a directory don't share it.
+    pub fn read_only_socket(&self) -> Result<PathBuf> {
+        let socket = self.socket()?;
+        let name = match socket.file_stem() {
+            Some(stem) => format!("{}-ro.sock", stem.to_string_lossy()),
+            None => Server::READ_ONLY_SOCKET.to_owned(),
+        };
+        Ok(socket.with_file_name(name))
+    }
+
+    /// The flags giving these paths, so a process started from the cli uses the same ones.
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        let given = [
+            ("--config", &self.config),
+            ("--state-dir", &self.state_dir),
+            ("--hypr-dir", &self.hypr_dir),
+            ("--socket", &self.socket),
+        ];
+        for (flag, path) in given {
+            if let Some(path) = path {
+                args.extend([flag.into(), path.into()]);
+            }
+        }
+        if self.explicit_paths {
+            args.push("--explicit-paths".into());
+        }
+        args
+    }
+
+    /// The paths after hyprland restarted, with a runtime dir the instance started last, unless
+    /// the hyprland dir was given.
+    pub fn after_hypr_restart(&self) -> Self {
+        let hypr_dir = match &self.hypr_dir {
+            Some(_) => None,
+            None => PathBuilder::latest_hypr_basepath().map(PathBuilder::into_basepath),
+        };
+        Self {
+            hypr_dir: hypr_dir.or_else(|| self.hypr_dir.clone()),
+            ..self.clone()
+        }
+    }
+
+    /// Wait for hyprland to create its sockets in the hyprland dir, failing after `timeout`.
+    pub async fn wait_for_hypr(&self, timeout: Duration) -> Result<()> {
+        let hypr_dir = self.hypr_dir()?;
+        let sockets = [".socket.sock", ".socket2.sock"].map(|socket| hypr_dir.join(socket));
+        let missing = || sockets.iter().find(|socket| !socket.exists());
+
+        let Some(socket) = missing() else {
+            return Ok(());
+        };
+        info!(socket = %socket.display(), "waiting for hyprland");
+        let deadline = Instant::now() + timeout;
+        while let Some(socket) = missing() {
+            if Instant::now() >= deadline {
+                return Err(anyhow!(
+                    "hyprland didn't create {} within {}s",
+                    socket.display(),
+                    timeout.as_secs()
+                ));
+            }
+            time::sleep(Self::HYPR_POLL).await;
+        }
+        Ok(())
+    }
+
+    const HYPR_POLL: Duration = Duration::from_millis(100);
+
+    fn given_or(
+        &self,
+        path: &Option<PathBuf>,
+        guess: impl FnOnce() -> Result<PathBuf>,
+    ) -> Result<PathBuf> {
+        match path {
+            Some(path) => Ok(path.clone()),
+            None if self.explicit_paths => Err(anyhow!("path not given with --explicit-paths")),
+            None => guess(),
+        }
+    }
+
+    /// Check that the explicitly given paths exist, so a broken setup fails on startup instead
+    /// of on first use.
+    pub fn verify(&self) -> Result<()> {
+        if !self.explicit_paths {
+            return Ok(());
+        }
+
+        let mut problems = Vec::new();
+        let config = self.config()?;
+        if !config.is_file() {
+            problems.push(format!("config {} is not a file", config.display()));
+        }
+        let state_dir = self.state_dir()?;
+        if !state_dir.is_dir() {
+            problems.push(format!(
+                "state dir {} is not a directory",
+                state_dir.display()
+            ));
+        }
+        let hypr_dir = self.hypr_dir()?;
+        for socket in [".socket.sock", ".socket2.sock"] {
+            if !hypr_dir.join(socket).exists() {
+                problems.push(format!(
+                    "hyprland dir {} has no {socket}",
+                    hypr_dir.display()
+                ));
+            }
+        }
+        let socket = self.socket()?;
+        if !socket.parent().is_some_and(Path::is_dir) {
+            problems.push(format!(
+                "socket {} is not inside an existing directory",
+                socket.display()
+            ));
+        }
+
+        if !problems.is_empty() {
+            return Err(anyhow!("invalid paths: {}", problems.join(", ")));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,93 @@
+//! Fast path for the requests bound to keys, which skips clap and the runtime.
+//!
+//! `ws-mgr g3`, `ws-mgr m3` and `ws-mgr f3` go to, move to and fetch register 3, and so do
+//! `wsgoto 3`, `wsmoveto 3` and `wsfetch 3` with the binary symlinked to those names. Anything
+//! else, including global flags, goes through the full cli.
+
+use crate::{
+    path_builder::Paths,
+    server::types::{Failure, Register, Request},
+    socket::Unreachable,
+};
+use anyhow::{Context, Result};
+use std::{
+    ffi::OsString,
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How long a keybind waits for the server before giving up, so a hung server doesn't leave
+/// key presses piling up.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Send the request `args` ask for, `None` if they aren't meant for the fast path.
+pub fn run(args: &[OsString]) -> Option<Result<()>> {
+    let request = parse(args)?;
+    Some(socket().and_then(|socket| send(&socket, &encode(&request))))
+}
+
+fn parse(args: &[OsString]) -> Option<Request<'static>> {
+    let [bin, arg] = args else {
+        return None;
+    };
+    let arg = arg.to_str()?;
+    let (action, register) = match Path::new(bin).file_name()?.to_str()? {
+        "wsgoto" => ("g", arg),
+        "wsmoveto" => ("m", arg),
+        "wsfetch" => ("f", arg),
+        _ => arg.split_at_checked(1)?,
+    };
+    if !register.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let register: Register = register.parse().ok()?;
+
+    match action {
+        "g" => Some(Request::Goto { register }),
+        "m" => Some(Request::Moveto { register }),
+        "f" => Some(Request::Fetch { register }),
+        _ => None,
+    }
+}
+
+/// The socket of the server, as the full cli would find it without flags.
+pub fn socket() -> Result<PathBuf> {
+    // Clap reads the variable for the full cli.
+    match std::env::var_os("WS_MGR_SOCKET") {
+        Some(socket) => Ok(PathBuf::from(socket)),
+        None => Paths::default().socket(),
+    }
+}
+
+/// `request` followed by a flush, the way the cli sends it, ready to be written as is.
+pub fn encode(request: &Request) -> Vec<u8> {
+    let mut msg = serde_json::to_vec(request).expect("requests serialize");
+    msg.extend_from_slice(b"\n\"flush\"\n");
+    msg
+}
+
+/// Send `msg`, as [`encode`]d, to the server at `socket` and wait for it to answer.
+pub fn send(socket: &Path, msg: &[u8]) -> Result<()> {
+    let mut stream =
+        UnixStream::connect(socket).map_err(|err| Unreachable::from_io(err, socket))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    stream.write_all(msg)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .context("the server didn't answer in time")?;
+    match reply
+        .lines()
+        .find_map(|line| serde_json::from_str::<Failure>(line).ok())
+    {
+        Some(failure) => Err(failure.into()),
+        None => Ok(()),
+    }
+}
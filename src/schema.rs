@@ -0,0 +1,370 @@
+use crate::server::types::{Request, ServerError};
+use serde_json::{json, Map, Value};
+use std::fmt::{self, Display, Formatter};
+
+/// Type of a request field, displayed the way the schema spells it.
+#[derive(Debug, Clone, Copy)]
+pub enum Ty {
+    Str,
+    U16,
+    U32,
+    I32,
+    Usize,
+    Bool,
+    /// May be `null` or left out.
+    Opt(&'static Ty),
+    /// An array of these.
+    List(&'static Ty),
+    /// One of these strings.
+    Choice(&'static [&'static str]),
+    /// A compound type described in the `types` of the schema.
+    Named(&'static str),
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Str => f.write_str("string"),
+            Ty::U16 => f.write_str("u16"),
+            Ty::U32 => f.write_str("u32"),
+            Ty::I32 => f.write_str("i32"),
+            Ty::Usize => f.write_str("usize"),
+            Ty::Bool => f.write_str("bool"),
+            Ty::Opt(ty) => write!(f, "{ty}?"),
+            Ty::List(ty) => write!(f, "{ty}[]"),
+            Ty::Choice(choices) => {
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" | ")?;
+                    }
+                    write!(f, "{choice:?}")?;
+                }
+                Ok(())
+            }
+            Ty::Named(name) => f.write_str(name),
+        }
+    }
+}
+
+const DYNAMIC_RULES: Ty = Ty::Choice(&["current", "previous"]);
+const PREFIX_ACTIONS: Ty = Ty::Choice(&["moveto", "fetch", "bind", "unbind"]);
+const MASTER_ORIENTATIONS: Ty = Ty::Choice(&["left", "right", "top", "bottom", "center"]);
+const IMPORT_MODES: Ty = Ty::Choice(&["merge", "replace"]);
+
+/// How a request variant is put on the wire.
+enum Body {
+    /// A bare string naming the variant.
+    Unit,
+    /// An object with the variant as its only key, holding these fields and their types.
+    Fields(&'static [(&'static str, Ty)]),
+    /// An object with the variant as its only key, holding a list of this type.
+    List(Ty),
+}
+
+/// A request as described by [`protocol`].
+struct RequestSchema {
+    /// Name of the [`Request`] variant.
+    variant: &'static str,
+    body: Body,
+    response: &'static str,
+    /// Sent to check the description against the actual definition of [`Request`].
+    example: &'static str,
+}
+
+/// Generate [`REQUESTS`] along with [`variant_of`], whose match breaks the build once a
+/// [`Request`] variant or one of its fields is missing from the list.
+///
+/// Variants are listed as `Unit`, `Tuple(ty)` for lists, or `Struct { field: ty, .. }` with
+/// every field of the variant.
+macro_rules! requests {
+    ($(
+        $variant:ident $(($list:expr))? $({ $($field:ident: $ty:expr),* $(,)? })?
+            => $response:expr, $example:expr;
+    )*) => {
+        const REQUESTS: &[RequestSchema] = &[$(
+            RequestSchema {
+                variant: stringify!($variant),
+                body: body!($(($list))? $({ $($field: $ty),* })?),
+                response: $response,
+                example: $example,
+            },
+        )*];
+
+        /// Name of the variant of `request`.
+        fn variant_of(request: &Request) -> &'static str {
+            match request {
+                $(pattern!($variant $(($list))? $({ $($field: $ty),* })?) => stringify!($variant),)*
+            }
+        }
+    };
+}
+
+macro_rules! body {
+    () => {
+        Body::Unit
+    };
+    (($list:expr)) => {
+        Body::List($list)
+    };
+    ({ $($field:ident: $ty:expr),* }) => {
+        Body::Fields(&[$((stringify!($field), $ty)),*])
+    };
+}
+
+/// A pattern matching the variant, naming every field without `..`.
+macro_rules! pattern {
+    ($variant:ident) => {
+        Request::$variant {}
+    };
+    ($variant:ident ($list:expr)) => {
+        Request::$variant { 0: _ }
+    };
+    ($variant:ident { $($field:ident: $ty:expr),* }) => {
+        Request::$variant { $($field: _),* }
+    };
+}
+
+requests! {
+    Create { name: Ty::Str } => "ok", r#"{"create":{"name":"dev"}}"#;
+    CreateFrom { name: Ty::Str, template: Ty::Str }
+        => "ok", r#"{"create_from":{"name":"dev","template":"coding"}}"#;
+    Bind { name: Ty::Str, register: Ty::U16, force: Ty::Opt(&Ty::Bool), ttl: Ty::Opt(&Ty::U32) }
+        => "ok", r#"{"bind":{"name":"dev","register":1,"ttl":7200}}"#;
+    BindIf {
+        name: Ty::Str,
+        register: Ty::U16,
+        expected: Ty::Opt(&Ty::Str),
+        force: Ty::Opt(&Ty::Bool),
+    } => "ok", r#"{"bind_if":{"name":"dev","register":1,"expected":null}}"#;
+    BindDynamic { register: Ty::U16, rule: DYNAMIC_RULES, force: Ty::Opt(&Ty::Bool) }
+        => "ok", r#"{"bind_dynamic":{"register":0,"rule":"previous"}}"#;
+    Unbind { register: Ty::U16, force: Ty::Opt(&Ty::Bool) }
+        => "ok", r#"{"unbind":{"register":1,"force":true}}"#;
+    Lock { register: Ty::U16 } => "ok", r#"{"lock":{"register":1}}"#;
+    Unlock { register: Ty::U16 } => "ok", r#"{"unlock":{"register":1}}"#;
+    GroupCreate { group: Ty::Str, members: Ty::List(&Ty::Str) }
+        => "ok", r#"{"group_create":{"group":"work","members":["dev","mail"]}}"#;
+    GroupRemove { group: Ty::Str } => "ok", r#"{"group_remove":{"group":"work"}}"#;
+    GroupGoto { group: Ty::Str } => "ok", r#"{"group_goto":{"group":"work"}}"#;
+    GroupRead { group: Ty::Opt(&Ty::Str) }
+        => "map of group names to their members, string[]", r#"{"group_read":{"group":null}}"#;
+    SwitchContext { context: Ty::Opt(&Ty::Str) }
+        => "ok", r#"{"switch_context":{"context":"work"}}"#;
+    PinMonitor { register: Ty::U16, monitor: Ty::Opt(&Ty::Str) }
+        => "ok", r#"{"pin_monitor":{"register":1,"monitor":"DP-1"}}"#;
+    UnpinMonitor { register: Ty::U16 } => "ok", r#"{"unpin_monitor":{"register":1}}"#;
+    Configure { name: Ty::Str, settings: Ty::Named("settings") }
+        => "ok", r#"{"configure":{"name":"dev","settings":{}}}"#;
+    Merge {
+        from: Ty::Named("workspace"),
+        to: Ty::Named("workspace"),
+        delete: Ty::Opt(&Ty::Bool),
+    } => "ok", r#"{"merge":{"from":"mail","to":"chat"}}"#;
+    OpenProject { path: Ty::Str, register: Ty::Opt(&Ty::U16), terminal: Ty::Opt(&Ty::Bool) }
+        => "ok", r#"{"open_project":{"path":"/home/me/src/dev"}}"#;
+    Goto { register: Ty::U16 } => "ok", r#"{"goto":{"register":1}}"#;
+    GotoMru { n: Ty::Usize } => "ok", r#"{"goto_mru":{"n":2}}"#;
+    Moveto { register: Ty::U16 } => "ok", r#"{"moveto":{"register":1}}"#;
+    Dispatch { raw: Ty::Str } => "ok", r#"{"dispatch":{"raw":"movefocus l"}}"#;
+    HyprGoto { selector: Ty::Str } => "ok", r#"{"hypr_goto":{"selector":"e+1"}}"#;
+    HyprMoveto { selector: Ty::Str } => "ok", r#"{"hypr_moveto":{"selector":"m-1"}}"#;
+    Fetch { register: Ty::U16 } => "ok", r#"{"fetch":{"register":1}}"#;
+    MovetoHere { register: Ty::U16 } => "ok", r#"{"moveto_here":{"register":1}}"#;
+    Prefix { action: PREFIX_ACTIONS } => "ok", r#"{"prefix":{"action":"moveto"}}"#;
+    GotoOrBind { register: Ty::U16, bind: Ty::Opt(&Ty::Bool) }
+        => "ok, or goto_or_bind_response if unbound", r#"{"goto_or_bind":{"register":1,"bind":true}}"#;
+    Read {
+        workspace: Ty::Opt(&Ty::Named("workspace")),
+        stream: Ty::Opt(&Ty::Bool),
+        registers: Ty::Opt(&Ty::Named("register_range")),
+    }
+        => "read_response, or with stream a read_chunk per entry", r#"{"read":{"workspace":null}}"#;
+    Gc => "names of the removed workspaces, string[]", r#""gc""#;
+    Undo => "ok", r#""undo""#;
+    Reload => "ok", r#""reload""#;
+    History { limit: Ty::Opt(&Ty::Usize) } => "record[]", r#"{"history":{"limit":null}}"#;
+    Export => "snapshot", r#""export""#;
+    Import { mode: IMPORT_MODES, state: Ty::Named("snapshot") }
+        => "ok", r#"{"import":{"mode":"merge","state":{"version":1,"workspaces":{},"registers":{}}}}"#;
+    Subscribe
+        => "a snapshot, then an update per change until the connection closes", r#""subscribe""#;
+    Stats => "stats", r#""stats""#;
+    Metrics => "prometheus text exposition format, raw", r#""metrics""#;
+    Logs { lines: Ty::Opt(&Ty::Usize) } => "log lines, raw", r#"{"logs":{"lines":null}}"#;
+    Ping => "\"pong\"", r#""ping""#;
+    Flush => "the hyprland reply to the queued batch, raw", r#""flush""#;
+    Preview => "the queued batch, raw, which is dropped instead of sent", r#""preview""#;
+    Handoff => "internal, only sent between servers", r#""handoff""#;
+    Batch(Ty::Named("request"))
+        => "the responses of all requests in order", r#"{"batch":["ping"]}"#;
+}
+
+/// Shapes of the types the request fields are given in.
+fn types() -> Value {
+    json!({
+        "workspace": "a register as u16, or a workspace name as string",
+        "register_range": {"start": "u16", "end": "u16, included"},
+        "settings": {
+            "monitor": Ty::Opt(&Ty::Str).to_string(),
+            "gaps_in": Ty::Opt(&Ty::U32).to_string(),
+            "gaps_out": Ty::Opt(&Ty::U32).to_string(),
+            "persistent": Ty::Opt(&Ty::Bool).to_string(),
+            "layout": Ty::Opt(&Ty::Str).to_string(),
+            "master_orientation": Ty::Opt(&MASTER_ORIENTATIONS).to_string(),
+            "cwd": Ty::Opt(&Ty::Str).to_string(),
+            "special": Ty::Opt(&Ty::Bool).to_string(),
+            "icon": Ty::Opt(&Ty::Str).to_string(),
+            "label": Ty::Opt(&Ty::Str).to_string(),
+            "context": Ty::Opt(&Ty::Str).to_string(),
+            "urgent": Ty::Opt(&Ty::Bool).to_string(),
+            "windowrules": "string[]?",
+        },
+        "snapshot": {
+            "version": Ty::U32.to_string(),
+            "workspaces": "map of workspace names to settings",
+            "registers": "map of registers to workspace names",
+            "dynamic": format!("map of registers to the {DYNAMIC_RULES} workspace they follow"),
+            "locked": "u16[]",
+            "expiring": "map of registers to when they get unbound, milliseconds since the unix epoch",
+            "context": "string?",
+            "groups": "map of group names to member names",
+            "pinned": "map of workspace names to the monitors they are pinned to",
+            "glyphs": "map of registers to what bars show for them, ignored by import",
+            "urgent": "names of the workspaces hyprland reported urgent, only sent to subscribers",
+            "generation": "u64?, ignored by import",
+        },
+        "read_response": {
+            "workspaces": "map of workspace names to settings",
+            "registers": "map of registers to workspace names",
+            "generation": "u64",
+            "pending_dispatches": "string[]?",
+            "active_workspace": "string?",
+            "active_register": "u16?",
+            "windows": "map of workspace names to window counts, null until counted",
+            "context": "string?",
+            "glyphs": "map of registers to what bars show for them",
+        },
+        "goto_or_bind_response": {
+            "unbound": {"register": "u16"},
+            "bound": {"register": "u16", "name": "string"},
+        },
+        "read_chunk": {
+            "workspace": {"name": "string", "settings": "settings"},
+            "register": {"register": "u16", "name": "string"},
+            "end": {
+                "generation": "u64",
+                "pending_dispatches": "string[]?",
+                "active_workspace": "string?",
+                "active_register": "u16?",
+                "windows": "map of workspace names to window counts?",
+                "context": "string?",
+                "glyphs": "map of registers to what bars show for them",
+            },
+        },
+        "record": {
+            "seq": "u64",
+            "time": "u64, milliseconds since the unix epoch",
+            "event": "the change, an object with the kind of event as its only key",
+        },
+        "stats": {
+            "connections": {"active": "usize", "max": "usize", "rejected": "u64", "reaped": "u64"},
+            "subscribers": "object[]",
+            "usage": "map of workspace names to {gotos: u64, focused_ms: u64}",
+        },
+    })
+}
+
+/// One sample of every [`ServerError`], serialized to describe its fields.
+fn errors() -> Vec<ServerError> {
+    let message = String::new;
+    vec![
+        ServerError::UnknownWorkspace { name: message() },
+        ServerError::RegisterUnbound { register: 0 },
+        ServerError::NameInUse { name: message() },
+        ServerError::Conflict {
+            register: 0,
+            current: None,
+        },
+        ServerError::Stale {
+            expected: 0,
+            current: 0,
+        },
+        ServerError::RegisterLocked { register: 0 },
+        ServerError::HyprIpc { message: message() },
+        ServerError::Protocol { message: message() },
+        ServerError::Rejected { message: message() },
+        ServerError::ReadOnly,
+        ServerError::PermissionDenied { uid: None },
+        ServerError::TooManyConnections { max: 0 },
+        ServerError::Internal { message: message() },
+    ]
+}
+
+/// Describe every request the server accepts, with the fields it takes and what it is
+/// answered with, along with the errors it may fail with.
+pub fn protocol() -> Value {
+    let mut requests = Map::new();
+    for schema in REQUESTS {
+        let request: Request =
+            serde_json::from_str(schema.example).expect("schema examples are valid requests");
+        assert_eq!(
+            variant_of(&request),
+            schema.variant,
+            "schema example of {} is another request",
+            schema.variant
+        );
+
+        let mut description = Map::new();
+        match schema.body {
+            Body::Unit => {}
+            Body::Fields(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, ty)| ((*name).to_owned(), ty.to_string().into()))
+                    .collect();
+                description.insert("fields".to_owned(), Value::Object(fields));
+            }
+            Body::List(ty) => {
+                description.insert("list".to_owned(), ty.to_string().into());
+            }
+        }
+        description.insert("response".to_owned(), schema.response.into());
+        description.insert("mutating".to_owned(), request.is_mutating().into());
+        description.insert("read_only".to_owned(), request.is_read_only().into());
+        description.insert(
+            "example".to_owned(),
+            serde_json::from_str(schema.example).expect("schema examples are json"),
+        );
+        requests.insert(request.kind().to_owned(), Value::Object(description));
+    }
+
+    let errors: Map<_, _> = errors()
+        .into_iter()
+        .map(|error| {
+            let mut fields = match serde_json::to_value(&error) {
+                Ok(Value::Object(fields)) => fields,
+                _ => unreachable!("errors serialize to objects"),
+            };
+            fields.remove("error");
+            let fields = fields.keys().cloned().map(Value::from).collect();
+            (error.kind().to_owned(), Value::Array(fields))
+        })
+        .collect();
+
+    json!({
+        "framing": "newline delimited json, or frames of a `@{len}\\n` header and len bytes, \
+            responses to frames end with an empty one",
+        "requests": requests,
+        "pipelined": {
+            "request": {"id": "u64", "request": "request", "if_generation": "u64?"},
+            "reply": {
+                "id": "u64",
+                "request_id": "u64",
+                "generation": "u64",
+                "ok": "response, or the error fields",
+            },
+        },
+        "types": types(),
+        "errors": errors,
+    })
+}
@@ -1,221 +1,2372 @@
 use crate::{
-    hypr::{Hypr, Workspace as HyprWorkspace},
-    path_builder::PathBuilder,
-    server::types::Request,
-    socket::Socket,
+    config::{Config, DeclareConfig},
+    daemon,
+    hypr::{Compositor, Dispatcher, EventStream, Hypr, HyprEvent, Workspace as HyprWorkspace},
+    logging,
+    path_builder::Paths,
+    server::{
+        audit::AuditLog,
+        clock::Clock,
+        connections::{Access, Connections},
+        handoff::Handoff,
+        hooks::Hook,
+        lock::Lock,
+        log::EventLog,
+        metrics::{Metrics, Timed},
+        subscription::{Fanout, Pending},
+        types::{
+            Bindings, Event, Failure, GotoOrBindResponse, Interner, Prefix, PrefixAction, Priority,
+            ReadChunk, Register, Reply, Request, ServerError, Stats, Tagged, Update, Windows,
+        },
+    },
+    socket::{Framing, InvalidMsg, Socket},
 };
-use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context, Result};
 use std::{
-    collections::{hash_map::Entry, BTreeMap, HashMap},
-    fmt::Write,
-    io::ErrorKind,
-    path::Path,
-    sync::Arc,
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    fs::Permissions,
+    future,
+    io::{self, ErrorKind},
+    mem,
+    ops::RangeInclusive,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 use tokio::{
-    fs::remove_file,
-    net::{unix::SocketAddr, UnixListener},
-    sync::RwLock,
+    fs::{create_dir_all, metadata, remove_file, set_permissions},
+    io::AsyncBufReadExt,
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, RwLock, SemaphorePermit},
+    time,
 };
 use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
-use types::{util::IterMap, ReadResponse, Workspace};
+use types::{
+    util::IterMap, DynamicRule, Focus, ImportMode, ReadResponse, Snapshot, Usage, Workspace,
+    WorkspaceSettings,
+};
 
+mod audit;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+mod clock;
+mod connections;
+mod context;
+mod declarative;
+mod expiry;
+mod gc;
+mod handoff;
+mod hooks;
+mod lock;
+mod log;
+mod metrics;
+mod notify;
+mod pins;
+mod profiles;
+mod settings;
+pub mod simulation;
+pub mod subscription;
 pub mod types;
+mod usage;
+
+tokio::task_local! {
+    /// The generation the pipelined request being handled is conditional on, until its first
+    /// change got committed.
+    static IF_GENERATION: Cell<Option<u64>>;
+}
+
+/// What a read not narrowed to a range of registers lists.
+const ALL_REGISTERS: RangeInclusive<Register> = Register::MIN..=Register::MAX;
 
 #[derive(Debug, Default)]
 pub struct Server {
     inner: RwLock<Inner>,
+    config: Config,
+    declared: RwLock<DeclareConfig>,
+    /// Where the declared state is reloaded from, unset in simulations.
+    config_path: Option<PathBuf>,
+    /// Where the usage counters are saved, unset in simulations.
+    usage_file: Option<PathBuf>,
+    connections: Connections,
+    handoff: Handoff,
+    /// Held from opening the event log until handing off, unset in simulations.
+    lock: Mutex<Option<Lock>>,
+    audit: AuditLog,
+    metrics: Arc<Metrics>,
+    /// Id of the next message, which is logged and sent back with errors and replies.
+    request_ids: AtomicU64,
+    #[cfg(feature = "chaos")]
+    chaos: Arc<chaos::Chaos>,
 }
 
+/// Projection of the [`EventLog`], which is the actual source of truth.
 #[derive(Debug, Default)]
 struct Inner {
-    workspaces: HashMap<Arc<str>, WorkspaceSettings>,
-    registers: BTreeMap<u8, Arc<str>>,
+    log: EventLog,
+    subscribers: Fanout,
+    workspaces: BTreeMap<Arc<str>, WorkspaceSettings>,
+    registers: Bindings,
+    /// Names of the workspaces, which every map keeps a copy of instead of its own.
+    names: Interner,
+    /// Registers following the focus instead of pointing to a fixed workspace.
+    dynamic: BTreeMap<Register, DynamicRule>,
+    /// Registers only a forced request can bind or unbind.
+    locked: BTreeSet<Register>,
+    /// When bindings given a ttl get unbound, in milliseconds since the unix epoch.
+    expiring: BTreeMap<Register, u64>,
+    /// Context full reads are filtered by, if one was switched to.
+    context: Option<Arc<str>>,
+    /// Members of every group, by group name.
+    groups: BTreeMap<Arc<str>, Vec<Arc<str>>>,
+    /// Monitors workspaces get moved back to, by workspace name.
+    pinned: BTreeMap<Arc<str>, Arc<str>>,
+    /// Not part of the log, hyprland reports it again soon enough after a restart.
+    focus: Focus,
+    /// Not part of the log either, it lapses long before a restart is done.
+    prefix: Option<Prefix>,
+    /// Seeded from hyprland when listening to its events, which keep it up to date.
+    windows: Windows,
+    /// Workspaces hyprland reported urgent since they were last focused, not part of the log
+    /// so neither undo nor restarts see them.
+    urgent: BTreeSet<Arc<str>>,
+    /// Not part of the log, it is saved to its own file, see [`Server::USAGE`].
+    usage: Usage,
+}
+
+impl Inner {
+    /// Record `event` in the log and apply it to the projected state.
+    ///
+    /// Fails with [`ServerError::Stale`] if the request committing it is conditional on
+    /// another generation, checked under the same lock the change is made with.
+    async fn commit(&mut self, event: Event) -> Result<()> {
+        let current = self.log.generation();
+        let stale = IF_GENERATION.try_with(|expected| match expected.get() {
+            Some(expected) if expected != current => Some(expected),
+            _ => {
+                // Later changes of the request follow from the first, they don't check again.
+                expected.set(None);
+                None
+            }
+        });
+        if let Ok(Some(expected)) = stale {
+            return Err(ServerError::Stale { expected, current }.into());
+        }
+
+        let record = self.log.append(event).await?;
+        self.subscribers.publish(record);
+
+        let event = record.event.clone();
+        match event {
+            // Undoing an arbitrary event is only exact if the state gets rebuilt from scratch.
+            Event::Reverted { .. } => self.rebuild(),
+            event => self.apply(&event),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Created { name, settings } => {
+                let name = self.names.intern(name);
+                self.workspaces
+                    .entry(name)
+                    .or_insert_with(|| settings.clone());
+            }
+            Event::Bound {
+                name,
+                register,
+                expires,
+            } => {
+                let name = self.workspace(name);
+                self.dynamic.remove(register);
+                self.registers.insert(*register, name);
+                match expires {
+                    Some(expires) => self.expiring.insert(*register, *expires),
+                    None => self.expiring.remove(register),
+                };
+            }
+            Event::BoundDynamic { register, rule } => {
+                self.registers.remove(register);
+                self.expiring.remove(register);
+                self.dynamic.insert(*register, *rule);
+            }
+            Event::Unbound { register } => {
+                self.registers.remove(register);
+                self.expiring.remove(register);
+                self.dynamic.remove(register);
+            }
+            Event::Locked { register } => {
+                self.locked.insert(*register);
+            }
+            Event::Unlocked { register } => {
+                self.locked.remove(register);
+            }
+            Event::GroupCreated { group, members } => {
+                let members = members.iter().map(|name| self.workspace(name)).collect();
+                self.groups.insert(Arc::clone(group), members);
+            }
+            Event::GroupRemoved { group } => {
+                self.groups.remove(group);
+            }
+            Event::ContextSwitched { context } => {
+                self.context = context.clone();
+            }
+            Event::Pinned { name, monitor } => {
+                if let Some(name) = self.names.get(name) {
+                    self.pinned.insert(Arc::clone(name), Arc::clone(monitor));
+                }
+            }
+            Event::Unpinned { name } => {
+                self.pinned.remove(name);
+            }
+            Event::Configured { name, settings } => {
+                if let Some(current) = self.workspaces.get_mut(name) {
+                    *current = settings.clone();
+                }
+            }
+            Event::Removed { name } => {
+                self.workspaces.remove(name);
+                self.names.release(name);
+                self.pinned.remove(name);
+                for members in self.groups.values_mut() {
+                    members.retain(|member| member != name);
+                }
+                self.groups.retain(|_, members| !members.is_empty());
+                self.registers.remove_workspace(name);
+                let registers = &self.registers;
+                self.expiring
+                    .retain(|register, _| registers.contains_key(register));
+            }
+            Event::Imported { mode, state } => {
+                if let ImportMode::Replace = mode {
+                    self.workspaces.clear();
+                    self.names.clear();
+                    self.registers.clear();
+                    self.expiring.clear();
+                    self.dynamic.clear();
+                    self.locked.clear();
+                    self.pinned.clear();
+                    self.groups.clear();
+                    self.context = None;
+                }
+
+                for (name, settings) in &state.workspaces {
+                    let name = self.names.intern(name);
+                    self.workspaces.insert(name, settings.clone());
+                }
+                for (register, name) in &state.registers {
+                    self.apply(&Event::Bound {
+                        name: Arc::clone(name),
+                        register: *register,
+                        expires: state.expiring.get(register).copied(),
+                    });
+                }
+                for (register, rule) in &state.dynamic {
+                    self.apply(&Event::BoundDynamic {
+                        register: *register,
+                        rule: *rule,
+                    });
+                }
+                for (group, members) in &state.groups {
+                    self.apply(&Event::GroupCreated {
+                        group: Arc::clone(group),
+                        members: members.clone(),
+                    });
+                }
+                self.locked.extend(&state.locked);
+                if state.context.is_some() {
+                    self.context = state.context.clone();
+                }
+                for (name, monitor) in &state.pinned {
+                    self.apply(&Event::Pinned {
+                        name: Arc::clone(name),
+                        monitor: Arc::clone(monitor),
+                    });
+                }
+            }
+            Event::Reverted { .. } => {}
+        }
+    }
+
+    /// Whether the workspace `name` with `settings` of its own is urgent, configured so or
+    /// reported by hyprland.
+    fn urgent(&self, name: &str, settings: &WorkspaceSettings) -> Option<bool> {
+        settings
+            .urgent
+            .or(self.urgent.contains(name).then_some(true))
+    }
+
+    /// The shared name of the workspace `name`, which is created without settings unless it
+    /// exists.
+    fn workspace(&mut self, name: &Arc<str>) -> Arc<str> {
+        let name = self.names.intern(name);
+        self.workspaces.entry(Arc::clone(&name)).or_default();
+        name
+    }
+
+    /// The register of the `n`th most recently focused workspace a register points to,
+    /// counting from 1. The lowest register is taken for workspaces bound to several.
+    fn recent_register(&self, n: usize) -> Option<Register> {
+        let mut registers = self
+            .focus
+            .recent()
+            .filter_map(|name| self.registers.of(name).next());
+        registers.nth(n.saturating_sub(1))
+    }
+
+    /// Refuse to change `register` while it is locked, unless the change is `force`d.
+    fn check_unlocked(&self, register: Register, force: bool) -> Result<(), ServerError> {
+        match !force && self.locked.contains(&register) {
+            true => Err(ServerError::RegisterLocked { register }),
+            false => Ok(()),
+        }
+    }
+
+    /// Name of the workspace `register` points to right now, following the focus if dynamic.
+    fn target(&self, register: Register) -> Option<Arc<str>> {
+        match self.dynamic.get(&register) {
+            Some(rule) => self.focus.resolve(*rule).cloned(),
+            None => self.registers.get(&register).cloned(),
+        }
+    }
+
+    /// How hyprland knows the workspace `register` points to right now, if any.
+    fn hypr_target(&self, register: Register) -> Option<(Arc<str>, bool)> {
+        let name = self.target(register)?;
+        let special = self
+            .workspaces
+            .get(&name)
+            .and_then(|settings| settings.special)
+            .unwrap_or(false);
+        Some((name, special))
+    }
+
+    /// The lowest register pointing to the workspace `name`.
+    fn register_of(&self, name: Option<&str>) -> Option<Register> {
+        self.registers.of(name?).next()
+    }
+
+    /// Name of the existing workspace `workspace` refers to.
+    fn resolve(&self, workspace: Workspace) -> Result<Arc<str>, ServerError> {
+        match workspace {
+            Workspace::Workspace(name) => match self.workspaces.get_key_value(name) {
+                Some((name, _)) => Ok(Arc::clone(name)),
+                None => Err(ServerError::UnknownWorkspace { name: name.into() }),
+            },
+            Workspace::Register(register) => self
+                .registers
+                .get(&register)
+                .cloned()
+                .ok_or(ServerError::RegisterUnbound { register }),
+        }
+    }
+
+    /// Name of the workspace a read of `workspace` shows, following dynamic registers to
+    /// wherever they point right now.
+    fn resolve_read(&self, workspace: Workspace) -> Result<Arc<str>, ServerError> {
+        match workspace {
+            Workspace::Register(register) => self
+                .target(register)
+                .ok_or(ServerError::RegisterUnbound { register }),
+            workspace => self.resolve(workspace),
+        }
+    }
+
+    /// Whether a read narrowed to `registers` lists the workspace `name`, any without a range.
+    fn listed(&self, registers: Option<&RangeInclusive<Register>>, name: &str) -> bool {
+        registers.is_none_or(|range| {
+            self.registers
+                .of(name)
+                .any(|register| range.contains(&register))
+        })
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: Snapshot::VERSION,
+            workspaces: self.workspaces.clone(),
+            registers: BTreeMap::clone(&self.registers),
+            dynamic: self.dynamic.clone(),
+            locked: self.locked.clone(),
+            expiring: self.expiring.clone(),
+            context: self.context.clone(),
+            groups: self.groups.clone(),
+            pinned: self.pinned.clone(),
+            glyphs: BTreeMap::new(),
+            urgent: BTreeSet::new(),
+            generation: Some(self.log.generation()),
+        }
+    }
+
+    /// Throw away the projected state and replay every event still in effect.
+    fn rebuild(&mut self) {
+        self.workspaces.clear();
+        self.names.clear();
+        self.registers.clear();
+        self.dynamic.clear();
+        self.locked.clear();
+        self.expiring.clear();
+        self.pinned.clear();
+        self.groups.clear();
+        self.context = None;
+
+        let log = mem::take(&mut self.log);
+        for record in log.effective() {
+            self.apply(&record.event);
+        }
+        self.log = log;
+    }
 }
 
 impl Server {
     pub const SOCKET: &'static str = "ws-mgr.sock";
+    pub const READ_ONLY_SOCKET: &'static str = "ws-mgr-ro.sock";
+    pub const EVENT_LOG: &'static str = "events.jsonl";
+    /// Entries of a streamed read sent per frame.
+    const STREAM_CHUNK: usize = 64;
 
-    #[instrument(name = "socket server", skip(self), err)]
-    pub async fn run(self: Arc<Self>) -> Result<()> {
-        let mut hypr_dir = PathBuilder::hypr_basepath()?;
+    fn new(log: EventLog, config: Config) -> Self {
+        let mut inner = Inner {
+            log,
+            subscribers: Fanout::new(config.subscriptions.clone()),
+            ..Default::default()
+        };
+        inner.rebuild();
 
-        let hypr_path: Arc<Path> = hypr_dir.with_filename(".socket.sock").into();
-        let socket = hypr_dir.with_filename(Self::SOCKET);
-        if let Err(err) = remove_file(socket).await {
-            if err.kind() != ErrorKind::NotFound {
-                return Err(err.into());
-            }
+        Self {
+            inner: RwLock::new(inner),
+            declared: RwLock::new(config.declare.clone()),
+            config_path: None,
+            usage_file: None,
+            connections: Connections::new(config.connections.clone()),
+            handoff: Handoff::default(),
+            lock: Mutex::default(),
+            audit: AuditLog::default(),
+            metrics: Arc::default(),
+            request_ids: AtomicU64::new(1),
+            #[cfg(feature = "chaos")]
+            chaos: Arc::new(chaos::Chaos::from_env()),
+            config,
+        }
+    }
+
+    /// Create a server recovering its state from the event log in the state directory.
+    ///
+    /// Fails if another server claimed the socket, see [`Lock`].
+    pub async fn open(config: Config, paths: &Paths) -> Result<Self> {
+        let lock = Lock::acquire(&Lock::path(&paths.socket()?))?;
+        let state_dir = paths.state_dir()?;
+        create_dir_all(&state_dir).await?;
+
+        let audit = match config.audit {
+            true => AuditLog::open(&state_dir.join(AuditLog::FILE)).await?,
+            false => AuditLog::default(),
+        };
+
+        let usage_file = state_dir.join(Self::USAGE);
+        let mut server = Self {
+            audit,
+            lock: Mutex::new(Some(lock)),
+            config_path: Some(paths.config()?),
+            ..Self::new(
+                EventLog::open(&state_dir.join(Self::EVENT_LOG), Clock::default()).await?,
+                config,
+            )
+        };
+        server.inner.get_mut().usage = Self::load_usage(&usage_file).await;
+        server.usage_file = Some(usage_file);
+        Ok(server)
+    }
+
+    #[instrument(name = "socket server", skip_all, err)]
+    pub async fn run(self: Arc<Self>, paths: &Paths) -> Result<()> {
+        let socket = bind(&paths.socket()?, self.config.connections.mode).await?;
+        self.serve(paths, socket).await
+    }
+
+    /// Accept clients on `socket`, and the read-only socket if enabled, until the server gets
+    /// handed off.
+    async fn serve(self: Arc<Self>, paths: &Paths, mut socket: UnixListener) -> Result<()> {
+        // Only the socket is handed off, the new server binds the read-only one again.
+        let mut read_only = self.bind_read_only(paths).await?;
+        daemon::ready();
+        self.reconcile().await?;
+        tokio::spawn({
+            let server_state = Arc::clone(&self);
+            let config_path = paths.config()?;
+
+            async move { server_state.watch_declared(&config_path).await }
+                .instrument(info_span!("declarative"))
+        });
+
+        // Clients only queue their batches with it, a slow hyprland holds up nothing else.
+        let dispatcher = Dispatcher::spawn(&paths.hypr_dir()?.join(".socket.sock"));
+        self.push_rules(&mut Hypr::dispatched(dispatcher.clone()))
+            .await;
+        self.apply_monitor_profile(&mut Hypr::dispatched(dispatcher.clone()))
+            .await;
+        self.enforce_pins(&mut Hypr::dispatched(dispatcher.clone()))
+            .await;
+
+        let (moved, mut hypr_moved) = mpsc::unbounded_channel();
+        tokio::spawn({
+            let server_state = Arc::clone(&self);
+            let paths = paths.clone();
+            let dispatcher = dispatcher.clone();
+
+            async move { server_state.follow_hypr(paths, dispatcher, moved).await }
+                .instrument(info_span!("events"))
+        });
+
+        tokio::spawn(
+            Arc::clone(&self)
+                .persist_usage()
+                .instrument(info_span!("usage")),
+        );
+        tokio::spawn(
+            Arc::clone(&self)
+                .expire_bindings(dispatcher.clone())
+                .instrument(info_span!("expiry")),
+        );
+
+        if let Some(interval) = self.config.gc_interval {
+            tokio::spawn({
+                let server_state = Arc::clone(&self);
+                let dispatcher = dispatcher.clone();
+
+                async move {
+                    server_state
+                        .collect_garbage(dispatcher, Duration::from_secs(interval))
+                        .await
+                }
+                .instrument(info_span!("gc"))
+            });
         }
-        let socket = UnixListener::bind(socket)?;
 
-        while let Ok((stream, socket)) = socket.accept().await {
+        let mut paths = paths.clone();
+        loop {
+            let (stream, access) = tokio::select! {
+                res = socket.accept() => match res {
+                    Ok((stream, _)) => (stream, Access::Full),
+                    Err(_) => break,
+                },
+                res = accept_if(read_only.as_ref()) => match res {
+                    Ok(stream) => (stream, Access::ReadOnly),
+                    Err(err) => {
+                        warn!(?err, "accepting on the read-only socket failed");
+                        continue;
+                    }
+                },
+                Some(moved) = hypr_moved.recv() => {
+                    match self.move_sockets(&paths, &moved).await {
+                        Ok(Some(sockets)) => (socket, read_only) = sockets,
+                        Ok(None) => {}
+                        Err(err) => warn!(?err, "failed to move the sockets, keeping the old ones"),
+                    }
+                    paths = moved;
+                    continue;
+                }
+                // Clients queue up in the backlog until the new server accepts them.
+                () = self.handoff.requested() => {
+                    self.handoff.listener(&socket)?;
+                    self.handoff.done().await;
+                    break;
+                }
+            };
+
+            let peer = stream.peer_cred();
+            if let Err(err) = &peer {
+                warn!(?err, "can't get the credentials of the client");
+            }
+            let peer = peer.ok();
+            if let Err(err) = self.connections.authorize(peer.as_ref()) {
+                warn!(?peer, "rejecting client, {err}");
+                self.reject(stream, err);
+                continue;
+            }
+
+            let Some(permit) = self.connections.admit() else {
+                warn!("rejecting client, too many connections");
+                let max = self.connections.max();
+                self.reject(stream, ServerError::TooManyConnections { max });
+                continue;
+            };
+
+            let span = info_span!(
+                "client",
+                pid = peer.and_then(|peer| peer.pid()),
+                uid = peer.map(|peer| peer.uid())
+            );
             tokio::spawn({
                 let server_state = Arc::clone(&self);
-                let hypr_path = Arc::clone(&hypr_path);
+                let hypr = Hypr::dispatched(dispatcher.clone());
 
-                async {
+                async move {
+                    let _permit = permit;
                     let res = server_state
-                        .handle_client(Socket::from_unixstream(stream), socket, hypr_path)
+                        .handle_client(Socket::from_unixstream(stream), hypr, access)
                         .await;
                     if let Err(err) = res {
                         error!(?err, "client failed with {err}");
                     }
                 }
-                .instrument(info_span!("client"))
+                .instrument(span)
             });
         }
 
         Ok(())
     }
 
-    pub async fn handle_client(
-        self: Arc<Self>,
-        mut stream: Socket,
-        _: SocketAddr,
-        hypr_path: Arc<Path>,
-    ) -> Result<()> {
-        info!("connected");
+    /// The read-only socket, if enabled.
+    async fn bind_read_only(&self, paths: &Paths) -> Result<Option<UnixListener>> {
+        match self.config.connections.read_only {
+            true => Ok(Some(
+                bind(
+                    &paths.read_only_socket()?,
+                    self.config.connections.read_only_mode,
+                )
+                .await?,
+            )),
+            false => Ok(None),
+        }
+    }
+
+    /// Bind the sockets where clients look for them once hyprland restarted in another
+    /// runtime dir, `None` if they stay where they are since the socket was given.
+    async fn move_sockets(
+        &self,
+        from: &Paths,
+        to: &Paths,
+    ) -> Result<Option<(UnixListener, Option<UnixListener>)>> {
+        let socket = to.socket()?;
+        if socket == from.socket()? {
+            return Ok(None);
+        }
+
+        let lock = Lock::acquire(&Lock::path(&socket))?;
+        let listener = bind(&socket, self.config.connections.mode).await?;
+        let read_only = self.bind_read_only(to).await?;
+        *self.lock.lock().expect("lock poisoned") = Some(lock);
+        // Likely gone along with the old runtime dir already.
+        for old in [from.socket()?, from.read_only_socket()?] {
+            let _ = remove_file(old).await;
+        }
+
+        info!(socket = %socket.display(), "moved the socket after hyprland restarted");
+        Ok(Some((listener, read_only)))
+    }
+
+    /// Answer the client with `err` instead of serving it.
+    fn reject(&self, stream: UnixStream, err: ServerError) {
+        let read_timeout = self.connections.read_timeout();
+        tokio::spawn(async move {
+            let mut stream = Socket::from_unixstream(stream);
+            stream.write_msg(&err)?;
+            stream.flush().await?;
+            // Closing before the client sent its request would fail its write instead.
+            let _ = time::timeout(read_timeout, stream.drain()).await;
+            anyhow::Ok(())
+        });
+    }
 
-        let mut hypr = Hypr::new(&hypr_path);
+    /// Push the settings of every workspace to hyprland, which forgot them if it restarted or
+    /// reloaded its config.
+    async fn push_rules<C: Compositor>(&self, hypr: &mut C) {
+        for (name, settings) in &self.inner.read().await.workspaces {
+            self.push_settings(hypr, name, settings);
+        }
+        if let Err(err) = hypr.flush(None).await {
+            warn!(?err, "failed to push workspace rules");
+        }
+    }
 
+    /// Listen to the events of hyprland, following it through restarts.
+    ///
+    /// Once hyprland closes the event socket, wait for it to come back, possibly in another
+    /// runtime dir, which `moved` is told about. Then send everything to the new sockets and
+    /// push the workspace rules, with the persistent workspaces and monitors, it forgot.
+    async fn follow_hypr(
+        &self,
+        mut paths: Paths,
+        dispatcher: Dispatcher,
+        moved: mpsc::UnboundedSender<Paths>,
+    ) {
         loop {
-            let res = async {
-                debug!("waiting for input");
-                if !stream.fetch_msg().await? {
-                    return Ok(false);
+            let hypr_dir = match paths.hypr_dir() {
+                Ok(hypr_dir) => hypr_dir,
+                Err(err) => {
+                    error!(?err, "no hyprland dir to listen to events in");
+                    return;
                 }
+            };
+            let hypr_path = hypr_dir.join(".socket.sock");
+            if let Err(err) = self.listen_events(|| Hypr::new(&hypr_path)).await {
+                error!(?err, "event listener failed with {err}");
+            }
 
-                if let Err(err) = self.handle_message(&mut stream, &mut hypr).await {
-                    warn!(?err, "error processing message");
+            info!("waiting for hyprland to come back");
+            paths = wait_for_restart(&paths).await;
+            let restarted = paths.hypr_dir().unwrap_or_default();
+            if restarted != hypr_dir {
+                info!(hypr_dir = %restarted.display(), "hyprland restarted in another dir");
+                dispatcher.retarget(&restarted.join(".socket.sock"));
+                let _ = moved.send(paths.clone());
+            }
+            self.push_rules(&mut Hypr::dispatched(dispatcher.clone()))
+                .await;
+            self.apply_monitor_profile(&mut Hypr::dispatched(dispatcher.clone()))
+                .await;
+            self.enforce_pins(&mut Hypr::dispatched(dispatcher.clone()))
+                .await;
+        }
+    }
 
-                    write!(stream, "{}", err)?;
-                    stream.flush().await?;
+    /// Handle the events of the compositor until it closes the stream, with compositors made
+    /// by `compositor` to query and dispatch to.
+    #[instrument(name = "event listener", skip_all, err)]
+    async fn listen_events<C: Compositor>(&self, mut compositor: impl FnMut() -> C) -> Result<()> {
+        let mut events = compositor().subscribe_events().await?;
+        // Events sent in the meantime queue up on the socket, replaying them is harmless.
+        if let Err(err) = self.seed_windows(&mut compositor()).await {
+            warn!(?err, "failed to count the windows of workspaces");
+        }
+        while let Some(event) = events.next_event().await? {
+            self.handle_event(&mut compositor(), event).await;
+        }
+
+        info!("the compositor closed the event stream");
+        Ok(())
+    }
+
+    /// React to `event`, querying `hypr` where the event alone doesn't tell enough.
+    pub async fn handle_event<C: Compositor>(&self, hypr: &mut C, event: HyprEvent) {
+        debug!(?event, "hyprland event");
+        #[cfg(feature = "chaos")]
+        if self.chaos.drop_event() {
+            warn!(?event, "chaos: dropping event");
+            return;
+        }
+
+        if let HyprEvent::Workspace { name } = &event {
+            let mut lock = self.inner.write().await;
+            // Focus history and usage outlive the event, they keep the shared name.
+            let shared = lock
+                .names
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| Arc::clone(name));
+            lock.focus.focused(Arc::clone(&shared));
+            let now = lock.log.now();
+            lock.usage.focused(&shared, now);
+            drop(lock);
+            if let Err(err) = self.set_urgent(name, false).await {
+                warn!(?err, name = &**name, "failed to clear urgency");
+            }
+        }
+        match &event {
+            HyprEvent::OpenWindow { address, workspace }
+            | HyprEvent::MoveWindow { address, workspace } => {
+                self.inner.write().await.windows.opened(address, workspace);
+            }
+            HyprEvent::CloseWindow { address } => {
+                self.inner.write().await.windows.closed(address);
+            }
+            _ => {}
+        }
+        if let HyprEvent::Urgent { address } = &event {
+            if let Err(err) = self.mark_urgent(hypr, address).await {
+                warn!(
+                    ?err,
+                    address = &**address,
+                    "failed to mark urgent workspace"
+                );
+            }
+        }
+        if let HyprEvent::CreateWorkspace { name } = &event {
+            if let Err(err) = self.auto_bind(name).await {
+                warn!(?err, name = &**name, "failed to bind new workspace");
+            }
+        }
+
+        if let HyprEvent::ConfigReloaded = &event {
+            self.push_rules(hypr).await;
+        }
+        if let HyprEvent::MonitorAdded { .. } | HyprEvent::MonitorRemoved { .. } = &event {
+            self.apply_monitor_profile(hypr).await;
+        }
+        // Pins to a monitor that was gone are taken up again once it is back.
+        if let HyprEvent::MonitorAdded { .. } = &event {
+            self.enforce_pins(hypr).await;
+        }
+        if let HyprEvent::MoveWorkspace { name, monitor } = &event {
+            let moved_away = self
+                .inner
+                .read()
+                .await
+                .pinned
+                .get(name)
+                .is_some_and(|pinned| pinned != monitor);
+            if moved_away {
+                self.enforce_pins(hypr).await;
+            }
+        }
+
+        if let HyprEvent::DestroyWorkspace { name } = &event {
+            let registers = {
+                let guard = self.inner.read().await;
+                guard
+                    .registers
+                    .of(name)
+                    .map(|register| register.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+
+            hooks::run(
+                &self.config.hooks,
+                Hook::WorkspaceDestroyed,
+                &[("WS_MGR_WORKSPACE", name), ("WS_MGR_REGISTERS", &registers)],
+            );
+            // The windows asking for attention are gone with it.
+            if let Err(err) = self.set_urgent(name, false).await {
+                warn!(?err, name = &**name, "failed to clear urgency");
+            }
+        }
+    }
+
+    /// The workspace hyprland focuses, asking hyprland if no event told yet.
+    async fn active_workspace<C: Compositor>(&self, hypr: &mut C) -> Option<Arc<str>> {
+        if let Some(current) = self.inner.read().await.focus.current() {
+            return Some(Arc::clone(current));
+        }
+
+        match hypr.active_workspace().await {
+            Ok(active) => {
+                let name: Arc<str> = active.name.into();
+                self.inner.write().await.focus.focused(Arc::clone(&name));
+                Some(name)
+            }
+            Err(err) => {
+                debug!(?err, "can't tell the active workspace");
+                None
+            }
+        }
+    }
+
+    /// Answer a read with a [`ReadChunk`] per entry, sending a frame every [`Self::STREAM_CHUNK`]
+    /// entries so the response never has to be buffered as a whole.
+    ///
+    /// The entries are copied out first, a slow client mustn't hold the state locked.
+    async fn stream_read<C: Compositor>(
+        &self,
+        stream: &mut Socket,
+        hypr: &mut C,
+        workspace: Option<Workspace<'_>>,
+        range: Option<RangeInclusive<Register>>,
+    ) -> Result<(), ServerError> {
+        let pending_dispatches = Some(hypr.queued()).filter(|queued| !queued.is_empty());
+        let active_workspace = self.active_workspace(hypr).await;
+
+        let (workspaces, registers, active_register, windows, context, generation) = {
+            let guard = self.inner.read().await;
+            let name = workspace
+                .map(|workspace| guard.resolve_read(workspace))
+                .transpose()?;
+            // Reading a workspace by name or register shows it whatever its context.
+            let context = guard.context.as_deref();
+            let shown = |workspace: &str| match &name {
+                Some(name) => **name == *workspace,
+                None => guard.workspaces.get(workspace).is_some_and(|settings| {
+                    self.in_context(context, settings) && guard.listed(range.as_ref(), workspace)
+                }),
+            };
+
+            let workspaces: Vec<_> = match &name {
+                // A dynamic register may follow the focus to a workspace that isn't managed.
+                Some(name) => {
+                    let unmanaged = WorkspaceSettings::default();
+                    let settings = guard.workspaces.get(name).unwrap_or(&unmanaged);
+                    vec![ReadChunk::Workspace {
+                        name: Arc::clone(name),
+                        settings: self.current(&guard, name, settings),
+                    }]
                 }
+                None => guard
+                    .workspaces
+                    .iter()
+                    .filter(|(workspace, _)| shown(workspace))
+                    .map(|(name, settings)| ReadChunk::Workspace {
+                        name: Arc::clone(name),
+                        settings: self.current(&guard, name, settings),
+                    })
+                    .collect(),
+            };
+            let registers: Vec<_> = match (workspace, &name) {
+                (Some(Workspace::Register(register)), Some(name)) => vec![ReadChunk::Register {
+                    register,
+                    name: Arc::clone(name),
+                }],
+                _ => guard
+                    .registers
+                    .range(range.clone().unwrap_or(ALL_REGISTERS))
+                    .filter(|(_, register_pointee)| shown(register_pointee))
+                    .map(|(register, name)| ReadChunk::Register {
+                        register: *register,
+                        name: Arc::clone(name),
+                    })
+                    .collect(),
+            };
+            let active_register = guard.register_of(active_workspace.as_deref());
+            let windows = guard
+                .windows
+                .counts(guard.workspaces.keys().filter(|workspace| shown(workspace)));
+            (
+                workspaces,
+                registers,
+                active_register,
+                windows,
+                guard.context.clone(),
+                guard.log.generation(),
+            )
+        };
 
-                Result::<_, anyhow::Error>::Ok(true)
+        for chunk in workspaces
+            .chunks(Self::STREAM_CHUNK)
+            .chain(registers.chunks(Self::STREAM_CHUNK))
+        {
+            for entry in chunk {
+                stream.write_msg(entry)?;
             }
-            .instrument(info_span!("message"))
-            .await;
+            stream.flush().await?;
+        }
+        stream.write_msg(&ReadChunk::End {
+            generation,
+            pending_dispatches,
+            active_workspace,
+            active_register,
+            windows,
+            context,
+            glyphs: self.config.glyphs.clone(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Do what a pending prefix asked for with the `register` of the goto completing it.
+    async fn complete_prefix<C: Compositor>(
+        &self,
+        stream: &mut Socket,
+        hypr: &mut C,
+        action: PrefixAction,
+        register: Register,
+    ) -> Result<(), ServerError> {
+        let active;
+        let request = match action {
+            PrefixAction::Moveto => Request::Moveto { register },
+            PrefixAction::Fetch => Request::Fetch { register },
+            PrefixAction::Unbind => Request::Unbind {
+                register,
+                force: false,
+            },
+            PrefixAction::Bind => {
+                active = self
+                    .active_workspace(hypr)
+                    .await
+                    .ok_or_else(|| ServerError::rejected("no workspace is focused"))?;
+                Request::Bind {
+                    name: &active,
+                    register,
+                    force: false,
+                    ttl: None,
+                }
+            }
+        };
+        Box::pin(self.handle_message(stream, hypr, request)).await
+    }
+
+    /// The focused monitor, if the workspace `name` is open on another one.
+    ///
+    /// A workspace hyprland doesn't have open yet is opened on the focused monitor anyway.
+    async fn focused_monitor_elsewhere<C: Compositor>(
+        &self,
+        hypr: &mut C,
+        name: &str,
+    ) -> Result<Option<String>, ServerError> {
+        let workspaces = hypr.list_workspaces().await.map_err(ServerError::hypr)?;
+        let monitor_of = |name: &str| {
+            workspaces
+                .iter()
+                .find(|workspace| workspace.name == name)
+                .map(|workspace| &*workspace.monitor)
+        };
+        let active = self.active_workspace(hypr).await;
+        let monitor = active
+            .as_deref()
+            .and_then(monitor_of)
+            .ok_or_else(|| ServerError::rejected("the focused monitor isn't known"))?;
+
+        let elsewhere = monitor_of(name).is_some_and(|current| current != monitor);
+        Ok(elsewhere.then(|| monitor.to_owned()))
+    }
+
+    /// Refuse to bind the workspace `name` unless it is managed, open in hyprland or focused,
+    /// so registers don't end up pointing nowhere by a typo.
+    async fn check_exists<C: Compositor>(
+        &self,
+        hypr: &mut C,
+        name: &str,
+    ) -> Result<(), ServerError> {
+        if self.inner.read().await.names.get(name).is_some() {
+            return Ok(());
+        }
+        let workspaces = hypr.list_workspaces().await.map_err(ServerError::hypr)?;
+        if workspaces.iter().any(|workspace| workspace.name == name)
+            || self.active_workspace(hypr).await.as_deref() == Some(name)
+        {
+            return Ok(());
+        }
+        Err(ServerError::UnknownWorkspace {
+            name: name.to_owned(),
+        })
+    }
+
+    /// Bind `register` to the workspace `name`, creating it as needed.
+    ///
+    /// With `expected` set, only if the register points to that workspace right now, or to
+    /// none if it is `Some(None)`, under the same lock the binding is made with.
+    async fn bind_register<C: Compositor>(
+        &self,
+        hypr: &mut C,
+        name: &str,
+        register: Register,
+        force: bool,
+        ttl: Option<u32>,
+        expected: Option<Option<&str>>,
+    ) -> Result<(), ServerError> {
+        self.declared.read().await.check_bind(register, name)?;
+
+        let mut lock = self.inner.write().await;
+        lock.check_unlocked(register, force)?;
+        if let Some(expected) = expected {
+            let current = lock.target(register);
+            if current.as_deref() != expected {
+                return Err(ServerError::Conflict {
+                    register,
+                    current: current.map(|name| name.to_string()),
+                });
+            }
+        }
+
+        let (name, created) = match lock.names.get(name) {
+            Some(name) => (Arc::clone(name), false),
+            None => (Arc::from(name), true),
+        };
+
+        let expires = ttl.map(|ttl| lock.log.now() + u64::from(ttl) * 1000);
+        lock.commit(Event::Bound {
+            name: Arc::clone(&name),
+            register,
+            expires,
+        })
+        .await?;
+        drop(lock);
+
+        if created {
+            self.push_settings(hypr, &name, &WorkspaceSettings::default());
+            hooks::run(
+                &self.config.hooks,
+                Hook::Create,
+                &[("WS_MGR_WORKSPACE", &name)],
+            );
+        }
+        hooks::run(
+            &self.config.hooks,
+            Hook::Bind,
+            &[("WS_MGR_WORKSPACE", &name), ("WS_MGR_REGISTER", &register)],
+        );
+        if self.config.notifications {
+            notify::send(format!("register {register} → {name}"));
+        }
+        Ok(())
+    }
+
+    /// Count the windows of every workspace from scratch.
+    pub async fn seed_windows<C: Compositor>(&self, hypr: &mut C) -> Result<()> {
+        let clients = hypr.list_clients().await?;
+        self.inner.write().await.windows.seed(
+            clients
+                .iter()
+                .map(|client| (&*client.address, &*client.workspace.name)),
+        );
+        Ok(())
+    }
+
+    /// Mark the managed workspace holding the window at `address` as urgent.
+    async fn mark_urgent<C: Compositor>(&self, hypr: &mut C, address: &str) -> Result<()> {
+        let clients = hypr.list_clients().await?;
+        let Some(client) = clients
+            .iter()
+            .find(|client| client.address.trim_start_matches("0x") == address)
+        else {
+            debug!(address, "urgent window isn't known to hyprland");
+            return Ok(());
+        };
+        self.set_urgent(&client.workspace.name, true).await
+    }
+
+    /// Set or clear the urgency hyprland reported for the workspace `name`, if it is managed,
+    /// sending subscribers a snapshot if that changes anything.
+    async fn set_urgent(&self, name: &str, urgent: bool) -> Result<()> {
+        let mut lock = self.inner.write().await;
+        let Some(name) = lock.names.get(name).cloned() else {
+            return Ok(());
+        };
+        let changed = match urgent {
+            true => lock.urgent.insert(name),
+            false => lock.urgent.remove(&name),
+        };
+        if changed {
+            lock.subscribers.refresh();
+        }
+        Ok(())
+    }
+
+    /// Bind the workspace `name` hyprland just created to the registers of matching
+    /// [`AutoBindRule`](crate::config::AutoBindRule)s that are still free.
+    async fn auto_bind(&self, name: &Arc<str>) -> Result<()> {
+        let rules = self
+            .config
+            .auto_bind
+            .iter()
+            .filter(|rule| rule.name.is_match(name));
+
+        let declared = self.declared.read().await;
+        let mut lock = self.inner.write().await;
+        for rule in rules {
+            let register = rule.register;
+            if lock.registers.contains_key(&register)
+                || lock.dynamic.contains_key(&register)
+                || declared.check_bind(register, name).is_err()
+            {
+                continue;
+            }
+            info!(register, name = &**name, "binding new workspace");
+            lock.commit(Event::Bound {
+                name: Arc::clone(name),
+                register,
+                expires: None,
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
 
-            if !res? {
+    pub async fn handle_client<C: Compositor>(
+        self: Arc<Self>,
+        mut stream: Socket,
+        hypr: C,
+        access: Access,
+    ) -> Result<()> {
+        info!(?access, "connected");
+        stream.set_max_msg(self.config.connections.max_message);
+        let peer = stream.inner.get_ref().peer_cred().ok();
+        let hypr = Timed::new(hypr, Arc::clone(&self.metrics));
+        #[cfg(feature = "chaos")]
+        let hypr = chaos::Faulty::new(hypr, Arc::clone(&self.chaos));
+        let mut hypr = hypr;
+        let mut pipelined = false;
+
+        loop {
+            debug!("waiting for input");
+            let Some(res) = self.connections.idle(stream.inner.fill_buf()).await else {
+                info!("reaping idle client");
+                break;
+            };
+            res?;
+            let Some(fetched) = self.connections.read(stream.fetch_msg()).await else {
+                info!("reaping client taking too long to send its request");
                 break;
+            };
+            let fetched = match fetched {
+                Ok(fetched) => fetched,
+                Err(err) => match err.downcast::<InvalidMsg>() {
+                    // Whatever follows can't be told apart from the rest of the junk.
+                    Ok(invalid) => {
+                        warn!(err = %invalid, "disconnecting client sending junk");
+                        let err = ServerError::protocol(invalid.into());
+                        self.metrics.error(&err);
+                        let request_id = self.request_ids.fetch_add(1, Ordering::Relaxed);
+                        stream.write_msg(&Failure::new(err, request_id))?;
+                        stream.flush().await?;
+                        // Closing with the junk unread would reset the connection, losing the
+                        // error along with it.
+                        let _ = self.connections.read(stream.drain()).await;
+                        break;
+                    }
+                    Err(err) => return Err(err),
+                },
+            };
+            if !fetched {
+                break;
+            }
+
+            let request_id = self.request_ids.fetch_add(1, Ordering::Relaxed);
+            async {
+                let msg = mem::take(&mut stream.read_buf);
+                if let Ok(tagged) = serde_json::from_slice::<Tagged>(&msg) {
+                    pipelined = true;
+                    self.metrics.request(&tagged.request);
+                    let audited = self.audit.capture(&tagged.request);
+                    let reply = match access.check(&tagged.request) {
+                        Ok(()) => {
+                            let served = self.serving(&tagged.request).await;
+                            let reply = self
+                                .handle_tagged(&mut stream, &mut hypr, request_id, tagged)
+                                .await;
+                            served.done(&self.metrics);
+                            reply
+                        }
+                        Err(error) => Reply::Err {
+                            id: tagged.id,
+                            request_id,
+                            generation: self.inner.read().await.log.generation(),
+                            error,
+                        },
+                    };
+                    if let Some(error) = reply.error() {
+                        self.metrics.error(error);
+                    }
+                    if let Some(request) = audited {
+                        self.audit
+                            .record(peer.as_ref(), request, reply.error())
+                            .await;
+                    }
+                    stream.write_msg(&reply)?;
+                } else {
+                    let res = match serde_json::from_slice(&msg) {
+                        Ok(request) => {
+                            self.metrics.request(&request);
+                            let audited = self.audit.capture(&request);
+                            let res = match access.check(&request) {
+                                Ok(()) => {
+                                    let served = self.serving(&request).await;
+                                    let res =
+                                        self.handle_message(&mut stream, &mut hypr, request).await;
+                                    served.done(&self.metrics);
+                                    res
+                                }
+                                err => err,
+                            };
+                            if let Some(request) = audited {
+                                self.audit
+                                    .record(peer.as_ref(), request, res.as_ref().err())
+                                    .await;
+                            }
+                            res
+                        }
+                        Err(err) => Err(ServerError::protocol(err.into())),
+                    };
+                    if let Err(err) = res {
+                        warn!(?err, "error processing message");
+                        self.metrics.error(&err);
+
+                        stream.write_msg(&Failure::new(err, request_id))?;
+                        stream.flush().await?;
+                    }
+                }
+                stream.read_buf = msg;
+
+                // Pipelining clients don't flush, so do it once all queued requests are handled,
+                // or after every request if configured to. Requests already waiting still get to
+                // join the batch, so a preview sent along with a dispatch sees it.
+                let hypr_config = &self.config.hypr;
+                let caught_up =
+                    (pipelined || hypr_config.auto_flush) && !stream.has_pending().await;
+                if caught_up || hypr_config.max_batch < hypr.queued_len() {
+                    if let Err(err) = flush_hypr(&mut hypr, &mut stream).await {
+                        warn!(?err, "error sending batch");
+                        let err = ServerError::hypr(err);
+                        self.metrics.error(&err);
+                        stream.write_msg(&Failure::new(err, request_id))?;
+                    }
+                    stream.flush().await?;
+                }
+
+                anyhow::Ok(())
             }
+            .instrument(info_span!("message", request_id))
+            .await?;
         }
 
-        hypr.flush(Some(&mut stream.write_buf)).await?;
+        // Responses are still read after the client shut down its side, so a batch failing
+        // now is reported like any other error instead of dropping what is still buffered.
+        if let Err(err) = flush_hypr(&mut hypr, &mut stream).await {
+            warn!(?err, "error sending batch");
+            let err = ServerError::hypr(err);
+            self.metrics.error(&err);
+            let request_id = self.request_ids.fetch_add(1, Ordering::Relaxed);
+            stream.write_msg(&Failure::new(err, request_id))?;
+        }
         stream.flush().await?;
+        // Subscribers leave without waiting for the end, so not getting to send it is fine.
+        stream.write_end();
+        let _ = stream.flush().await;
 
         info!("disconnected");
 
         Ok(())
     }
 
-    pub async fn handle_message<'a>(&self, stream: &'a mut Socket, hypr: &mut Hypr) -> Result<()> {
-        let request: Request = stream.read_msg()?;
+    /// The state as exported, with the glyphs bars show for registers.
+    fn snapshot(&self, inner: &Inner) -> Snapshot {
+        Snapshot {
+            glyphs: self.config.glyphs.clone(),
+            ..inner.snapshot()
+        }
+    }
+
+    /// The state as sent to subscribers, which also tells the workspaces hyprland reported
+    /// urgent.
+    fn subscribed_snapshot(&self, inner: &Inner) -> Snapshot {
+        Snapshot {
+            urgent: inner.urgent.clone(),
+            ..self.snapshot(inner)
+        }
+    }
+
+    /// Wait until `request` may be served according to its [`Priority`].
+    async fn serving(&self, request: &Request<'_>) -> Serving<'_> {
+        let priority = request.priority();
+        Serving {
+            priority,
+            // Subscriptions and handoffs last until the connection closes, timing them says
+            // nothing about how quickly requests are served.
+            start: (!matches!(request, Request::Subscribe | Request::Handoff)).then(Instant::now),
+            _permit: self.connections.serve(priority).await,
+        }
+    }
+
+    /// Handle a pipelined request, capturing whatever it responds with in the [`Reply`].
+    async fn handle_tagged<C: Compositor>(
+        &self,
+        stream: &mut Socket,
+        hypr: &mut C,
+        request_id: u64,
+        Tagged {
+            id,
+            request,
+            if_generation,
+        }: Tagged<'_>,
+    ) -> Reply {
+        let current = self.inner.read().await.log.generation();
+        let res = match if_generation.filter(|_| request.is_mutating()) {
+            Some(expected) if expected != current => Err(ServerError::Stale { expected, current }),
+            // Checked again by the first change it commits, the state may move on meanwhile.
+            expected => {
+                IF_GENERATION
+                    .scope(
+                        Cell::new(expected),
+                        self.handle_pipelined(stream, hypr, request),
+                    )
+                    .await
+            }
+        };
+
+        let generation = self.inner.read().await.log.generation();
+        match res {
+            Ok(ok) => Reply::Ok {
+                id,
+                request_id,
+                generation,
+                ok,
+            },
+            Err(error) => {
+                warn!(?error, id, "error processing message");
+                Reply::Err {
+                    id,
+                    request_id,
+                    generation,
+                    error,
+                }
+            }
+        }
+    }
+
+    /// What the pipelined `request` would have responded with on its own.
+    async fn handle_pipelined<C: Compositor>(
+        &self,
+        stream: &mut Socket,
+        hypr: &mut C,
+        request: Request<'_>,
+    ) -> Result<serde_json::Value, ServerError> {
+        match request {
+            Request::Subscribe | Request::Handoff => Err(ServerError::Protocol {
+                message: "subscriptions and handoffs can't be pipelined".to_owned(),
+            }),
+            // Flushing the stream would send the response before it is wrapped.
+            Request::Flush => {
+                let mut reply = Vec::new();
+                match hypr.flush(Some(&mut reply)).await {
+                    Ok(()) => Ok(String::from_utf8_lossy(&reply).into()),
+                    Err(err) => Err(ServerError::hypr(err)),
+                }
+            }
+            request => {
+                let framing = stream.framing();
+                let mark = stream.write_buf.len();
+                stream.set_framing(Framing::Lines);
+                let res = self.handle_message(stream, hypr, request).await;
+                let response = stream.write_buf.split_off(mark);
+                stream.set_framing(framing);
+
+                res.map(|()| match response.trim_ascii() {
+                    [] => serde_json::Value::Null,
+                    response => serde_json::from_slice(response)
+                        .unwrap_or_else(|_| String::from_utf8_lossy(response).into_owned().into()),
+                })
+            }
+        }
+    }
+
+    /// Handle a single request.
+    ///
+    /// The state is only ever locked for in-memory work and appending to the log, never across
+    /// talking to hyprland or spawning hooks, so a slow hyprland only stalls its own client.
+    pub async fn handle_message<C: Compositor>(
+        &self,
+        stream: &mut Socket,
+        hypr: &mut C,
+        request: Request<'_>,
+    ) -> Result<(), ServerError> {
         debug!(?request, "input");
         match request {
             Request::Create { name } => {
                 let mut lock = self.inner.write().await;
-                match lock.workspaces.entry(name.into()) {
-                    Entry::Vacant(vacant) => vacant.insert(WorkspaceSettings::default()),
-                    Entry::Occupied(_) => return Err(anyhow!("name already in use")),
+                if lock.workspaces.contains_key(name) {
+                    return Err(ServerError::NameInUse { name: name.into() });
+                }
+                lock.commit(Event::Created {
+                    name: name.into(),
+                    settings: Default::default(),
+                })
+                .await?;
+                drop(lock);
+
+                self.push_settings(hypr, name, &WorkspaceSettings::default());
+                hooks::run(
+                    &self.config.hooks,
+                    Hook::Create,
+                    &[("WS_MGR_WORKSPACE", &name)],
+                );
+            }
+            Request::CreateFrom { name, template } => {
+                let template = self.config.templates.get(template).ok_or_else(|| {
+                    ServerError::rejected(format!("there is no template named {template}"))
+                })?;
+                // Hyprland splits batches on `;`, so a command containing one can't be sent.
+                if let Some(command) = template.exec.iter().find(|command| command.contains(';')) {
+                    return Err(ServerError::rejected(format!(
+                        "template command {command:?} contains a `;`"
+                    )));
+                }
+                if let Some(rule) = template.settings.unsendable_rule() {
+                    return Err(ServerError::rejected(format!(
+                        "template window rule {rule:?} contains a `;`"
+                    )));
+                }
+
+                let mut lock = self.inner.write().await;
+                if lock.workspaces.contains_key(name) {
+                    return Err(ServerError::NameInUse { name: name.into() });
+                }
+                lock.commit(Event::Created {
+                    name: name.into(),
+                    settings: template.settings.clone(),
+                })
+                .await?;
+                drop(lock);
+
+                self.push_settings(hypr, name, &template.settings);
+                for command in &template.exec {
+                    hypr.exec(HyprWorkspace::Name(name), command);
+                }
+
+                hooks::run(
+                    &self.config.hooks,
+                    Hook::Create,
+                    &[("WS_MGR_WORKSPACE", &name)],
+                );
+            }
+            Request::Bind {
+                name,
+                register,
+                force,
+                ttl,
+            } => {
+                if !force {
+                    self.check_exists(hypr, name).await?;
+                }
+                self.bind_register(hypr, name, register, force, ttl, None)
+                    .await?
+            }
+            Request::BindIf {
+                name,
+                register,
+                expected,
+                force,
+            } => {
+                if !force {
+                    self.check_exists(hypr, name).await?;
+                }
+                self.bind_register(hypr, name, register, force, None, Some(expected))
+                    .await?
+            }
+            Request::BindDynamic {
+                register,
+                rule,
+                force,
+            } => {
+                self.declared.read().await.check_bind_dynamic(register)?;
+
+                let mut lock = self.inner.write().await;
+                lock.check_unlocked(register, force)?;
+                if lock.dynamic.get(&register) == Some(&rule) {
+                    return Ok(());
+                }
+                lock.commit(Event::BoundDynamic { register, rule }).await?;
+            }
+            Request::Unbind { register, force } => {
+                self.declared.read().await.check_unbind(register)?;
+
+                let mut lock = self.inner.write().await;
+                lock.check_unlocked(register, force)?;
+                if !lock.registers.contains_key(&register) && !lock.dynamic.contains_key(&register)
+                {
+                    return Ok(());
+                }
+                lock.commit(Event::Unbound { register }).await?;
+                drop(lock);
+
+                hooks::run(
+                    &self.config.hooks,
+                    Hook::Unbind,
+                    &[("WS_MGR_REGISTER", &register)],
+                );
+                if self.config.notifications {
+                    notify::send(format!("register {register} unbound"));
+                }
+            }
+            Request::Lock { register } => {
+                let mut lock = self.inner.write().await;
+                if lock.locked.contains(&register) {
+                    return Ok(());
+                }
+                lock.commit(Event::Locked { register }).await?;
+            }
+            Request::Unlock { register } => {
+                let mut lock = self.inner.write().await;
+                if !lock.locked.contains(&register) {
+                    return Ok(());
+                }
+                lock.commit(Event::Unlocked { register }).await?;
+            }
+            Request::GroupCreate { group, members } => {
+                if members.is_empty() {
+                    return Err(ServerError::rejected("a group needs members"));
+                }
+                let mut unique: Vec<Arc<str>> = Vec::with_capacity(members.len());
+                for member in members {
+                    if !unique.iter().any(|name| **name == *member) {
+                        unique.push(member.into());
+                    }
+                }
+
+                let mut lock = self.inner.write().await;
+                if lock.groups.get(group) == Some(&unique) {
+                    return Ok(());
+                }
+                lock.commit(Event::GroupCreated {
+                    group: group.into(),
+                    members: unique,
+                })
+                .await?;
+            }
+            Request::GroupRemove { group } => {
+                let mut lock = self.inner.write().await;
+                let Some((group, _)) = lock.groups.get_key_value(group) else {
+                    return Err(unknown_group(group));
                 };
+                let group = Arc::clone(group);
+                lock.commit(Event::GroupRemoved { group }).await?;
+            }
+            Request::GroupGoto { group } => {
+                let active = self.active_workspace(hypr).await;
+                let guard = self.inner.read().await;
+                let members = guard
+                    .groups
+                    .get(group)
+                    .ok_or_else(|| unknown_group(group))?;
+                let start = members
+                    .iter()
+                    .position(|name| Some(name) == active.as_ref())
+                    .map_or(0, |i| i + 1);
+                let context = guard.context.as_deref();
+                let next = (0..members.len())
+                    .map(|offset| &members[(start + offset) % members.len()])
+                    .find(|name| {
+                        guard
+                            .workspaces
+                            .get(*name)
+                            .is_some_and(|settings| self.in_context(context, settings))
+                    })
+                    .ok_or_else(|| {
+                        ServerError::rejected(format!(
+                            "no member of group {group:?} is in the context"
+                        ))
+                    })?;
+                let special = guard
+                    .workspaces
+                    .get(next)
+                    .is_some_and(|settings| settings.special == Some(true));
+                hypr.go_to(hypr_workspace(next, special));
             }
-            Request::Bind { name, register } => {
+            Request::GroupRead { group: None } => {
+                stream.write_msg(&self.inner.read().await.groups)?;
+            }
+            Request::GroupRead { group: Some(group) } => {
+                let guard = self.inner.read().await;
+                let (group, members) = guard
+                    .groups
+                    .get_key_value(group)
+                    .ok_or_else(|| unknown_group(group))?;
+                stream.write_msg(&IterMap::new([(group, members)]))?;
+            }
+            Request::SwitchContext { context } => {
+                let context: Option<Arc<str>> = context.map(Arc::from);
                 let mut lock = self.inner.write().await;
-                let name = match lock.workspaces.get_key_value(name) {
-                    Some((name, _)) => Arc::clone(name),
+                if lock.context == context {
+                    return Ok(());
+                }
+                lock.commit(Event::ContextSwitched { context }).await?;
+            }
+            Request::PinMonitor { register, monitor } => {
+                let target = self.inner.read().await.hypr_target(register);
+                let (name, special) = target.ok_or(ServerError::RegisterUnbound { register })?;
+                if special {
+                    return Err(ServerError::rejected(
+                        "special workspaces show on the focused monitor",
+                    ));
+                }
+                let monitor: Arc<str> = match monitor {
+                    Some(monitor) => monitor.into(),
                     None => {
-                        let name = Arc::from(name);
-                        lock.workspaces
-                            .insert(Arc::clone(&name), WorkspaceSettings::default());
+                        let active = hypr.active_workspace().await.map_err(ServerError::hypr)?;
+                        active.monitor.into()
+                    }
+                };
+
+                let mut lock = self.inner.write().await;
+                if lock.pinned.get(&name) != Some(&monitor) {
+                    lock.commit(Event::Pinned { name, monitor }).await?;
+                }
+                drop(lock);
+                self.queue_pinned_moves(hypr)
+                    .await
+                    .map_err(ServerError::hypr)?;
+            }
+            Request::UnpinMonitor { register } => {
+                let mut lock = self.inner.write().await;
+                let name = lock
+                    .target(register)
+                    .ok_or(ServerError::RegisterUnbound { register })?;
+                if lock.pinned.contains_key(&name) {
+                    lock.commit(Event::Unpinned { name }).await?;
+                }
+            }
+            Request::Configure { name, settings } => {
+                if let Some(rule) = settings.unsendable_rule() {
+                    return Err(ServerError::rejected(format!(
+                        "window rule {rule:?} contains a `;`"
+                    )));
+                }
+
+                let mut lock = self.inner.write().await;
+                let Some((name, _)) = lock.workspaces.get_key_value(name) else {
+                    return Err(ServerError::UnknownWorkspace { name: name.into() });
+                };
+                let name = Arc::clone(name);
+                lock.commit(Event::Configured {
+                    name: Arc::clone(&name),
+                    settings: settings.clone(),
+                })
+                .await?;
+                drop(lock);
+
+                // Hyprland can't drop a rule without reloading its config, so cleared settings
+                // only take effect on the next reload.
+                self.push_settings(hypr, &name, &settings);
+            }
+            Request::Merge { from, to, delete } => {
+                let (from, to) = {
+                    let guard = self.inner.read().await;
+                    (guard.resolve(from)?, guard.resolve(to)?)
+                };
+                if from == to {
+                    return Err(ServerError::rejected(format!(
+                        "can't merge {from} into itself"
+                    )));
+                }
+                if delete {
+                    self.declared.read().await.check_remove(&from)?;
+                }
+
+                let clients = hypr.clients_on(&from).await.map_err(ServerError::hypr)?;
+                for client in &clients {
+                    hypr.move_window(HyprWorkspace::Name(&to), &client.address);
+                }
+
+                if delete {
+                    let mut lock = self.inner.write().await;
+                    if lock.workspaces.contains_key(&from) {
+                        lock.commit(Event::Removed { name: from }).await?;
+                    }
+                }
+            }
+            Request::Gc => {
+                let removed = self.gc(hypr).await?;
+                stream.write_msg(&removed)?;
+            }
+            Request::Undo => {
+                let declared = self.declared.read().await;
+                let mut lock = self.inner.write().await;
+                let record = lock
+                    .log
+                    .last_revertible()
+                    .ok_or_else(|| ServerError::rejected("nothing to undo"))?;
+                declared.check_undo(&record.event)?;
+                let seq = record.seq;
+
+                lock.commit(Event::Reverted { seq }).await?;
+                drop((lock, declared));
+
+                self.reconcile().await?;
+            }
+            Request::Reload => self.reload().await?,
+            Request::History { limit } => {
+                let guard = self.inner.read().await;
+                let records = guard.log.records();
+                let start = limit.map_or(0, |limit| records.len().saturating_sub(limit));
+
+                stream.write_msg(&records[start..])?;
+            }
+            Request::Export => {
+                let guard = self.inner.read().await;
+                stream.write_msg(&self.snapshot(&guard))?;
+            }
+            Request::Import { mode, state } => {
+                state.check_version().map_err(ServerError::protocol)?;
+
+                let mut lock = self.inner.write().await;
+                lock.commit(Event::Imported { mode, state }).await?;
+                drop(lock);
+
+                self.reconcile().await?;
+            }
+            Request::OpenProject {
+                path,
+                register,
+                terminal,
+            } => {
+                let projects = &self.config.projects;
+                let name = projects.workspace_name(Path::new(path)).ok_or_else(|| {
+                    ServerError::rejected(format!("{path} has no name to name a workspace after"))
+                })?;
+                let terminal = match terminal {
+                    true => Some(projects.terminal.as_deref().ok_or_else(|| {
+                        ServerError::rejected("there is no terminal configured for projects")
+                    })?),
+                    false => None,
+                };
+                if terminal.is_some() && path.contains(';') {
+                    return Err(ServerError::rejected(format!(
+                        "can't open a terminal in {path}, it contains a `;`"
+                    )));
+                }
+                if let Some(register) = register {
+                    self.declared.read().await.check_bind(register, &name)?;
+                }
+
+                let mut lock = self.inner.write().await;
+                let existing = lock
+                    .workspaces
+                    .get_key_value(name.as_str())
+                    .map(|(name, settings)| (Arc::clone(name), settings.clone()));
+                let created = existing.is_none();
+                let name = match existing {
+                    Some((name, settings)) => {
+                        if settings.cwd.as_deref() != Some(path) {
+                            let settings = WorkspaceSettings {
+                                cwd: Some(path.to_owned()),
+                                ..settings
+                            };
+                            lock.commit(Event::Configured {
+                                name: Arc::clone(&name),
+                                settings,
+                            })
+                            .await?;
+                        }
+                        name
+                    }
+                    None => {
+                        let name: Arc<str> = name.into();
+                        lock.commit(Event::Created {
+                            name: Arc::clone(&name),
+                            settings: WorkspaceSettings {
+                                cwd: Some(path.to_owned()),
+                                ..Default::default()
+                            },
+                        })
+                        .await?;
                         name
                     }
                 };
+                if let Some(register) = register {
+                    if lock.registers.get(&register) != Some(&name) {
+                        lock.commit(Event::Bound {
+                            name: Arc::clone(&name),
+                            register,
+                            expires: None,
+                        })
+                        .await?;
+                    }
+                }
+                drop(lock);
 
-                lock.registers.insert(register, name);
+                hypr.go_to(HyprWorkspace::Name(&name));
+                if let Some(terminal) = terminal {
+                    hypr.exec(
+                        HyprWorkspace::Name(&name),
+                        &format!("cd {} && {terminal}", shell_quote(path)),
+                    );
+                }
+
+                if created {
+                    hooks::run(
+                        &self.config.hooks,
+                        Hook::Create,
+                        &[("WS_MGR_WORKSPACE", &name)],
+                    );
+                }
             }
-            Request::Unbind { register } => {
+            Request::Prefix { action } => {
+                let timeout = self
+                    .config
+                    .prefix_timeout
+                    .unwrap_or(Prefix::DEFAULT_TIMEOUT);
                 let mut lock = self.inner.write().await;
-                lock.registers.remove(&register);
+                let expires = lock.log.now() + timeout;
+                lock.prefix = Some(Prefix { action, expires });
             }
             Request::Goto { register } => {
-                let lock = self.inner.read().await;
-                let name = lock.registers.get(&register).ok_or_else(|| {
-                    anyhow!("register {register} does not point to any workspace")
+                let prefix = {
+                    let mut lock = self.inner.write().await;
+                    let now = lock.log.now();
+                    lock.prefix.take().filter(|prefix| now < prefix.expires)
+                };
+                if let Some(prefix) = prefix {
+                    return self
+                        .complete_prefix(stream, hypr, prefix.action, register)
+                        .await;
+                }
+
+                let target = self.inner.read().await.hypr_target(register);
+                let Some((name, special)) = target else {
+                    if self.config.notifications {
+                        notify::send(format!("register {register} is unbound"));
+                    }
+                    return Err(ServerError::RegisterUnbound { register });
+                };
+
+                hypr.go_to(hypr_workspace(&name, special));
+                self.inner.write().await.usage.went_to(&name);
+
+                hooks::run(
+                    &self.config.hooks,
+                    Hook::Goto,
+                    &[("WS_MGR_WORKSPACE", &name), ("WS_MGR_REGISTER", &register)],
+                );
+            }
+            Request::GotoMru { n } => {
+                if n == 0 {
+                    return Err(ServerError::rejected(
+                        "n counts from 1, which is the focused workspace",
+                    ));
+                }
+                let register = self.inner.read().await.recent_register(n);
+                let register = register.ok_or_else(|| {
+                    ServerError::rejected(format!(
+                        "fewer than {n} workspaces with a register were focused"
+                    ))
                 })?;
+                return Box::pin(self.handle_message(stream, hypr, Request::Goto { register }))
+                    .await;
+            }
+            Request::Dispatch { raw } => {
+                // Hyprland splits batches on `;`, a dispatcher containing one would run more.
+                if raw.trim().is_empty() || raw.contains(';') {
+                    return Err(ServerError::rejected(format!(
+                        "{raw:?} isn't a single dispatcher"
+                    )));
+                }
+                hypr.dispatch(raw.trim());
+            }
+            Request::HyprGoto { selector } => hypr.go_to(hypr_selector(selector)?),
+            Request::HyprMoveto { selector } => hypr.move_to(hypr_selector(selector)?),
+            Request::Fetch { register } => {
+                let target = self.inner.read().await.hypr_target(register);
+                let (name, special) = target.ok_or(ServerError::RegisterUnbound { register })?;
+                // Special workspaces always open on the focused monitor.
+                if special {
+                    hypr.go_to(hypr_workspace(&name, special));
+                    return Ok(());
+                }
+
+                if let Some(monitor) = self.focused_monitor_elsewhere(hypr, &name).await? {
+                    hypr.move_workspace_to_monitor(HyprWorkspace::Name(&name), &monitor);
+                }
+                hypr.go_to(HyprWorkspace::Name(&name));
+            }
+            Request::GotoOrBind { register, bind } => {
+                let bound = self.inner.read().await.target(register).is_some();
+                if bound {
+                    return Box::pin(self.handle_message(stream, hypr, Request::Goto { register }))
+                        .await;
+                }
 
-                hypr.go_to(HyprWorkspace::Name(name));
+                let active = match bind {
+                    true => self.active_workspace(hypr).await,
+                    false => None,
+                };
+                let Some(name) = active else {
+                    stream.write_msg(&GotoOrBindResponse::Unbound { register })?;
+                    return Ok(());
+                };
+                // Binding the focused workspace is all there is to do, it is already gone to.
+                Box::pin(self.handle_message(
+                    stream,
+                    hypr,
+                    Request::Bind {
+                        name: &name,
+                        register,
+                        force: false,
+                        ttl: None,
+                    },
+                ))
+                .await?;
+                stream.write_msg(&GotoOrBindResponse::Bound { register, name })?;
             }
             Request::Moveto { register } => {
-                let lock = self.inner.read().await;
-                let name = lock.registers.get(&register).ok_or_else(|| {
-                    anyhow!("register {register} does not point to any workspace")
+                let target = self.inner.read().await.hypr_target(register);
+                let (name, special) = target.ok_or(ServerError::RegisterUnbound { register })?;
+
+                hypr.move_to(hypr_workspace(&name, special));
+            }
+            Request::MovetoHere { register } => {
+                let target = self.inner.read().await.hypr_target(register);
+                let (name, special) = target.ok_or(ServerError::RegisterUnbound { register })?;
+
+                // Special workspaces always open on the focused monitor.
+                let monitor = match special {
+                    true => None,
+                    false => self.focused_monitor_elsewhere(hypr, &name).await?,
+                };
+                hypr.move_to(hypr_workspace(&name, special));
+                if let Some(monitor) = monitor {
+                    hypr.move_workspace_to_monitor(HyprWorkspace::Name(&name), &monitor);
+                }
+            }
+            Request::Read {
+                workspace: Some(_),
+                registers: Some(_),
+                ..
+            } => {
+                return Err(ServerError::rejected(
+                    "only full reads can be narrowed to registers",
+                ))
+            }
+            Request::Read {
+                registers: Some(range),
+                ..
+            } if range.is_empty() => {
+                return Err(ServerError::rejected(format!(
+                    "register range {}-{} is empty",
+                    range.start(),
+                    range.end()
+                )))
+            }
+            Request::Read {
+                workspace,
+                stream: true,
+                registers,
+            } => self.stream_read(stream, hypr, workspace, registers).await?,
+            Request::Read {
+                workspace,
+                stream: false,
+                registers,
+            } => {
+                let pending_dispatches = Some(hypr.queued()).filter(|queued| !queued.is_empty());
+                let active_workspace = self.active_workspace(hypr).await;
+                match workspace {
+                    Some(Workspace::Workspace(name)) => {
+                        let guard = self.inner.read().await;
+                        let (name, settings) =
+                            guard.workspaces.get_key_value(name).ok_or_else(|| {
+                                ServerError::UnknownWorkspace {
+                                    name: name.to_owned(),
+                                }
+                            })?;
+
+                        stream.write_msg(&ReadResponse {
+                            workspaces: IterMap::new([(
+                                name,
+                                self.current(&guard, name, settings),
+                            )]),
+                            registers: IterMap::new(
+                                guard.registers.of(name).map(|register| (register, name)),
+                            ),
+                            generation: guard.log.generation(),
+                            pending_dispatches,
+                            active_register: guard.register_of(active_workspace.as_deref()),
+                            active_workspace,
+                            windows: guard.windows.counts([name]),
+                            context: guard.context.clone(),
+                            glyphs: self.config.glyphs.clone(),
+                        })?;
+                    }
+                    Some(Workspace::Register(register)) => {
+                        let guard = self.inner.read().await;
+                        let name = guard.resolve_read(Workspace::Register(register))?;
+                        // A dynamic register may follow the focus to a workspace that isn't
+                        // managed.
+                        let unmanaged = WorkspaceSettings::default();
+                        let settings = guard.workspaces.get(&name).unwrap_or(&unmanaged);
+
+                        stream.write_msg(&ReadResponse {
+                            workspaces: IterMap::new([(
+                                &name,
+                                self.current(&guard, &name, settings),
+                            )]),
+                            registers: IterMap::new([(register, &name)]),
+                            generation: guard.log.generation(),
+                            pending_dispatches,
+                            active_register: guard.register_of(active_workspace.as_deref()),
+                            active_workspace,
+                            windows: guard.windows.counts([&name]),
+                            context: guard.context.clone(),
+                            glyphs: self.config.glyphs.clone(),
+                        })?;
+                    }
+                    None => {
+                        let guard = self.inner.read().await;
+                        let context = guard.context.as_deref();
+                        let shown = |name: &str| {
+                            guard.workspaces.get(name).is_some_and(|settings| {
+                                self.in_context(context, settings)
+                                    && guard.listed(registers.as_ref(), name)
+                            })
+                        };
+                        stream.write_msg(&ReadResponse {
+                            workspaces: IterMap::new(
+                                guard.workspaces.iter().filter(|(name, _)| shown(name)).map(
+                                    |(name, settings)| (name, self.current(&guard, name, settings)),
+                                ),
+                            ),
+                            registers: IterMap::new(
+                                guard
+                                    .registers
+                                    .range(registers.clone().unwrap_or(ALL_REGISTERS))
+                                    .filter(|(_, name)| shown(name)),
+                            ),
+                            generation: guard.log.generation(),
+                            pending_dispatches,
+                            active_register: guard.register_of(active_workspace.as_deref()),
+                            active_workspace,
+                            windows: guard
+                                .windows
+                                .counts(guard.workspaces.keys().filter(|name| shown(name))),
+                            context: guard.context.clone(),
+                            glyphs: self.config.glyphs.clone(),
+                        })?;
+                    }
+                }
+            }
+            Request::Subscribe => {
+                flush_hypr(hypr, stream).await.map_err(ServerError::hypr)?;
+                self.stream_updates(stream, None).await?;
+            }
+            Request::Handoff => self.hand_off(stream).await?,
+            Request::Batch(requests) => {
+                // Subscriptions and handoffs take the connection over, and batches don't nest.
+                let unbatchable = requests.iter().find(|request| {
+                    matches!(
+                        request,
+                        Request::Batch(_) | Request::Subscribe | Request::Handoff
+                    )
+                });
+                if let Some(request) = unbatchable {
+                    return Err(ServerError::rejected(format!(
+                        "{} can't be part of a batch",
+                        request.kind()
+                    )));
+                }
+
+                for request in requests {
+                    Box::pin(self.handle_message(stream, hypr, request)).await?;
+                }
+            }
+            Request::Stats => {
+                let mut lock = self.inner.write().await;
+                let now = lock.log.now();
+                stream.write_msg(&Stats {
+                    connections: self.connections.stats(),
+                    subscribers: lock.subscribers.stats(),
+                    usage: lock.usage.counters(now).clone(),
                 })?;
+            }
+            Request::Metrics => {
+                stream.write_raw(self.metrics.render(&self.connections.stats()).as_bytes())
+            }
+            Request::Logs { lines } => stream
+                .write_raw(logging::recent(lines.unwrap_or(logging::RECENT_RECORDS)).as_bytes()),
+            Request::Ping => stream.write_msg("pong")?,
+            Request::Flush => {
+                flush_hypr(hypr, stream).await.map_err(ServerError::hypr)?;
+                stream.flush().await?;
+            }
+            Request::Preview => {
+                if let Some(batch) = hypr.discard() {
+                    stream.write_raw(batch.as_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
 
-                hypr.move_to(HyprWorkspace::Name(name));
+impl Server {
+    /// Turn the connection into a subscription, streaming every [`Update`] until either side
+    /// disconnects.
+    ///
+    /// A subscription handed over by the previous server passes `resume` with the last record
+    /// its client received and continues right after it instead of starting with a snapshot.
+    #[instrument(name = "subscription", skip_all, err)]
+    async fn stream_updates(&self, stream: &mut Socket, resume: Option<Option<u64>>) -> Result<()> {
+        let (subscriber, mut seq) = {
+            let guard = self.inner.read().await;
+            let subscriber = guard.subscribers.subscribe();
+            let seq = guard.log.records().last().map(|record| record.seq);
+
+            match resume {
+                Some(resume) => {
+                    for record in guard.log.records() {
+                        if Some(record.seq) > resume {
+                            stream.write_msg(&Update::Record(record.clone()))?;
+                        }
+                    }
+                }
+                None => stream.write_msg(&Update::Snapshot {
+                    seq,
+                    state: self.subscribed_snapshot(&guard),
+                })?,
             }
-            Request::Read { workspace } => match workspace {
-                Some(Workspace::Workspace(name)) => {
-                    let guard = self.inner.read().await;
-                    let (name, settings) = guard
-                        .workspaces
-                        .get_key_value(name)
-                        .ok_or_else(|| anyhow!("{name} doesn't point to any valid workspace"))?;
-
-                    stream.write_msg(&ReadResponse {
-                        workspaces: IterMap::new([(name, settings)]),
-                        registers: IterMap::new(
-                            guard
-                                .registers
-                                .iter()
-                                .filter(|(_, register_pointee)| *register_pointee == name),
-                        ),
-                    })?;
+            subscriber.snapshot_sent(seq);
+            (subscriber, seq)
+        };
+        info!("subscribed");
+
+        loop {
+            // A subscriber that stopped reading only blocks its own connection, records for it
+            // pile up in its queue until the lag policy steps in.
+            tokio::select! {
+                res = self.connections.write(stream.flush()) => match res {
+                    Some(res) => res?,
+                    None => {
+                        warn!("dropping subscriber that stopped reading");
+                        break;
+                    }
+                },
+                () = subscriber.disconnected() => break,
+            }
+
+            let mut pending = loop {
+                tokio::select! {
+                    pending = subscriber.next() => break pending,
+                    () = self.handoff.requested() => {
+                        self.handoff.subscriber(stream, seq)?;
+                        // Reading on would steal input meant for the new server.
+                        future::pending::<()>().await;
+                    }
+                    // Input after subscribing is ignored, but a client hanging up has to free
+                    // its connection.
+                    res = stream.inner.fill_buf() => match res {
+                        Ok([]) | Err(_) => break None,
+                        Ok(input) => {
+                            let len = input.len();
+                            stream.inner.consume(len);
+                        }
+                    },
                 }
-                Some(Workspace::Register(register)) => {
-                    let guard = self.inner.read().await;
-                    let name = guard
-                        .registers
-                        .get(&register)
-                        .ok_or_else(|| anyhow!("{register} does not point to any workspace"))?;
-
-                    let settings = guard
-                        .workspaces
-                        .get(name)
-                        .ok_or_else(|| anyhow!("{name} doesn't point to any valid workspace"))?;
-
-                    stream.write_msg(&ReadResponse {
-                        workspaces: IterMap::new([(name, settings)]),
-                        registers: IterMap::new([(register, name)]),
-                    })?;
+            };
+            if let (Some(Pending::Records(records)), Some(debounce)) =
+                (&pending, self.config.subscriptions.debounce())
+            {
+                // Let the burst settle, the snapshot then stands in for all of its records.
+                time::sleep(debounce).await;
+                if subscriber.try_next().is_some() || 1 < records.len() {
+                    pending = Some(Pending::Snapshot);
                 }
-                None => {
+            }
+            match pending {
+                Some(Pending::Records(records)) => {
+                    for record in records {
+                        seq = Some(record.seq);
+                        stream.write_msg(&Update::Record(record))?;
+                    }
+                }
+                Some(Pending::Snapshot) => {
                     let guard = self.inner.read().await;
-                    stream.write_msg(&ReadResponse {
-                        workspaces: IterMap::new(&guard.workspaces),
-                        registers: IterMap::new(&guard.registers),
+                    seq = guard.log.records().last().map(|record| record.seq);
+
+                    stream.write_msg(&Update::Snapshot {
+                        seq,
+                        state: self.subscribed_snapshot(&guard),
                     })?;
+                    subscriber.snapshot_sent(seq);
                 }
-            },
-            Request::Flush => {
-                hypr.flush(Some(&mut stream.write_buf)).await?;
-                stream.flush().await?;
+                None => break,
             }
         }
 
+        info!("unsubscribed");
         Ok(())
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct WorkspaceSettings {}
+/// The paths once a hyprland instance accepts connections on its event socket again.
+async fn wait_for_restart(paths: &Paths) -> Paths {
+    let mut interval = time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let restarted = paths.after_hypr_restart();
+        let Ok(hypr_dir) = restarted.hypr_dir() else {
+            continue;
+        };
+        if UnixStream::connect(hypr_dir.join(".socket2.sock"))
+            .await
+            .is_ok()
+        {
+            return restarted;
+        }
+    }
+}
+
+/// The workspace `name` as hyprland knows it, `special:name` for scratchpads.
+fn hypr_workspace(name: &str, special: bool) -> HyprWorkspace<'_> {
+    match special {
+        true => HyprWorkspace::Special(Some(name)),
+        false => HyprWorkspace::Name(name),
+    }
+}
+
+fn unknown_group(group: &str) -> ServerError {
+    ServerError::rejected(format!("there is no group named {group:?}"))
+}
+
+/// Listen on `path` with the permission bits `mode`, replacing the socket a previous server
+/// left behind.
+///
+/// Refuses to start in a directory owned by another user, who could swap the socket for their
+/// own at any time.
+async fn bind(path: &Path, mode: u32) -> Result<UnixListener> {
+    if mode & !0o777 != 0 {
+        return Err(anyhow!("invalid socket mode {mode:#o}"));
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let owner = metadata(dir)
+        .await
+        .with_context(|| format!("can't stat socket directory {}", dir.display()))?
+        .uid();
+    let uid = connections::euid();
+    if owner != uid {
+        return Err(anyhow!(
+            "socket directory {} is owned by uid {owner}, not by us ({uid})",
+            dir.display()
+        ));
+    }
+
+    if let Err(err) = remove_file(path).await {
+        if err.kind() != ErrorKind::NotFound {
+            return Err(err.into());
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    set_permissions(path, Permissions::from_mode(mode))
+        .await
+        .with_context(|| format!("can't set permissions of {}", path.display()))?;
 
-#[allow(clippy::derivable_impls)]
-impl Default for WorkspaceSettings {
-    fn default() -> Self {
-        Self {}
+    Ok(listener)
+}
+
+/// A request being served, holding back bookkeeping beyond the limit until it is done.
+struct Serving<'a> {
+    priority: Priority,
+    start: Option<Instant>,
+    _permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Serving<'_> {
+    /// Record how long the request took, from when it arrived.
+    fn done(self, metrics: &Metrics) {
+        if let Some(start) = self.start {
+            metrics.served(self.priority, start.elapsed().as_secs_f64());
+        }
+    }
+}
+
+/// Accept the next client on `listener`, never resolving if there is none.
+async fn accept_if(listener: Option<&UnixListener>) -> io::Result<UnixStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(stream, _)| stream),
+        None => future::pending().await,
     }
 }
+
+/// Send the batch queued up in `hypr`, passing the reply of hyprland on to the client.
+async fn flush_hypr<C: Compositor>(hypr: &mut C, stream: &mut Socket) -> Result<()> {
+    let mut reply = Vec::new();
+    hypr.flush(Some(&mut reply)).await?;
+    stream.write_raw(&reply);
+    Ok(())
+}
+
+/// Quote `arg` for `sh`, so it is passed as a single word.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Parse the hyprland workspace selector of a `HyprGoto` or `HyprMoveto`.
+fn hypr_selector(selector: &str) -> Result<HyprWorkspace<'_>, ServerError> {
+    HyprWorkspace::parse(selector).ok_or_else(|| {
+        ServerError::rejected(format!("{selector:?} isn't a hyprland workspace selector"))
+    })
+}
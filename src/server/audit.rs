@@ -0,0 +1,102 @@
+use crate::server::{
+    clock::Clock,
+    types::{Request, ServerError},
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    net::unix::UCred,
+    sync::Mutex,
+};
+use tracing::{instrument, warn};
+
+/// Append-only log of every mutating request, who sent it and how it turned out.
+///
+/// Unlike the [`super::log::EventLog`] it also keeps requests that failed or didn't change
+/// anything, and is never read back by the server.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    clock: Clock,
+}
+
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    time: u64,
+    pid: Option<i32>,
+    uid: Option<u32>,
+    request: serde_json::Value,
+    outcome: Outcome<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome<'a> {
+    Ok,
+    Failed(&'a ServerError),
+}
+
+impl AuditLog {
+    pub const FILE: &'static str = "audit.jsonl";
+
+    #[instrument(name = "audit log", err)]
+    pub async fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+            clock: Clock::default(),
+        })
+    }
+
+    /// The request to record once it was handled, `None` if it doesn't need to be recorded.
+    ///
+    /// Requests are captured up front since handling them consumes them.
+    pub fn capture(&self, request: &Request) -> Option<serde_json::Value> {
+        if self.file.is_none() || !request.is_mutating() {
+            return None;
+        }
+        serde_json::to_value(request).ok()
+    }
+
+    /// Append a captured `request` of `peer`, which failed with `error` if there is one.
+    ///
+    /// Failing to write is only logged, the request was already handled anyway.
+    pub async fn record(
+        &self,
+        peer: Option<&UCred>,
+        request: serde_json::Value,
+        error: Option<&ServerError>,
+    ) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let entry = Entry {
+            time: self.clock.now(),
+            pid: peer.and_then(UCred::pid),
+            uid: peer.map(UCred::uid),
+            request,
+            outcome: error.map_or(Outcome::Ok, Outcome::Failed),
+        };
+
+        let res = async {
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+            let mut file = file.lock().await;
+            file.write_all(&line).await?;
+            file.flush().await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(err) = res {
+            warn!(?err, "failed to write audit log");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Fault injection for resilience testing, only compiled in with the `chaos` feature.
+//!
+//! Faults are configured through env vars when the server starts:
+//! - `WS_MGR_CHAOS_HYPR_ERRORS`: probability of sending a batch to hyprland failing
+//! - `WS_MGR_CHAOS_MAX_DELAY`: maximum milliseconds hyprland takes to reply
+//! - `WS_MGR_CHAOS_DROP_EVENTS`: probability of a hyprland event getting lost
+//! - `WS_MGR_CHAOS_SEED`: seed making the injected faults reproducible
+
+use crate::hypr::{Client, Compositor, MonitorInfo, Workspace, WorkspaceInfo};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
+use tracing::warn;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    pub hypr_errors: f64,
+    /// Milliseconds.
+    pub max_delay: u64,
+    pub drop_events: f64,
+    pub seed: u64,
+}
+
+impl Faults {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            hypr_errors: var("WS_MGR_CHAOS_HYPR_ERRORS")?.unwrap_or_default(),
+            max_delay: var("WS_MGR_CHAOS_MAX_DELAY")?.unwrap_or_default(),
+            drop_events: var("WS_MGR_CHAOS_DROP_EVENTS")?.unwrap_or_default(),
+            seed: var("WS_MGR_CHAOS_SEED")?.unwrap_or_default(),
+        })
+    }
+}
+
+fn var<T: std::str::FromStr>(name: &str) -> Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(
+            value.parse().with_context(|| format!("invalid {name}"))?,
+        )),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chaos {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    faults: Faults,
+    rng: u64,
+}
+
+impl Chaos {
+    pub fn from_env() -> Self {
+        let chaos = Self::default();
+        match Faults::from_env() {
+            Ok(faults) => chaos.set(faults),
+            Err(err) => warn!(?err, "not injecting any faults"),
+        }
+        chaos
+    }
+
+    /// Replace the injected faults, restarting the random sequence from their seed.
+    pub fn set(&self, faults: Faults) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        // xorshift gets stuck on zero.
+        state.rng = faults.seed | 1;
+        state.faults = faults;
+    }
+
+    pub fn drop_event(&self) -> bool {
+        let mut state = self.state.lock().expect("lock poisoned");
+        let probability = state.faults.drop_events;
+        state.chance(probability)
+    }
+
+    /// Delay and whether to fail the next batch sent to hyprland.
+    fn hypr_fault(&self) -> (Duration, bool) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        let delay = match state.faults.max_delay {
+            0 => 0,
+            max_delay => state.next() % (max_delay + 1),
+        };
+        let probability = state.faults.hypr_errors;
+        (Duration::from_millis(delay), state.chance(probability))
+    }
+}
+
+impl State {
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        0.0 < probability && (self.next() as f64 / u64::MAX as f64) < probability
+    }
+}
+
+/// Compositor with the faults of [`Chaos`] injected whenever a batch gets sent.
+pub struct Faulty<C> {
+    inner: C,
+    chaos: Arc<Chaos>,
+    /// Whether anything was queued since the last successful flush.
+    pending: bool,
+}
+
+impl<C> Faulty<C> {
+    pub fn new(inner: C, chaos: Arc<Chaos>) -> Self {
+        Self {
+            inner,
+            chaos,
+            pending: false,
+        }
+    }
+}
+
+impl<C: Compositor> Compositor for Faulty<C> {
+    type Events = C::Events;
+
+    fn go_to(&mut self, workspace: Workspace) {
+        self.pending = true;
+        self.inner.go_to(workspace)
+    }
+
+    fn move_to(&mut self, workspace: Workspace) {
+        self.pending = true;
+        self.inner.move_to(workspace)
+    }
+
+    fn move_window(&mut self, workspace: Workspace, address: &str) {
+        self.pending = true;
+        self.inner.move_window(workspace, address)
+    }
+
+    fn move_workspace_to_monitor(&mut self, workspace: Workspace, monitor: &str) {
+        self.pending = true;
+        self.inner.move_workspace_to_monitor(workspace, monitor)
+    }
+
+    fn exec(&mut self, workspace: Workspace, command: &str) {
+        self.pending = true;
+        self.inner.exec(workspace, command)
+    }
+
+    fn keyword(&mut self, key: &str, value: &str) {
+        self.pending = true;
+        self.inner.keyword(key, value)
+    }
+
+    fn dispatch(&mut self, dispatcher: &str) {
+        self.pending = true;
+        self.inner.dispatch(dispatcher)
+    }
+
+    async fn clients_on(&mut self, workspace: &str) -> Result<Vec<Client>> {
+        self.inner.clients_on(workspace).await
+    }
+
+    async fn list_clients(&mut self) -> Result<Vec<Client>> {
+        self.inner.list_clients().await
+    }
+
+    async fn active_workspace(&mut self) -> Result<WorkspaceInfo> {
+        self.inner.active_workspace().await
+    }
+
+    async fn list_workspaces(&mut self) -> Result<Vec<WorkspaceInfo>> {
+        self.inner.list_workspaces().await
+    }
+
+    async fn list_monitors(&mut self) -> Result<Vec<MonitorInfo>> {
+        self.inner.list_monitors().await
+    }
+
+    async fn subscribe_events(&mut self) -> Result<C::Events> {
+        self.inner.subscribe_events().await
+    }
+
+    fn discard(&mut self) -> Option<String> {
+        self.pending = false;
+        self.inner.discard()
+    }
+
+    fn queued(&self) -> Vec<String> {
+        self.inner.queued()
+    }
+
+    async fn flush(&mut self, reply: Option<&mut Vec<u8>>) -> Result<()> {
+        if self.pending {
+            let (delay, fail) = self.chaos.hypr_fault();
+            time::sleep(delay).await;
+            if fail {
+                return Err(anyhow!("chaos: injected hyprland socket error"));
+            }
+        }
+
+        self.inner.flush(reply).await?;
+        self.pending = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the timestamps recorded in the event log.
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    /// Only moves when advanced explicitly, used to make simulations reproducible.
+    Manual(Arc<AtomicU64>),
+}
+
+impl Clock {
+    /// Milliseconds since the unix epoch.
+    pub fn now(&self) -> u64 {
+        match self {
+            Clock::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis() as u64),
+            Clock::Manual(time) => time.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn advance(&self, millis: u64) {
+        if let Clock::Manual(time) = self {
+            time.fetch_add(millis, Ordering::SeqCst);
+        }
+    }
+}
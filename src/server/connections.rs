@@ -0,0 +1,147 @@
+use crate::{
+    config::ConnectionsConfig,
+    server::types::{ConnectionStats, Priority, Request, ServerError},
+};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::unix::UCred,
+    sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit},
+    time,
+};
+
+/// What a client may do, depending on the socket it connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Full,
+    /// Only requests observing the state, see [`Request::is_read_only`].
+    ReadOnly,
+}
+
+impl Access {
+    pub fn check(self, request: &Request) -> Result<(), ServerError> {
+        match self {
+            Self::ReadOnly if !request.is_read_only() => Err(ServerError::ReadOnly),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Limits how many clients can be connected at once and counts the ones turned away or
+/// reaped for being idle or not reading.
+#[derive(Debug)]
+pub struct Connections {
+    config: ConnectionsConfig,
+    permits: Arc<Semaphore>,
+    bookkeeping: Semaphore,
+    uid: u32,
+    rejected: AtomicU64,
+    reaped: AtomicU64,
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Self::new(ConnectionsConfig::default())
+    }
+}
+
+impl Connections {
+    pub fn new(config: ConnectionsConfig) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(config.max)),
+            bookkeeping: Semaphore::new(config.max_bookkeeping.max(1)),
+            uid: euid(),
+            config,
+            rejected: AtomicU64::new(0),
+            reaped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.config.max
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.read_timeout)
+    }
+
+    /// Whether the peer may connect at all, see [`ConnectionsConfig::allowed_uids`].
+    pub fn authorize(&self, peer: Option<&UCred>) -> Result<(), ServerError> {
+        let Some(allowed) = &self.config.allowed_uids else {
+            return Ok(());
+        };
+        match peer.map(UCred::uid) {
+            Some(uid) if uid == self.uid || allowed.contains(&uid) => Ok(()),
+            uid => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(ServerError::PermissionDenied { uid })
+            }
+        }
+    }
+
+    /// A permit to hold for as long as the connection lives, `None` if there are too many.
+    pub fn admit(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = Arc::clone(&self.permits).try_acquire_owned().ok();
+        if permit.is_none() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        permit
+    }
+
+    /// Wait until a request of `priority` may be served, holding the permit while it is.
+    pub async fn serve(&self, priority: Priority) -> Option<SemaphorePermit<'_>> {
+        match priority {
+            Priority::Bookkeeping => {
+                let permit = self.bookkeeping.acquire().await;
+                Some(permit.expect("the semaphore is never closed"))
+            }
+            Priority::Navigation | Priority::Other => None,
+        }
+    }
+
+    /// Wait for the client to start its next request, `None` if it idled for too long.
+    pub async fn idle<T>(&self, future: impl Future<Output = T>) -> Option<T> {
+        self.reap_after(Duration::from_secs(self.config.idle_timeout), future)
+            .await
+    }
+
+    /// Wait for the client to finish sending a request, `None` if it took too long.
+    pub async fn read<T>(&self, future: impl Future<Output = T>) -> Option<T> {
+        self.reap_after(self.read_timeout(), future).await
+    }
+
+    /// Wait for the client to take what was sent to it, `None` if it stopped reading.
+    pub async fn write<T>(&self, future: impl Future<Output = T>) -> Option<T> {
+        self.reap_after(Duration::from_secs(self.config.write_timeout), future)
+            .await
+    }
+
+    async fn reap_after<T>(&self, timeout: Duration, future: impl Future<Output = T>) -> Option<T> {
+        let res = time::timeout(timeout, future).await.ok();
+        if res.is_none() {
+            self.reaped.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            active: self.config.max - self.permits.available_permits(),
+            max: self.config.max,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            reaped: self.reaped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The user the server runs as.
+pub fn euid() -> u32 {
+    // SAFETY: geteuid has no preconditions and always succeeds.
+    unsafe { libc::geteuid() }
+}
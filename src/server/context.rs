@@ -0,0 +1,20 @@
+//! Contexts like `work` and `personal`, which hide the workspaces of the others from bars
+//! without touching them.
+
+use crate::server::{types::WorkspaceSettings, Server};
+
+impl Server {
+    /// Whether a workspace with `settings` of its own is shown while switched to `context`.
+    ///
+    /// Workspaces without a context, their own or from the `[defaults]`, show in every one.
+    pub(super) fn in_context(&self, context: Option<&str>, settings: &WorkspaceSettings) -> bool {
+        let Some(context) = context else {
+            return true;
+        };
+        let own = settings
+            .context
+            .as_deref()
+            .or(self.config.defaults.context.as_deref());
+        own.is_none_or(|own| own == context)
+    }
+}
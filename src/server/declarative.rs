@@ -0,0 +1,182 @@
+use crate::{
+    config::{Config, DeclareConfig},
+    server::{
+        types::{Event, Register, ServerError},
+        Inner, Server,
+    },
+};
+use anyhow::Result;
+use std::{path::Path, sync::Arc, time::Duration};
+use tokio::{fs, time};
+use tracing::{info, instrument, warn};
+
+impl DeclareConfig {
+    /// Whether binding `register` to `name` is compatible with the declared state.
+    pub fn check_bind(&self, register: Register, name: &str) -> Result<(), ServerError> {
+        match self.registers.get(&register) {
+            Some(declared) if declared != name => Err(ServerError::rejected(format!(
+                "register {register} is declared as {declared} in the config"
+            ))),
+            None if !self.adhoc => Err(ServerError::rejected(format!(
+                "register {register} isn't declared in the config and adhoc bindings are disabled"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_unbind(&self, register: Register) -> Result<(), ServerError> {
+        match self.registers.get(&register) {
+            Some(declared) => Err(ServerError::rejected(format!(
+                "register {register} is declared as {declared} in the config"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether letting `register` follow the focus is compatible with the declared state.
+    pub fn check_bind_dynamic(&self, register: Register) -> Result<(), ServerError> {
+        self.check_unbind(register)?;
+        match self.adhoc {
+            true => Ok(()),
+            false => Err(ServerError::rejected(format!(
+                "register {register} isn't declared in the config and adhoc bindings are disabled"
+            ))),
+        }
+    }
+
+    /// Whether removing the workspace `name` is compatible with the declared state.
+    pub fn check_remove(&self, name: &str) -> Result<(), ServerError> {
+        if self.workspaces.iter().any(|declared| declared == name) {
+            return Err(ServerError::rejected(format!(
+                "workspace {name} is declared in the config"
+            )));
+        }
+        match self
+            .registers
+            .iter()
+            .find(|(_, declared)| *declared == name)
+        {
+            Some((register, _)) => Err(ServerError::rejected(format!(
+                "register {register} is declared as {name} in the config"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether undoing `event` is compatible with the declared state, which reconciling would
+    /// otherwise commit again right away.
+    pub fn check_undo(&self, event: &Event) -> Result<(), ServerError> {
+        match event {
+            Event::Created { name, .. } => self.check_remove(name),
+            Event::Bound { name, register, .. }
+                if self.registers.get(register).map(String::as_str) == Some(&**name) =>
+            {
+                self.check_unbind(*register)
+            }
+            // Only fails with adhoc bindings disabled, the name doesn't matter then.
+            Event::Unbound { register } if !self.registers.contains_key(register) => {
+                self.check_bind(*register, "")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The events turning the state in `inner` into the declared one.
+    fn plan(&self, inner: &Inner) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        for name in &self.workspaces {
+            if !inner.workspaces.contains_key(name.as_str()) {
+                events.push(Event::Created {
+                    name: name.as_str().into(),
+                    settings: Default::default(),
+                });
+            }
+        }
+        for (&register, name) in &self.registers {
+            if inner.registers.get(&register).map(|name| &**name) != Some(name.as_str()) {
+                events.push(Event::Bound {
+                    name: name.as_str().into(),
+                    register,
+                    expires: None,
+                });
+            }
+        }
+        if !self.adhoc {
+            for &register in inner.registers.keys().chain(inner.dynamic.keys()) {
+                if !self.registers.contains_key(&register) {
+                    events.push(Event::Unbound { register });
+                }
+            }
+        }
+
+        events
+    }
+}
+
+impl Server {
+    /// Commit whatever is needed to bring the state back in line with the declared one.
+    #[instrument(skip(self), err)]
+    pub(super) async fn reconcile(&self) -> Result<()> {
+        let declared = self.declared.read().await;
+        let mut lock = self.inner.write().await;
+
+        let events = declared.plan(&lock);
+        if !events.is_empty() {
+            info!(changes = events.len(), "reconciling with declared state");
+        }
+        for event in events {
+            lock.commit(event).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the declared state from the config again and reconcile with it, right away
+    /// instead of once the config watcher notices a change.
+    pub(super) async fn reload(&self) -> Result<(), ServerError> {
+        if let Some(path) = &self.config_path {
+            let config = Config::load_from(path)
+                .await
+                .map_err(|err| ServerError::rejected(format!("invalid config: {err:#}")))?;
+            *self.declared.write().await = config.declare;
+        }
+        self.reconcile().await?;
+        Ok(())
+    }
+
+    /// Reload the declared state whenever the config file at `path` changes.
+    pub(super) async fn watch_declared(self: Arc<Self>, path: &Path) {
+        let modified_at = || async {
+            fs::metadata(path)
+                .await
+                .and_then(|meta| meta.modified())
+                .ok()
+        };
+        // The config was already loaded on startup, a missing one counts as changed once it
+        // shows up.
+        let mut modified = modified_at().await;
+        let mut interval = time::interval(Duration::from_secs(2));
+
+        loop {
+            interval.tick().await;
+
+            let current = modified_at().await;
+            if current == modified {
+                continue;
+            }
+            modified = current;
+
+            match Config::load_from(path).await {
+                Ok(config) => {
+                    info!("config changed, reloading declared state");
+                    *self.declared.write().await = config.declare;
+                    if let Err(err) = self.reconcile().await {
+                        warn!(?err, "failed to reconcile with declared state");
+                    }
+                }
+                Err(err) => warn!(?err, "ignoring invalid config"),
+            }
+        }
+    }
+}
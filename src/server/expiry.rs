@@ -0,0 +1,88 @@
+use crate::{
+    hypr::{Compositor, Dispatcher, Hypr},
+    server::{
+        hooks::{self, Hook},
+        types::{Event, Register, ServerError},
+        Server,
+    },
+};
+use std::{sync::Arc, time::Duration};
+use tokio::time;
+use tracing::{info, warn};
+
+impl Server {
+    /// How often bindings are checked for having expired, which is as late as they get
+    /// unbound.
+    const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Unbind every register whose binding expired by now, removing the workspaces left
+    /// empty and unbound if `remove_expired` is set.
+    ///
+    /// Expiring bindings unbind even locked registers, the lock came after the ttl was given.
+    pub(super) async fn expire<C: Compositor>(&self, hypr: &mut C) -> Result<(), ServerError> {
+        // Checked every second, which mostly finds nothing, without holding up writers.
+        let lock = self.inner.read().await;
+        let now = lock.log.now();
+        if !lock.expiring.values().any(|expires| *expires <= now) {
+            return Ok(());
+        }
+        drop(lock);
+
+        // The declared state wins over an earlier ttl, like it does over an unbind.
+        let declared = self.declared.read().await;
+        let mut lock = self.inner.write().await;
+        let expired: Vec<(Register, Arc<str>)> = lock
+            .expiring
+            .iter()
+            .filter(|(register, expires)| {
+                **expires <= now && declared.check_unbind(**register).is_ok()
+            })
+            .filter_map(|(register, _)| Some((*register, lock.registers.get(register)?.clone())))
+            .collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        for (register, _) in &expired {
+            lock.commit(Event::Unbound {
+                register: *register,
+            })
+            .await?;
+        }
+        drop(lock);
+        drop(declared);
+        info!(unbound = expired.len(), "expired bindings");
+        for (register, _) in &expired {
+            hooks::run(
+                &self.config.hooks,
+                Hook::Unbind,
+                &[("WS_MGR_REGISTER", register)],
+            );
+        }
+
+        if self.config.remove_expired {
+            self.remove_garbage(hypr, |name| {
+                expired.iter().any(|(_, unbound)| &**unbound == name)
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Unbind expired bindings every [`Server::EXPIRY_CHECK_INTERVAL`].
+    pub(super) async fn expire_bindings(self: Arc<Self>, dispatcher: Dispatcher) {
+        let mut interval = time::interval(Self::EXPIRY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let mut hypr = Hypr::dispatched(dispatcher.clone());
+            let res = match self.expire(&mut hypr).await {
+                Ok(()) => hypr.flush(None).await,
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = res {
+                warn!(?err, "failed to expire bindings");
+            }
+        }
+    }
+}
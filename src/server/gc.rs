@@ -0,0 +1,92 @@
+use crate::{
+    hypr::{Compositor, Dispatcher, Hypr},
+    server::{
+        types::{Event, ServerError},
+        Server,
+    },
+};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::time;
+use tracing::{info, warn};
+
+impl Server {
+    /// Remove every workspace without windows that no register points to, returning their
+    /// names.
+    ///
+    /// Persistent and declared workspaces are kept. Workspaces hyprland doesn't list at all
+    /// are empty as well, since hyprland destroys empty workspaces once they are left.
+    pub(super) async fn gc<C: Compositor>(
+        &self,
+        hypr: &mut C,
+    ) -> Result<Vec<Arc<str>>, ServerError> {
+        self.remove_garbage(hypr, |_| true).await
+    }
+
+    /// Like [`Server::gc`], but only for the workspaces `among` picks.
+    pub(super) async fn remove_garbage<C: Compositor>(
+        &self,
+        hypr: &mut C,
+        among: impl Fn(&str) -> bool,
+    ) -> Result<Vec<Arc<str>>, ServerError> {
+        let windows: BTreeMap<_, _> = hypr
+            .list_workspaces()
+            .await
+            .map_err(ServerError::hypr)?
+            .into_iter()
+            .map(|workspace| (workspace.name, workspace.windows))
+            .collect();
+
+        let declared = self.declared.read().await;
+        let mut lock = self.inner.write().await;
+        let garbage: Vec<_> = lock
+            .workspaces
+            .iter()
+            .filter(|(name, settings)| {
+                among(name)
+                    && self.effective(settings).persistent != Some(true)
+                    && windows.get(&***name).copied().unwrap_or(0) == 0
+                    && !lock.registers.is_bound(name)
+                    && declared.check_remove(name).is_ok()
+            })
+            .map(|(name, _)| Arc::clone(name))
+            .collect();
+
+        for name in &garbage {
+            lock.commit(Event::Removed {
+                name: Arc::clone(name),
+            })
+            .await?;
+            // Hyprland keeps empty workspaces around if they are persistent, which they might
+            // be from its own config.
+            if windows.contains_key(&**name) {
+                hypr.keyword("workspace", &format!("name:{name}, persistent:false"));
+            }
+        }
+        if !garbage.is_empty() {
+            info!(removed = garbage.len(), "collected empty workspaces");
+        }
+
+        Ok(garbage)
+    }
+
+    /// Collect empty workspaces every `period`.
+    pub(super) async fn collect_garbage(self: Arc<Self>, dispatcher: Dispatcher, period: Duration) {
+        let mut interval = time::interval(period);
+        // The first tick completes right away, give hyprland time to open the workspaces
+        // of the restored state.
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let mut hypr = Hypr::dispatched(dispatcher.clone());
+            let res = match self.gc(&mut hypr).await {
+                Ok(_) => hypr.flush(None).await,
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = res {
+                warn!(?err, "failed to collect empty workspaces");
+            }
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Upgrading the server without dropping clients.
+//!
+//! A newly started server sends [`Request::Handoff`] to the running one, which stops accepting
+//! clients, waits for the ones in flight and sends back its listening socket and every
+//! subscriber connection via `SCM_RIGHTS`. The state lives in the event log anyway, so the new
+//! server only has to reopen it and resume each subscription after the last record its client
+//! received.
+
+use crate::{
+    path_builder::Paths,
+    server::{types::Request, Server},
+    socket::{Framing, Socket},
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    future, io, mem,
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+    ptr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, Interest},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, watch, Notify},
+    time::{self, Instant},
+};
+use tracing::{info, info_span, instrument, warn, Instrument};
+
+/// Most file descriptors a single message can carry on linux.
+const MAX_FDS: usize = 253;
+const MAX_PAYLOAD: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Handoff {
+    started: watch::Sender<bool>,
+    transfers: Mutex<Option<mpsc::UnboundedSender<Transfer>>>,
+    done: Notify,
+}
+
+impl Default for Handoff {
+    fn default() -> Self {
+        Self {
+            started: watch::Sender::new(false),
+            transfers: Mutex::default(),
+            done: Notify::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Transfer {
+    Listener(OwnedFd),
+    Subscriber(OwnedFd, Subscription),
+}
+
+/// A subscription as handed over, in the same order as the file descriptors.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Last record the client received.
+    seq: Option<u64>,
+    framed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    subscriptions: Vec<Subscription>,
+}
+
+/// What the new server received from the old one.
+pub struct HandedOver {
+    listener: UnixListener,
+    subscriptions: Vec<(UnixStream, Subscription)>,
+}
+
+impl Handoff {
+    /// Resolves once a handoff was requested.
+    pub async fn requested(&self) {
+        let mut started = self.started.subscribe();
+        // The sender lives as long as the server.
+        let _ = started.wait_for(|&started| started).await;
+    }
+
+    /// Hand the subscription on `stream` over, after everything up to `seq` was sent.
+    pub fn subscriber(&self, stream: &Socket, seq: Option<u64>) -> Result<()> {
+        let fd = stream.inner.get_ref().as_fd().try_clone_to_owned()?;
+        self.transfer(Transfer::Subscriber(
+            fd,
+            Subscription {
+                seq,
+                framed: stream.framing() == Framing::LengthPrefixed,
+            },
+        ));
+        Ok(())
+    }
+
+    pub fn listener(&self, listener: &UnixListener) -> Result<()> {
+        let fd = listener.as_fd().try_clone_to_owned()?;
+        self.transfer(Transfer::Listener(fd));
+        Ok(())
+    }
+
+    fn transfer(&self, transfer: Transfer) {
+        if let Some(transfers) = &*self.transfers.lock().expect("lock poisoned") {
+            // Only fails if the handoff already gave up.
+            let _ = transfers.send(transfer);
+        }
+    }
+
+    /// Resolves once everything was handed over and the server should exit.
+    pub async fn done(&self) {
+        self.done.notified().await
+    }
+}
+
+impl Server {
+    /// Hand everything over to the server that connected on `stream`, never returning if that
+    /// succeeded, so nothing gets committed until the process exits.
+    #[instrument(skip_all, err)]
+    pub(super) async fn hand_off(&self, stream: &mut Socket) -> Result<()> {
+        // The new server reads the counters once it took over.
+        let _ = self.save_usage().await;
+        let (transfers, mut received) = mpsc::unbounded_channel();
+        *self.handoff.transfers.lock().expect("lock poisoned") = Some(transfers);
+        self.handoff.started.send_replace(true);
+        info!("handing off to new server");
+
+        // Clients still being served could commit events the new server wouldn't know about.
+        let deadline = Instant::now() + self.connections.read_timeout();
+        loop {
+            let subscribers = self.inner.read().await.subscribers.stats().len();
+            // The connection requesting the handoff is active as well.
+            if self.connections.stats().active <= subscribers + 1 || deadline <= Instant::now() {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        let inner = self.inner.write().await;
+        let subscribers = inner.subscribers.stats().len();
+
+        let deadline = Instant::now() + self.connections.read_timeout();
+        let mut listener = None;
+        let mut subscriptions = Vec::new();
+        let mut fds = Vec::new();
+        while listener.is_none() || subscriptions.len() < subscribers {
+            match time::timeout_at(deadline, received.recv()).await {
+                Ok(Some(Transfer::Listener(fd))) => listener = Some(fd),
+                Ok(Some(Transfer::Subscriber(fd, subscription))) => {
+                    if fds.len() + 1 < MAX_FDS {
+                        fds.push(fd);
+                        subscriptions.push(subscription);
+                    } else {
+                        warn!("too many subscribers, dropping subscription");
+                    }
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+        let listener = listener.ok_or_else(|| anyhow!("listener wasn't handed over"))?;
+        if subscriptions.len() < subscribers {
+            warn!(
+                handed_over = subscriptions.len(),
+                subscribers, "not every subscription was handed over"
+            );
+        }
+
+        fds.insert(0, listener);
+        let payload = serde_json::to_vec(&State { subscriptions })?;
+        let raw_fds: Vec<RawFd> = fds.iter().map(AsRawFd::as_raw_fd).collect();
+        stream.flush().await?;
+        let socket = stream.inner.get_ref();
+        let sent = socket
+            .async_io(Interest::WRITABLE, || {
+                send_with_fds(socket.as_raw_fd(), &payload, &raw_fds)
+            })
+            .await?;
+        drop(fds);
+        stream.inner.get_mut().write_all(&payload[sent..]).await?;
+        // The new server claims the socket once it read everything, which it only does after
+        // the shutdown.
+        self.lock.lock().expect("lock poisoned").take();
+        stream.inner.get_mut().shutdown().await?;
+
+        info!(subscribers = raw_fds.len() - 1, "handed off");
+        self.handoff.done.notify_one();
+        let _inner = inner;
+        future::pending().await
+    }
+
+    /// Ask the server running on the configured socket to hand everything over.
+    #[instrument(skip_all, err)]
+    pub async fn request_handoff(paths: &Paths) -> Result<HandedOver> {
+        let mut stream = Socket::connect(&paths.socket()?)
+            .await?
+            .with_framing(Framing::LengthPrefixed);
+        stream.write_msg(&Request::Handoff)?;
+        stream.flush().await?;
+
+        let mut payload = vec![0; MAX_PAYLOAD];
+        let socket = stream.inner.get_ref();
+        let (len, mut fds) = socket
+            .async_io(Interest::READABLE, || {
+                recv_with_fds(socket.as_raw_fd(), &mut payload)
+            })
+            .await?;
+        payload.truncate(len);
+        stream.inner.get_mut().read_to_end(&mut payload).await?;
+
+        let Ok(State { subscriptions }) = serde_json::from_slice(&payload) else {
+            return Err(anyhow!(
+                "handoff refused: {}",
+                String::from_utf8_lossy(&payload).trim()
+            ));
+        };
+        if fds.len() != subscriptions.len() + 1 {
+            return Err(anyhow!(
+                "expected {} file descriptors, got {}",
+                subscriptions.len() + 1,
+                fds.len()
+            ));
+        }
+
+        let listener = std::os::unix::net::UnixListener::from(fds.remove(0));
+        listener.set_nonblocking(true)?;
+        let subscriptions = fds
+            .into_iter()
+            .zip(subscriptions)
+            .map(|(fd, subscription)| {
+                let stream = std::os::unix::net::UnixStream::from(fd);
+                stream.set_nonblocking(true)?;
+                Ok((UnixStream::from_std(stream)?, subscription))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(HandedOver {
+            listener: UnixListener::from_std(listener)?,
+            subscriptions,
+        })
+    }
+
+    /// Serve clients on the listener handed over, resuming every subscription.
+    pub async fn take_over(self: Arc<Self>, paths: &Paths, handed: HandedOver) -> Result<()> {
+        info!(
+            subscriptions = handed.subscriptions.len(),
+            "taking over from previous server"
+        );
+        for (stream, subscription) in handed.subscriptions {
+            let framing = if subscription.framed {
+                Framing::LengthPrefixed
+            } else {
+                Framing::Lines
+            };
+            let mut stream = Socket::from_unixstream(stream).with_framing(framing);
+
+            tokio::spawn({
+                let server_state = Arc::clone(&self);
+                async move {
+                    let _permit = server_state.connections.admit();
+                    if let Err(err) = server_state
+                        .stream_updates(&mut stream, Some(subscription.seq))
+                        .await
+                    {
+                        warn!(?err, "resumed subscription failed");
+                    }
+                }
+                .instrument(info_span!("client"))
+            });
+        }
+
+        self.serve(paths, handed.listener).await
+    }
+}
+
+/// Send `payload` with `fds` attached, returning how much of the payload was sent.
+fn send_with_fds(socket: RawFd, payload: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+    let fds_len = mem::size_of_val(fds) as u32;
+    // SAFETY: CMSG_SPACE only computes a size.
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut _,
+        iov_len: payload.len(),
+    };
+
+    // SAFETY: msghdr is plain data, all pointers stored in it outlive the sendmsg call and the
+    // control buffer has room for exactly one cmsghdr carrying `fds`.
+    let sent = unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len() as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+        ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+
+        libc::sendmsg(socket, &msg, libc::MSG_NOSIGNAL)
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(sent as usize)
+}
+
+/// Receive into `payload`, returning how much was received and the attached file descriptors.
+fn recv_with_fds(socket: RawFd, payload: &mut [u8]) -> io::Result<(usize, Vec<OwnedFd>)> {
+    let max_fds_len = (MAX_FDS * mem::size_of::<RawFd>()) as u32;
+    // SAFETY: CMSG_SPACE only computes a size.
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(max_fds_len) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr().cast(),
+        iov_len: payload.len(),
+    };
+
+    // SAFETY: msghdr is plain data and all pointers stored in it outlive the recvmsg call. The
+    // kernel only writes complete cmsghdrs into the control buffer, whose file descriptors are
+    // fresh and owned by nobody else.
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len() as _;
+
+        let received = libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC);
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut fds = Vec::new();
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                let len = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    / mem::size_of::<RawFd>();
+                for i in 0..len {
+                    fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(io::Error::other("file descriptors got truncated"));
+        }
+
+        Ok((received as usize, fds))
+    }
+}
@@ -0,0 +1,65 @@
+use crate::config::HooksConfig;
+use std::fmt::Display;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+/// Server events users can attach commands to in the `[hooks]` config section.
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    Bind,
+    Unbind,
+    Goto,
+    Create,
+    WorkspaceDestroyed,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::Bind => "bind",
+            Hook::Unbind => "unbind",
+            Hook::Goto => "goto",
+            Hook::Create => "create",
+            Hook::WorkspaceDestroyed => "workspace_destroyed",
+        }
+    }
+
+    fn command(self, config: &HooksConfig) -> Option<&str> {
+        match self {
+            Hook::Bind => config.on_bind.as_deref(),
+            Hook::Unbind => config.on_unbind.as_deref(),
+            Hook::Goto => config.on_goto.as_deref(),
+            Hook::Create => config.on_create.as_deref(),
+            Hook::WorkspaceDestroyed => config.on_workspace_destroyed.as_deref(),
+        }
+    }
+}
+
+/// Run the command configured for `hook` through `sh -c`, if there is one.
+///
+/// The event is described to the command by `WS_MGR_EVENT` and the `WS_MGR_*` variables
+/// in `env`, which is only formatted once there is a command to run.
+pub fn run(config: &HooksConfig, hook: Hook, env: &[(&str, &dyn Display)]) {
+    let Some(cmd) = hook.command(config) else {
+        return;
+    };
+    debug!(hook = hook.name(), cmd, "running hook");
+
+    let mut command = Command::new("sh");
+    command
+        .args(["-c", cmd])
+        .env("WS_MGR_EVENT", hook.name())
+        .envs(env.iter().map(|(key, value)| (key, value.to_string())));
+    spawn(command);
+}
+
+/// Spawn `command` in the background, only logging when it fails.
+pub fn spawn(mut command: Command) {
+    tokio::spawn(async move {
+        match command.status().await {
+            Ok(status) if !status.success() => warn!(?command, %status, "command failed"),
+            Ok(_) => {}
+            Err(err) => warn!(?command, ?err, "failed to run command"),
+        }
+    });
+}
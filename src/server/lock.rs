@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// Exclusive claim on a socket, so a second server started by accident fails instead of
+/// taking the socket away from the running one.
+///
+/// The claim is an flock on a file next to the socket, which the kernel releases when the
+/// process dies however it does. The file is left behind and holds the pid of the owner.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// The lock file for `socket`, `ws-mgr.sock` is claimed by `ws-mgr.lock`.
+    pub fn path(socket: &Path) -> PathBuf {
+        socket.with_extension("lock")
+    }
+
+    /// Claim the lock at `path`, failing right away if another process holds it.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        // SAFETY: the fd stays open for as long as the file lives.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(err.into());
+            }
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            let owner = match pid.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {pid})"),
+            };
+            return Err(anyhow!(
+                "another server{owner} holds {}, use --takeover to replace it",
+                path.display()
+            ));
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Self { _file: file })
+    }
+}
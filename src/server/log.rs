@@ -0,0 +1,143 @@
+use crate::server::{
+    clock::Clock,
+    types::{Event, Record},
+};
+use anyhow::Result;
+use std::{collections::HashSet, io::ErrorKind, path::Path};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::{info, instrument, warn};
+
+/// Append-only log of every [`Event`] the server ever committed.
+///
+/// If backed by a file, each record is written as a single json line before it is applied,
+/// so the state can be rebuilt after a crash by replaying the file.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    records: Vec<Record>,
+    file: Option<File>,
+    clock: Clock,
+}
+
+impl EventLog {
+    /// Open the log at `path`, loading all records already stored there.
+    ///
+    /// A trailing line that fails to parse is assumed to be a write interrupted by a crash and
+    /// is cut off, so the next record doesn't end up on the same line.
+    #[instrument(name = "event log", skip(clock), err)]
+    pub async fn open(path: &Path, clock: Clock) -> Result<Self> {
+        let mut records = Vec::new();
+        // Length of the lines that parsed, and whether the last of them lacks its newline.
+        let (mut valid, mut unterminated) = (0, false);
+        match fs::read_to_string(path).await {
+            Ok(content) => {
+                let mut lines = content.split_inclusive('\n').enumerate().peekable();
+                while let Some((line_nr, line)) = lines.next() {
+                    let json = line.strip_suffix('\n').unwrap_or(line);
+                    match serde_json::from_str(json.strip_suffix('\r').unwrap_or(json)) {
+                        Ok(record) => {
+                            records.push(record);
+                            valid += line.len();
+                            unterminated = !line.ends_with('\n');
+                        }
+                        Err(err) if lines.peek().is_none() => {
+                            warn!(?err, line_nr, "cutting off truncated record");
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        info!(records = records.len(), "loaded");
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.set_len(valid as u64).await?;
+        if unterminated {
+            file.write_all(b"\n").await?;
+            file.flush().await?;
+        }
+
+        Ok(Self {
+            records,
+            file: Some(file),
+            clock,
+        })
+    }
+
+    /// An in-memory log starting out with `records`.
+    pub fn from_records(records: Vec<Record>, clock: Clock) -> Self {
+        Self {
+            records,
+            file: None,
+            clock,
+        }
+    }
+
+    /// The time events get recorded with, in milliseconds since the unix epoch.
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Number of records appended so far, which only ever grows, undoing included.
+    pub fn generation(&self) -> u64 {
+        self.records.last().map_or(0, |record| record.seq + 1)
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Append `event` to the log, persisting it first if the log is backed by a file.
+    pub async fn append(&mut self, event: Event) -> Result<&Record> {
+        let record = Record {
+            seq: self.generation(),
+            time: self.clock.now(),
+            event,
+        };
+
+        if let Some(file) = &mut self.file {
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            file.write_all(&line).await?;
+            file.flush().await?;
+        }
+
+        self.records.push(record);
+        Ok(self.records.last().expect("just pushed a record"))
+    }
+
+    /// Sequence numbers of all records that were undone by a [`Event::Reverted`].
+    pub fn reverted(&self) -> HashSet<u64> {
+        self.records
+            .iter()
+            .filter_map(|record| match record.event {
+                Event::Reverted { seq } => Some(seq),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The newest record that is still in effect and can be undone.
+    pub fn last_revertible(&self) -> Option<&Record> {
+        let reverted = self.reverted();
+        self.records.iter().rev().find(|record| {
+            !matches!(record.event, Event::Reverted { .. }) && !reverted.contains(&record.seq)
+        })
+    }
+
+    /// All records still in effect, in the order they have to be applied.
+    pub fn effective(&self) -> impl Iterator<Item = &Record> {
+        let reverted = self.reverted();
+        self.records.iter().filter(move |record| {
+            !matches!(record.event, Event::Reverted { .. }) && !reverted.contains(&record.seq)
+        })
+    }
+}
@@ -0,0 +1,309 @@
+//! Counters exported in the prometheus text exposition format by [`Request::Metrics`].
+//!
+//! [`Request::Metrics`]: crate::server::types::Request::Metrics
+
+use crate::{
+    hypr::{Client, Compositor, MonitorInfo, Workspace, WorkspaceInfo},
+    server::types::{ConnectionStats, Priority, Request, ServerError},
+};
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Upper bounds in seconds of the buckets latencies are sorted into.
+const BUCKETS: [f64; 11] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    requests: BTreeMap<&'static str, u64>,
+    errors: BTreeMap<&'static str, u64>,
+    dispatches: Histogram,
+    /// Time from a request arriving until it was handled, by [`Priority`].
+    served: BTreeMap<&'static str, Histogram>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Samples of at most the corresponding bound in [`BUCKETS`], not cumulative.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    /// Write the samples of `name`, with `labels` like `priority="navigation",` put before
+    /// the bucket label.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        let bounds = BUCKETS.iter().map(ToString::to_string);
+        let counts = self.buckets.iter().map(|count| {
+            cumulative += count;
+            cumulative
+        });
+        for (bound, count) in bounds
+            .chain(["+Inf".to_owned()])
+            .zip(counts.chain([self.count]))
+        {
+            writeln!(
+                out,
+                "ws_mgr_{name}_bucket{{{labels}le=\"{bound}\"}} {count}"
+            )
+            .expect("writing to string doesn't fail");
+        }
+        let labels = labels.trim_end_matches(',');
+        let labels = match labels {
+            "" => String::new(),
+            labels => format!("{{{labels}}}"),
+        };
+        writeln!(out, "ws_mgr_{name}_sum{labels} {}", self.sum)
+            .expect("writing to string doesn't fail");
+        writeln!(out, "ws_mgr_{name}_count{labels} {}", self.count)
+            .expect("writing to string doesn't fail");
+    }
+}
+
+impl Metrics {
+    pub fn request(&self, request: &Request) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        *state.requests.entry(request.kind()).or_default() += 1;
+    }
+
+    pub fn error(&self, error: &ServerError) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        *state.errors.entry(error.kind()).or_default() += 1;
+    }
+
+    fn dispatch(&self, seconds: f64) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        state.dispatches.observe(seconds);
+    }
+
+    pub fn served(&self, priority: Priority, seconds: f64) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        state
+            .served
+            .entry(priority.as_str())
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// All metrics in the text exposition format.
+    pub fn render(&self, connections: &ConnectionStats) -> String {
+        let state = self.state.lock().expect("lock poisoned");
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "requests_total",
+            "counter",
+            "Requests handled, by type.",
+        );
+        for (request, count) in &state.requests {
+            metric(&mut out, "requests_total", ("request", request), *count);
+        }
+        header(
+            &mut out,
+            "errors_total",
+            "counter",
+            "Requests that failed, by error.",
+        );
+        for (error, count) in &state.errors {
+            metric(&mut out, "errors_total", ("error", error), *count);
+        }
+
+        header(
+            &mut out,
+            "hypr_dispatch_seconds",
+            "histogram",
+            "Time taken to send a batch to hyprland.",
+        );
+        state
+            .dispatches
+            .render(&mut out, "hypr_dispatch_seconds", "");
+
+        header(
+            &mut out,
+            "request_seconds",
+            "histogram",
+            "Time taken to serve a request, by priority.",
+        );
+        for (priority, served) in &state.served {
+            served.render(
+                &mut out,
+                "request_seconds",
+                &format!("priority=\"{priority}\","),
+            );
+        }
+
+        header(
+            &mut out,
+            "connections",
+            "gauge",
+            "Clients currently connected.",
+        );
+        writeln!(out, "ws_mgr_connections {}", connections.active)
+            .expect("writing to string doesn't fail");
+        header(
+            &mut out,
+            "connections_rejected_total",
+            "counter",
+            "Clients turned away without being served.",
+        );
+        writeln!(
+            out,
+            "ws_mgr_connections_rejected_total {}",
+            connections.rejected
+        )
+        .expect("writing to string doesn't fail");
+        header(
+            &mut out,
+            "connections_reaped_total",
+            "counter",
+            "Clients disconnected for idling or sending too slowly.",
+        );
+        writeln!(
+            out,
+            "ws_mgr_connections_reaped_total {}",
+            connections.reaped
+        )
+        .expect("writing to string doesn't fail");
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(
+        out,
+        "# HELP ws_mgr_{name} {help}\n# TYPE ws_mgr_{name} {kind}"
+    )
+    .expect("writing to string doesn't fail");
+}
+
+fn metric(out: &mut String, name: &str, (label, value): (&str, &str), count: u64) {
+    writeln!(out, "ws_mgr_{name}{{{label}=\"{value}\"}} {count}")
+        .expect("writing to string doesn't fail");
+}
+
+/// Compositor recording how long sending each batch to hyprland takes.
+pub struct Timed<C> {
+    inner: C,
+    metrics: Arc<Metrics>,
+    /// Whether anything was queued since the last successful flush.
+    pending: bool,
+}
+
+impl<C> Timed<C> {
+    pub fn new(inner: C, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner,
+            metrics,
+            pending: false,
+        }
+    }
+}
+
+impl<C: Compositor> Compositor for Timed<C> {
+    type Events = C::Events;
+
+    fn go_to(&mut self, workspace: Workspace) {
+        self.pending = true;
+        self.inner.go_to(workspace)
+    }
+
+    fn move_to(&mut self, workspace: Workspace) {
+        self.pending = true;
+        self.inner.move_to(workspace)
+    }
+
+    fn move_window(&mut self, workspace: Workspace, address: &str) {
+        self.pending = true;
+        self.inner.move_window(workspace, address)
+    }
+
+    fn move_workspace_to_monitor(&mut self, workspace: Workspace, monitor: &str) {
+        self.pending = true;
+        self.inner.move_workspace_to_monitor(workspace, monitor)
+    }
+
+    fn exec(&mut self, workspace: Workspace, command: &str) {
+        self.pending = true;
+        self.inner.exec(workspace, command)
+    }
+
+    fn keyword(&mut self, key: &str, value: &str) {
+        self.pending = true;
+        self.inner.keyword(key, value)
+    }
+
+    fn dispatch(&mut self, dispatcher: &str) {
+        self.pending = true;
+        self.inner.dispatch(dispatcher)
+    }
+
+    async fn clients_on(&mut self, workspace: &str) -> Result<Vec<Client>> {
+        self.inner.clients_on(workspace).await
+    }
+
+    async fn list_clients(&mut self) -> Result<Vec<Client>> {
+        self.inner.list_clients().await
+    }
+
+    async fn active_workspace(&mut self) -> Result<WorkspaceInfo> {
+        self.inner.active_workspace().await
+    }
+
+    async fn list_workspaces(&mut self) -> Result<Vec<WorkspaceInfo>> {
+        self.inner.list_workspaces().await
+    }
+
+    async fn list_monitors(&mut self) -> Result<Vec<MonitorInfo>> {
+        self.inner.list_monitors().await
+    }
+
+    async fn subscribe_events(&mut self) -> Result<C::Events> {
+        self.inner.subscribe_events().await
+    }
+
+    fn discard(&mut self) -> Option<String> {
+        self.pending = false;
+        self.inner.discard()
+    }
+
+    fn queued(&self) -> Vec<String> {
+        self.inner.queued()
+    }
+
+    async fn flush(&mut self, reply: Option<&mut Vec<u8>>) -> Result<()> {
+        if !self.pending {
+            return self.inner.flush(reply).await;
+        }
+
+        let start = Instant::now();
+        let res = self.inner.flush(reply).await;
+        self.metrics.dispatch(start.elapsed().as_secs_f64());
+        if res.is_ok() {
+            self.pending = false;
+        }
+        res
+    }
+}
@@ -0,0 +1,9 @@
+use crate::server::hooks;
+use tokio::process::Command;
+
+/// Show a desktop notification via `notify-send`, without waiting for it to be delivered.
+pub fn send(body: String) {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name=ws-mgr", "ws-mgr", &body]);
+    hooks::spawn(command);
+}
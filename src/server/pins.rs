@@ -0,0 +1,46 @@
+use crate::{
+    hypr::{Compositor, Workspace as HyprWorkspace},
+    server::Server,
+};
+use anyhow::Result;
+use tracing::{debug, warn};
+
+impl Server {
+    /// Move the workspaces pinned to a monitor back to it, if hyprland has them open on
+    /// another one.
+    pub(super) async fn enforce_pins<C: Compositor>(&self, hypr: &mut C) {
+        if self.inner.read().await.pinned.is_empty() {
+            return;
+        }
+        let enforced = match self.queue_pinned_moves(hypr).await {
+            Ok(()) => hypr.flush(None).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = enforced {
+            warn!(?err, "failed to move pinned workspaces back");
+        }
+    }
+
+    /// Queue the moves [`Server::enforce_pins`] makes, without flushing them.
+    ///
+    /// Pins to a monitor that isn't connected are left for when it is.
+    pub(super) async fn queue_pinned_moves<C: Compositor>(&self, hypr: &mut C) -> Result<()> {
+        let open = hypr.list_workspaces().await?;
+        let monitors = hypr.list_monitors().await?;
+        let pinned = self.inner.read().await.pinned.clone();
+        for workspace in &open {
+            let Some(monitor) = pinned.get(&*workspace.name) else {
+                continue;
+            };
+            if workspace.monitor == **monitor {
+                continue;
+            }
+            if !monitors.iter().any(|connected| connected.name == **monitor) {
+                debug!(name = workspace.name, %monitor, "pinned monitor isn't connected");
+                continue;
+            }
+            hypr.move_workspace_to_monitor(HyprWorkspace::Name(&workspace.name), monitor);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    config::MonitorProfile,
+    hypr::{Compositor, Workspace as HyprWorkspace},
+    server::{types::Event, Server},
+};
+use anyhow::Result;
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::{debug, info, warn};
+
+impl Server {
+    /// Move the workspaces where the monitor profile matching the connected monitors wants
+    /// them, if any does.
+    ///
+    /// Their monitor settings are changed as well, so workspaces hyprland opens later end up
+    /// on the right monitor too.
+    pub(super) async fn apply_monitor_profile<C: Compositor>(&self, hypr: &mut C) {
+        if self.config.profiles.is_empty() {
+            return;
+        }
+        if let Err(err) = self.try_apply_monitor_profile(hypr).await {
+            warn!(?err, "failed to apply the monitor profile");
+        }
+    }
+
+    async fn try_apply_monitor_profile<C: Compositor>(&self, hypr: &mut C) -> Result<()> {
+        let mut connected: Vec<_> = hypr
+            .list_monitors()
+            .await?
+            .into_iter()
+            .map(|monitor| monitor.name)
+            .collect();
+        connected.sort();
+        let Some((name, profile)) = self.config.profiles.iter().find(|(_, profile)| {
+            let mut monitors = profile.monitors.clone();
+            monitors.sort();
+            monitors == connected
+        }) else {
+            debug!(?connected, "no monitor profile matches");
+            return Ok(());
+        };
+        info!(profile = name, "applying monitor profile");
+
+        let open = hypr.list_workspaces().await?;
+        let mut lock = self.inner.write().await;
+        let mut moved = Vec::new();
+        for (name, monitor) in placements(&lock.registers, &lock.workspaces, profile) {
+            let Some(settings) = lock.workspaces.get(&name) else {
+                continue;
+            };
+            // Special workspaces show on whichever monitor is focused, and pins win over
+            // profiles.
+            if settings.special == Some(true) || lock.pinned.contains_key(&name) {
+                continue;
+            }
+            let mut settings = settings.clone();
+            if settings.monitor.as_deref() != Some(monitor) {
+                settings.monitor = Some(monitor.to_owned());
+                lock.commit(Event::Configured {
+                    name: Arc::clone(&name),
+                    settings: settings.clone(),
+                })
+                .await?;
+            }
+            moved.push((name, monitor, settings));
+        }
+        drop(lock);
+
+        for (name, monitor, settings) in &moved {
+            self.push_settings(hypr, name, settings);
+            let elsewhere = open
+                .iter()
+                .any(|workspace| workspace.name == **name && workspace.monitor != *monitor);
+            if elsewhere {
+                hypr.move_workspace_to_monitor(HyprWorkspace::Name(name), monitor);
+            }
+        }
+        hypr.flush(None).await
+    }
+}
+
+/// The monitor `profile` wants each workspace on, those given by name over those given by
+/// register.
+fn placements<'a, V>(
+    registers: &BTreeMap<u16, Arc<str>>,
+    workspaces: &BTreeMap<Arc<str>, V>,
+    profile: &'a MonitorProfile,
+) -> BTreeMap<Arc<str>, &'a str> {
+    let mut placements = BTreeMap::new();
+    for (register, monitor) in &profile.registers {
+        if let Some(name) = registers.get(register) {
+            placements.insert(Arc::clone(name), monitor.as_str());
+        }
+    }
+    for (name, monitor) in &profile.workspaces {
+        if let Some((name, _)) = workspaces.get_key_value(name.as_str()) {
+            placements.insert(Arc::clone(name), monitor.as_str());
+        }
+    }
+    placements
+}
@@ -0,0 +1,80 @@
+//! The settings in effect for a workspace, its own on top of the `[defaults]` of the config.
+
+use crate::{
+    hypr::Compositor,
+    server::{types::WorkspaceSettings, Inner, Server},
+};
+use tracing::warn;
+
+impl WorkspaceSettings {
+    /// These settings, with the ones left unset taken from `defaults`.
+    ///
+    /// The project directory, being special and urgency belong to the workspace alone, they
+    /// aren't inherited.
+    pub fn inherit(&self, defaults: &Self) -> Self {
+        Self {
+            monitor: self.monitor.clone().or_else(|| defaults.monitor.clone()),
+            gaps_in: self.gaps_in.or(defaults.gaps_in),
+            gaps_out: self.gaps_out.or(defaults.gaps_out),
+            persistent: self.persistent.or(defaults.persistent),
+            layout: self.layout.clone().or_else(|| defaults.layout.clone()),
+            master_orientation: self.master_orientation.or(defaults.master_orientation),
+            cwd: self.cwd.clone(),
+            special: self.special,
+            icon: self.icon.clone().or_else(|| defaults.icon.clone()),
+            label: self.label.clone().or_else(|| defaults.label.clone()),
+            context: self.context.clone().or_else(|| defaults.context.clone()),
+            urgent: self.urgent,
+            windowrules: match self.windowrules.is_empty() {
+                true => defaults.windowrules.clone(),
+                false => self.windowrules.clone(),
+            },
+        }
+    }
+}
+
+impl Server {
+    /// The settings in effect for a workspace with `settings` of its own.
+    pub(super) fn effective(&self, settings: &WorkspaceSettings) -> WorkspaceSettings {
+        settings.inherit(&self.config.defaults)
+    }
+
+    /// The settings in effect for the workspace `name`, as reads show them with the urgency
+    /// hyprland reported.
+    pub(super) fn current(
+        &self,
+        inner: &Inner,
+        name: &str,
+        settings: &WorkspaceSettings,
+    ) -> WorkspaceSettings {
+        WorkspaceSettings {
+            urgent: inner.urgent(name, settings),
+            ..self.effective(settings)
+        }
+    }
+
+    /// Queue the keywords applying the settings in effect for the workspace `name`, which has
+    /// `settings` of its own.
+    ///
+    /// Hyprland keeps every window rule it was given until it reloads its config, so pushing
+    /// the same settings twice leaves their window rules in twice, which is harmless.
+    pub(super) fn push_settings<C: Compositor>(
+        &self,
+        hypr: &mut C,
+        name: &str,
+        settings: &WorkspaceSettings,
+    ) {
+        let settings = self.effective(settings);
+        if let Some(rule) = settings.rule(name) {
+            hypr.keyword("workspace", &rule);
+        }
+        for rule in settings.window_rules(name) {
+            // Those of the workspace itself were checked before, the defaults weren't.
+            if rule.contains(';') {
+                warn!(rule, "skipping window rule containing a `;`");
+                continue;
+            }
+            hypr.keyword("windowrulev2", &rule);
+        }
+    }
+}
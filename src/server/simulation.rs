@@ -0,0 +1,574 @@
+use crate::{
+    config::Config,
+    hypr::{
+        Client, Compositor, Hypr, HyprEvent, HyprEvents, MonitorInfo, Workspace as HyprWorkspace,
+        WorkspaceInfo,
+    },
+    server::{
+        clock::Clock,
+        connections::Access,
+        log::EventLog,
+        types::{Request, ServerError},
+        Server,
+    },
+    socket::{Framing, Socket},
+};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    env,
+    fmt::{self, Write},
+    fs, mem,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{io::AsyncWriteExt, net::UnixStream, sync::Notify, task::JoinHandle};
+
+/// A single step of a simulation script.
+///
+/// Scripts are json lines, one step per line. Empty lines and lines starting with `#` are
+/// ignored.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// Send a request the way the cli does, followed by a `Flush`.
+    Request(serde_json::Value),
+    /// Send a request like `Request`, but on the read-only socket.
+    ReadOnly(serde_json::Value),
+    /// Send these bytes as is on a connection of their own, like a client sending junk.
+    Raw(Vec<u8>),
+    /// Send all requests at once on a single connection, without flushing.
+    Pipeline(Vec<serde_json::Value>),
+    /// Advance the clock by that many milliseconds.
+    Advance(u64),
+    /// Feed a raw hyprland event line to the server.
+    Event(String),
+    /// Have hyprland answer the next batch with this reply.
+    Reply(String),
+    /// Have sending the next batch to hyprland fail with this error.
+    Fail(String),
+    /// Have hyprland list these windows from now on, which the server counts like it would
+    /// on connecting to the event socket.
+    Clients(Vec<Client>),
+    /// Have hyprland list these workspaces from now on.
+    Workspaces(Vec<WorkspaceInfo>),
+    /// Have hyprland list these monitors from now on.
+    Monitors(Vec<MonitorInfo>),
+    /// Have hyprland hang on the next batch until `Resume`.
+    Stall,
+    /// Send a request on its own connection without waiting for the server to answer it.
+    ///
+    /// The step returns once the request either finished or is stuck on a stalled batch, the
+    /// response is recorded on `Resume`.
+    Background(serde_json::Value),
+    /// Let all stalled batches through and record the responses to background requests.
+    Resume,
+    /// Drop all in-memory state and recover it by replaying the event log.
+    Crash,
+    /// Crash midway through writing this line to the event log, recovering from a log file
+    /// ending in it like the server does on starting. The log stays a file from now on.
+    Torn(String),
+    /// Restart the server like `Crash`, with the contents of a `config.toml`.
+    Config(String),
+    /// Inject these faults from now on, the same way `WS_MGR_CHAOS_*` would.
+    #[cfg(feature = "chaos")]
+    Chaos(super::chaos::Faults),
+}
+
+/// Runs a [`Server`] against a scripted compositor and clock, recording everything that
+/// happens into a transcript.
+///
+/// Nothing depends on wall-clock time or a running hyprland, so the same script always
+/// produces the same transcript.
+pub struct Simulation {
+    server: Arc<Server>,
+    /// The `config.toml` the server runs with, kept across crashes.
+    config: String,
+    /// The file the event log is kept in once a write got torn.
+    log_file: Option<PathBuf>,
+    clock: Clock,
+    replies: Arc<Mutex<VecDeque<Scripted>>>,
+    transcript: Arc<Mutex<String>>,
+    /// Signaled whenever a batch starts stalling.
+    stalled: Arc<Notify>,
+    stalls: Vec<Arc<Notify>>,
+    background: Vec<(serde_json::Value, JoinHandle<Exchange>)>,
+    clients: Arc<Mutex<Vec<Client>>>,
+    workspaces: Arc<Mutex<Vec<WorkspaceInfo>>>,
+    monitors: Arc<Mutex<Vec<MonitorInfo>>>,
+}
+
+/// How hyprland answers a batch.
+enum Scripted {
+    Reply(String),
+    Fail(String),
+    /// Hang until notified, then answer `ok`.
+    Stall(Arc<Notify>),
+}
+
+/// What happened on a single connection, see [`exchange`].
+struct Exchange {
+    server: Result<()>,
+    output: Result<String>,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        let clock = Clock::Manual(Default::default());
+        Self {
+            server: Arc::new(Server::new(
+                EventLog::from_records(Vec::new(), clock.clone()),
+                Config::default(),
+            )),
+            config: String::new(),
+            log_file: None,
+            clock,
+            replies: Default::default(),
+            transcript: Default::default(),
+            stalled: Default::default(),
+            stalls: Vec::new(),
+            background: Vec::new(),
+            clients: Default::default(),
+            workspaces: Default::default(),
+            monitors: Default::default(),
+        }
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        if let Some(path) = &self.log_file {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Simulation {
+    /// Run every step of `script` and return the transcript, ending with the final state.
+    pub async fn run(mut self, script: &str) -> Result<String> {
+        for (line_nr, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let step = serde_json::from_str(line)
+                .with_context(|| format!("invalid step on line {}", line_nr + 1))?;
+            self.step(step).await?;
+        }
+
+        let state = self.server.inner.read().await.snapshot();
+        self.record(format_args!("= {}", serde_json::to_string(&state)?));
+
+        Ok(Arc::try_unwrap(mem::take(&mut self.transcript))
+            .map_err(|_| anyhow!("transcript still in use"))?
+            .into_inner()
+            .expect("transcript lock poisoned"))
+    }
+
+    pub async fn step(&mut self, step: Step) -> Result<()> {
+        match step {
+            Step::Request(request) => {
+                self.record(format_args!("> {request}"));
+                let requests = [request, serde_json::to_value(Request::Flush)?];
+                self.connect(&requests, Access::Full).await?;
+            }
+            Step::ReadOnly(request) => {
+                self.record(format_args!("> ro {request}"));
+                let requests = [request, serde_json::to_value(Request::Flush)?];
+                self.connect(&requests, Access::ReadOnly).await?;
+            }
+            Step::Raw(bytes) => {
+                self.record(format_args!("> raw {:?}", String::from_utf8_lossy(&bytes)));
+                let exchange = exchange_with(
+                    Arc::clone(&self.server),
+                    self.compositor(),
+                    Access::Full,
+                    |client| {
+                        // Junk isn't length prefixed, so there needn't be an end frame unless
+                        // the server answered in frames.
+                        client.set_framing(Framing::Lines);
+                        client.write_buf.extend_from_slice(&bytes);
+                        Ok(())
+                    },
+                )
+                .await;
+                self.record_exchange(exchange, "<")?;
+            }
+            Step::Pipeline(requests) => {
+                for request in &requests {
+                    self.record(format_args!("> {request}"));
+                }
+                self.connect(&requests, Access::Full).await?;
+            }
+            Step::Advance(millis) => {
+                self.record(format_args!("~ {millis}ms"));
+                self.clock.advance(millis);
+                let mut hypr = self.compositor();
+                self.server.expire(&mut hypr).await?;
+                hypr.flush(None).await?;
+            }
+            Step::Event(line) => {
+                self.record(format_args!("! {line}"));
+                let event =
+                    HyprEvent::parse(&line).ok_or_else(|| anyhow!("malformed event {line:?}"))?;
+                self.server
+                    .handle_event(&mut self.compositor(), event)
+                    .await;
+            }
+            Step::Reply(reply) => self.push_reply(Scripted::Reply(reply)),
+            Step::Fail(err) => self.push_reply(Scripted::Fail(err)),
+            Step::Clients(clients) => {
+                *self.clients.lock().expect("lock poisoned") = clients;
+                self.server.seed_windows(&mut self.compositor()).await?;
+            }
+            Step::Workspaces(workspaces) => {
+                *self.workspaces.lock().expect("lock poisoned") = workspaces
+            }
+            Step::Monitors(monitors) => *self.monitors.lock().expect("lock poisoned") = monitors,
+            Step::Stall => {
+                let stall = Arc::new(Notify::new());
+                self.stalls.push(Arc::clone(&stall));
+                self.push_reply(Scripted::Stall(stall));
+            }
+            Step::Background(request) => {
+                self.record(format_args!("& > {request}"));
+                let requests = vec![request.clone(), serde_json::to_value(Request::Flush)?];
+                let finished = Arc::new(Notify::new());
+                let exchange = tokio::spawn({
+                    let exchange = exchange(
+                        Arc::clone(&self.server),
+                        self.compositor(),
+                        Access::Full,
+                        requests,
+                    );
+                    let finished = Arc::clone(&finished);
+                    async move {
+                        let exchange = exchange.await;
+                        finished.notify_one();
+                        exchange
+                    }
+                });
+                tokio::select! {
+                    _ = finished.notified() => {}
+                    _ = self.stalled.notified() => {}
+                }
+                self.background.push((request, exchange));
+            }
+            Step::Resume => {
+                self.record(format_args!("# resume"));
+                for stall in self.stalls.drain(..) {
+                    stall.notify_one();
+                }
+                for (request, exchange) in std::mem::take(&mut self.background) {
+                    let exchange = exchange.await?;
+                    self.record(format_args!("& {request}"));
+                    self.record_exchange(exchange, "& <")?;
+                }
+            }
+            Step::Crash => {
+                self.record(format_args!("# crash"));
+                self.restart().await?;
+            }
+            Step::Torn(line) => {
+                self.record(format_args!("# torn {line:?}"));
+                let path = self.log_file.get_or_insert_with(|| {
+                    static FILES: AtomicU64 = AtomicU64::new(0);
+                    let n = FILES.fetch_add(1, Ordering::Relaxed);
+                    env::temp_dir().join(format!("ws-mgr-simulation-{}-{n}.jsonl", process::id()))
+                });
+                let mut log = String::new();
+                for record in self.server.inner.read().await.log.records() {
+                    log.push_str(&serde_json::to_string(record)?);
+                    log.push('\n');
+                }
+                log.push_str(&line);
+                fs::write(&*path, log)?;
+                self.restart().await?;
+            }
+            Step::Config(config) => {
+                self.record(format_args!("# config {config:?}"));
+                self.config = config;
+                self.restart().await?;
+            }
+            #[cfg(feature = "chaos")]
+            Step::Chaos(faults) => {
+                self.record(format_args!("# chaos {}", serde_json::to_string(&faults)?));
+                self.server.chaos.set(faults);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the server with a fresh one, recovering its state from the event log.
+    async fn restart(&mut self) -> Result<()> {
+        let log = match &self.log_file {
+            Some(path) => {
+                // Drop the old server first, so it doesn't hold the file open anymore.
+                self.server = Arc::default();
+                EventLog::open(path, self.clock.clone()).await?
+            }
+            None => {
+                let records = self.server.inner.read().await.log.records().to_vec();
+                EventLog::from_records(records, self.clock.clone())
+            }
+        };
+        self.server = Arc::new(Server::new(
+            log,
+            toml::from_str::<Config>(&self.config).context("invalid config")?,
+        ));
+        Ok(())
+    }
+
+    /// Send `requests` on a single connection and record everything the server sends back.
+    async fn connect(&self, requests: &[serde_json::Value], access: Access) -> Result<()> {
+        let exchange = exchange(
+            Arc::clone(&self.server),
+            self.compositor(),
+            access,
+            requests.to_vec(),
+        )
+        .await;
+        self.record_exchange(exchange, "<")
+    }
+
+    fn record_exchange(&self, exchange: Exchange, prefix: &str) -> Result<()> {
+        if let Err(err) = exchange.server {
+            self.record(format_args!("# client failed: {err:#}"));
+        }
+        for line in exchange.output?.lines() {
+            self.record(format_args!("{prefix} {line}"));
+        }
+        Ok(())
+    }
+
+    /// A [`Handler`] for the server as it currently is.
+    pub fn handler(&self) -> Result<Handler> {
+        let (stream, peer) = UnixStream::pair()?;
+        Ok(Handler {
+            server: Arc::clone(&self.server),
+            stream: Socket::from_unixstream(stream),
+            _peer: peer,
+            compositor: self.compositor(),
+        })
+    }
+
+    fn compositor(&self) -> SimCompositor {
+        SimCompositor {
+            hypr: Hypr::new(Path::new("")),
+            replies: Arc::clone(&self.replies),
+            transcript: Arc::clone(&self.transcript),
+            stalled: Arc::clone(&self.stalled),
+            clients: Arc::clone(&self.clients),
+            workspaces: Arc::clone(&self.workspaces),
+            monitors: Arc::clone(&self.monitors),
+        }
+    }
+
+    fn push_reply(&self, reply: Scripted) {
+        self.replies.lock().expect("lock poisoned").push_back(reply);
+    }
+
+    fn record(&self, line: fmt::Arguments) {
+        record(&self.transcript, line)
+    }
+}
+
+/// Hands parsed requests straight to [`Server::handle_message`], to measure handling them
+/// without parsing, connections or hyprland.
+///
+/// Nothing is recorded, and whatever a request queued for hyprland is dropped afterwards.
+pub struct Handler {
+    server: Arc<Server>,
+    stream: Socket,
+    /// Keeps `stream` connected, responses pile up unread.
+    _peer: UnixStream,
+    compositor: SimCompositor,
+}
+
+impl Handler {
+    pub async fn handle(&mut self, request: Request<'_>) -> Result<(), ServerError> {
+        let res = self
+            .server
+            .handle_message(&mut self.stream, &mut self.compositor, request)
+            .await;
+        self.compositor.hypr.clear();
+        res
+    }
+}
+
+/// Send `requests` on a fresh connection handled by `server`, then hang up and read everything
+/// it sends back.
+async fn exchange(
+    server: Arc<Server>,
+    compositor: SimCompositor,
+    access: Access,
+    requests: Vec<serde_json::Value>,
+) -> Exchange {
+    exchange_with(server, compositor, access, |client| {
+        for request in &requests {
+            client.write_msg(request)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Like [`exchange`], with `send` queueing whatever the client sends.
+async fn exchange_with(
+    server: Arc<Server>,
+    compositor: SimCompositor,
+    access: Access,
+    send: impl FnOnce(&mut Socket) -> Result<()>,
+) -> Exchange {
+    let (client, stream) = match UnixStream::pair() {
+        Ok(pair) => pair,
+        Err(err) => {
+            return Exchange {
+                server: Ok(()),
+                output: Err(err.into()),
+            }
+        }
+    };
+
+    let server = server.handle_client(Socket::from_unixstream(stream), compositor, access);
+    let client = async {
+        let mut client = Socket::from_unixstream(client).with_framing(Framing::LengthPrefixed);
+        send(&mut client)?;
+        client.flush().await?;
+        client.inner.shutdown().await?;
+
+        let mut output = String::new();
+        while let Some(msg) = client.next_msg().await? {
+            output.push_str(&String::from_utf8_lossy(msg));
+            output.push('\n');
+        }
+        client.ensure_ended()?;
+        Ok(output)
+    };
+
+    let (server, output) = tokio::join!(server, client);
+    Exchange { server, output }
+}
+
+fn record(transcript: &Mutex<String>, line: fmt::Arguments) {
+    let mut transcript = transcript.lock().expect("lock poisoned");
+    writeln!(transcript, "{line}").expect("writing to string doesn't fail");
+}
+
+/// Compositor answering batches from the scripted replies instead of talking to hyprland.
+struct SimCompositor {
+    hypr: Hypr,
+    replies: Arc<Mutex<VecDeque<Scripted>>>,
+    transcript: Arc<Mutex<String>>,
+    stalled: Arc<Notify>,
+    clients: Arc<Mutex<Vec<Client>>>,
+    workspaces: Arc<Mutex<Vec<WorkspaceInfo>>>,
+    monitors: Arc<Mutex<Vec<MonitorInfo>>>,
+}
+
+impl Compositor for SimCompositor {
+    type Events = HyprEvents;
+
+    fn go_to(&mut self, workspace: HyprWorkspace) {
+        self.hypr.go_to(workspace)
+    }
+
+    fn move_to(&mut self, workspace: HyprWorkspace) {
+        self.hypr.move_to(workspace)
+    }
+
+    fn move_window(&mut self, workspace: HyprWorkspace, address: &str) {
+        self.hypr.move_window(workspace, address)
+    }
+
+    fn move_workspace_to_monitor(&mut self, workspace: HyprWorkspace, monitor: &str) {
+        self.hypr.move_workspace_to_monitor(workspace, monitor)
+    }
+
+    fn exec(&mut self, workspace: HyprWorkspace, command: &str) {
+        self.hypr.exec(workspace, command)
+    }
+
+    fn keyword(&mut self, key: &str, value: &str) {
+        self.hypr.keyword(key, value)
+    }
+
+    fn dispatch(&mut self, dispatcher: &str) {
+        self.hypr.dispatch(dispatcher)
+    }
+
+    async fn clients_on(&mut self, workspace: &str) -> Result<Vec<Client>> {
+        let clients = self.clients.lock().expect("lock poisoned");
+        Ok(clients
+            .iter()
+            .filter(|client| client.workspace.name == workspace)
+            .cloned()
+            .collect())
+    }
+
+    async fn list_clients(&mut self) -> Result<Vec<Client>> {
+        Ok(self.clients.lock().expect("lock poisoned").clone())
+    }
+
+    async fn active_workspace(&mut self) -> Result<WorkspaceInfo> {
+        Err(anyhow!("the focus is only known from events"))
+    }
+
+    async fn list_workspaces(&mut self) -> Result<Vec<WorkspaceInfo>> {
+        Ok(self.workspaces.lock().expect("lock poisoned").clone())
+    }
+
+    async fn list_monitors(&mut self) -> Result<Vec<MonitorInfo>> {
+        Ok(self.monitors.lock().expect("lock poisoned").clone())
+    }
+
+    async fn subscribe_events(&mut self) -> Result<HyprEvents> {
+        Err(anyhow!(
+            "simulations replay the events of their script instead"
+        ))
+    }
+
+    fn discard(&mut self) -> Option<String> {
+        self.hypr.discard()
+    }
+
+    fn queued(&self) -> Vec<String> {
+        self.hypr.queued()
+    }
+
+    async fn flush(&mut self, reply: Option<&mut Vec<u8>>) -> Result<()> {
+        let Some(batch) = self.hypr.batch() else {
+            return Ok(());
+        };
+        record(&self.transcript, format_args!("hypr: {batch}"));
+
+        let response = self.replies.lock().expect("lock poisoned").pop_front();
+        let response = match response {
+            Some(Scripted::Stall(stall)) => {
+                self.stalled.notify_one();
+                stall.notified().await;
+                Ok("ok".to_owned())
+            }
+            Some(Scripted::Reply(response)) => Ok(response),
+            Some(Scripted::Fail(err)) => Err(err),
+            None => Ok("ok".to_owned()),
+        };
+        match response {
+            Ok(response) => {
+                if let Some(reply) = reply {
+                    reply.extend_from_slice(response.as_bytes());
+                }
+                self.hypr.clear();
+                Ok(())
+            }
+            Err(err) => Err(anyhow!(err)),
+        }
+    }
+}
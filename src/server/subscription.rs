@@ -0,0 +1,226 @@
+use crate::{
+    config::SubscriptionConfig,
+    server::types::{LagPolicy, Record, SubscriberStats},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// Hands every committed [`Record`] to all subscribers without ever waiting on them.
+///
+/// Each subscriber has its own bounded queue. What happens once a subscriber falls so far
+/// behind that its queue is full is decided by the [`LagPolicy`].
+#[derive(Debug, Default)]
+pub struct Fanout {
+    config: SubscriptionConfig,
+    subscribers: Mutex<Vec<Weak<Subscriber>>>,
+    next_id: Mutex<u64>,
+}
+
+#[derive(Debug)]
+pub struct Subscriber {
+    id: u64,
+    policy: LagPolicy,
+    queue: Mutex<Queue>,
+    notify: Notify,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    records: VecDeque<Record>,
+    snapshot_pending: bool,
+    closed: bool,
+    published: Option<u64>,
+    delivered_seq: Option<u64>,
+    delivered: u64,
+    dropped: u64,
+    coalesced: u64,
+    max_queued: usize,
+}
+
+/// What a subscriber has to send next.
+#[derive(Debug)]
+pub enum Pending {
+    /// Send a full snapshot, the queue overflowed and the dropped records got coalesced, or
+    /// something changed that isn't recorded.
+    Snapshot,
+    Records(Vec<Record>),
+}
+
+impl Fanout {
+    pub fn new(config: SubscriptionConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn subscribe(&self) -> Arc<Subscriber> {
+        let id = {
+            let mut next_id = self.next_id.lock().expect("lock poisoned");
+            *next_id += 1;
+            *next_id
+        };
+        let subscriber = Arc::new(Subscriber {
+            id,
+            policy: self.config.policy,
+            queue: Default::default(),
+            notify: Notify::new(),
+        });
+
+        self.subscribers
+            .lock()
+            .expect("lock poisoned")
+            .push(Arc::downgrade(&subscriber));
+        subscriber
+    }
+
+    pub fn publish(&self, record: &Record) {
+        let mut subscribers = self.subscribers.lock().expect("lock poisoned");
+        subscribers.retain(|subscriber| match subscriber.upgrade() {
+            Some(subscriber) => subscriber.push(record, self.config.capacity),
+            None => false,
+        });
+    }
+
+    /// Have every subscriber send a snapshot next, for changes that aren't recorded.
+    pub fn refresh(&self) {
+        let subscribers = self.subscribers.lock().expect("lock poisoned");
+        for subscriber in subscribers.iter().filter_map(Weak::upgrade) {
+            subscriber
+                .queue
+                .lock()
+                .expect("lock poisoned")
+                .snapshot_pending = true;
+            subscriber.notify.notify_one();
+        }
+    }
+
+    pub fn stats(&self) -> Vec<SubscriberStats> {
+        let subscribers = self.subscribers.lock().expect("lock poisoned");
+        subscribers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|subscriber| subscriber.stats())
+            .collect()
+    }
+}
+
+impl Subscriber {
+    /// Queue `record`, returns `false` if the subscriber got disconnected.
+    fn push(&self, record: &Record, capacity: usize) -> bool {
+        let mut queue = self.queue.lock().expect("lock poisoned");
+        queue.published = Some(record.seq);
+
+        if queue.snapshot_pending {
+            // The snapshot will contain this record anyway.
+            return true;
+        }
+
+        if capacity <= queue.records.len() {
+            match self.policy {
+                LagPolicy::DropOldest => {
+                    queue.records.pop_front();
+                    queue.dropped += 1;
+                }
+                LagPolicy::Disconnect => {
+                    warn!(subscriber = self.id, "disconnecting lagging subscriber");
+                    queue.closed = true;
+                    queue.records.clear();
+                    self.notify.notify_one();
+                    return false;
+                }
+                LagPolicy::CoalesceToSnapshot => {
+                    queue.dropped += queue.records.len() as u64;
+                    queue.coalesced += 1;
+                    queue.records.clear();
+                    queue.snapshot_pending = true;
+                    self.notify.notify_one();
+                    return true;
+                }
+            }
+        }
+
+        queue.records.push_back(record.clone());
+        queue.max_queued = queue.max_queued.max(queue.records.len());
+        self.notify.notify_one();
+        true
+    }
+
+    /// Wait for something to send, returns `None` once the subscriber got disconnected.
+    pub async fn next(&self) -> Option<Pending> {
+        loop {
+            let notified = self.notify.notified();
+            if let Some(pending) = self.try_next() {
+                return Some(pending);
+            }
+            if self.queue.lock().expect("lock poisoned").closed {
+                return None;
+            }
+            notified.await;
+        }
+    }
+
+    /// What there is to send right now, without waiting for more.
+    pub fn try_next(&self) -> Option<Pending> {
+        let mut queue = self.queue.lock().expect("lock poisoned");
+        if queue.closed {
+            return None;
+        }
+        if queue.snapshot_pending {
+            return Some(Pending::Snapshot);
+        }
+        if queue.records.is_empty() {
+            return None;
+        }
+        let records: Vec<Record> = queue.records.drain(..).collect();
+        queue.delivered += records.len() as u64;
+        queue.delivered_seq = records.last().map(|record| record.seq);
+        Some(Pending::Records(records))
+    }
+
+    /// Resolves once the subscriber got disconnected for lagging behind.
+    pub async fn disconnected(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.queue.lock().expect("lock poisoned").closed {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Record that a snapshot containing every record up to `seq` was sent.
+    ///
+    /// Must be called while no records can be published, so nothing slips between the
+    /// snapshot and the queue.
+    pub fn snapshot_sent(&self, seq: Option<u64>) {
+        let mut queue = self.queue.lock().expect("lock poisoned");
+        queue.snapshot_pending = false;
+        queue.records.retain(|record| Some(record.seq) > seq);
+        queue.delivered_seq = seq;
+    }
+
+    pub fn stats(&self) -> SubscriberStats {
+        let queue = self.queue.lock().expect("lock poisoned");
+        let lag = match (queue.published, queue.delivered_seq) {
+            (Some(published), Some(delivered)) => published.saturating_sub(delivered),
+            (Some(published), None) => published + 1,
+            (None, _) => 0,
+        };
+
+        SubscriberStats {
+            id: self.id,
+            policy: self.policy,
+            queued: queue.records.len(),
+            max_queued: queue.max_queued,
+            lag,
+            delivered: queue.delivered,
+            dropped: queue.dropped,
+            coalesced: queue.coalesced,
+        }
+    }
+}
@@ -1,12 +1,40 @@
 pub use self::{
-    read_response::ReadResponse,
-    request::{Request, Workspace},
+    bindings::Bindings,
+    dynamic::{DynamicRule, Focus},
+    error::{Failure, ServerError},
+    event::{Event, Record},
+    goto_or_bind_response::GotoOrBindResponse,
+    interner::Interner,
+    prefix::{Prefix, PrefixAction},
+    read_response::{OwnedReadResponse, ReadChunk, ReadResponse},
+    reply::Reply,
+    request::{ParseWorkspaceError, Priority, Register, Request, Tagged, Workspace},
+    settings::{MasterOrientation, WorkspaceSettings},
+    snapshot::{Change, ImportMode, Snapshot},
+    stats::{ConnectionStats, Stats},
+    subscription::{LagPolicy, SubscriberStats, Update},
+    usage::{Usage, WorkspaceUsage},
+    windows::Windows,
 };
 
 pub mod util {
     pub use super::iter_map::IterMap;
 }
 
+mod bindings;
+mod dynamic;
+mod error;
+mod event;
+mod goto_or_bind_response;
+mod interner;
 mod iter_map;
+mod prefix;
 mod read_response;
+mod reply;
 mod request;
+mod settings;
+mod snapshot;
+mod stats;
+mod subscription;
+mod usage;
+mod windows;
@@ -0,0 +1,77 @@
+use crate::server::types::Register;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Deref,
+    sync::Arc,
+};
+
+/// The workspace each register is bound to, also indexed by workspace so the registers of one
+/// are found without going through all of them.
+///
+/// Reads go through the map by register it derefs to, changes through the methods below so
+/// both stay in sync.
+#[derive(Debug, Default)]
+pub struct Bindings {
+    registers: BTreeMap<Register, Arc<str>>,
+    workspaces: HashMap<Arc<str>, BTreeSet<Register>>,
+}
+
+impl Bindings {
+    /// Bind `register` to `name`, returning what it was bound to before.
+    pub fn insert(&mut self, register: Register, name: Arc<str>) -> Option<Arc<str>> {
+        let previous = self.registers.insert(register, Arc::clone(&name));
+        if let Some(previous) = &previous {
+            self.unindex(register, previous);
+        }
+        self.workspaces.entry(name).or_default().insert(register);
+        previous
+    }
+
+    /// Unbind `register`, returning what it was bound to.
+    pub fn remove(&mut self, register: &Register) -> Option<Arc<str>> {
+        let name = self.registers.remove(register)?;
+        self.unindex(*register, &name);
+        Some(name)
+    }
+
+    /// Unbind every register bound to `name`, returning them.
+    pub fn remove_workspace(&mut self, name: &str) -> BTreeSet<Register> {
+        let registers = self.workspaces.remove(name).unwrap_or_default();
+        for register in &registers {
+            self.registers.remove(register);
+        }
+        registers
+    }
+
+    pub fn clear(&mut self) {
+        self.registers.clear();
+        self.workspaces.clear();
+    }
+
+    /// The registers bound to `name`, lowest first.
+    pub fn of(&self, name: &str) -> impl Iterator<Item = Register> + '_ {
+        self.workspaces.get(name).into_iter().flatten().copied()
+    }
+
+    /// Whether any register is bound to `name`.
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.workspaces.contains_key(name)
+    }
+
+    fn unindex(&mut self, register: Register, name: &str) {
+        if let Some(registers) = self.workspaces.get_mut(name) {
+            registers.remove(&register);
+            if registers.is_empty() {
+                self.workspaces.remove(name);
+            }
+        }
+    }
+}
+
+impl Deref for Bindings {
+    type Target = BTreeMap<Register, Arc<str>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.registers
+    }
+}
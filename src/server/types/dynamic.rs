@@ -0,0 +1,54 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, sync::Arc};
+
+/// Which workspace a dynamic register follows, as hyprland reports focus changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum DynamicRule {
+    /// The focused workspace.
+    Current,
+    /// The workspace focused before the current one, to go back to wherever you came from.
+    Previous,
+}
+
+/// The non-special workspaces hyprland focused, most recently focused first.
+#[derive(Debug, Default)]
+pub struct Focus {
+    current: Option<Arc<str>>,
+    previous: Option<Arc<str>>,
+    /// Every workspace once, up to [`Focus::RECENT`] of them.
+    recent: VecDeque<Arc<str>>,
+}
+
+impl Focus {
+    const RECENT: usize = 64;
+
+    /// Record that hyprland focused `name`, special workspaces are skipped.
+    pub fn focused(&mut self, name: Arc<str>) {
+        if name.starts_with("special") || self.current.as_ref() == Some(&name) {
+            return;
+        }
+        self.recent.retain(|recent| *recent != name);
+        self.recent.push_front(Arc::clone(&name));
+        self.recent.truncate(Self::RECENT);
+        self.previous = self.current.replace(name);
+    }
+
+    /// The workspaces focused so far, the current one first.
+    pub fn recent(&self) -> impl Iterator<Item = &Arc<str>> {
+        self.recent.iter()
+    }
+
+    pub fn current(&self) -> Option<&Arc<str>> {
+        self.current.as_ref()
+    }
+
+    /// The workspace `rule` currently points to.
+    pub fn resolve(&self, rule: DynamicRule) -> Option<&Arc<str>> {
+        match rule {
+            DynamicRule::Current => self.current.as_ref(),
+            DynamicRule::Previous => self.previous.as_ref(),
+        }
+    }
+}
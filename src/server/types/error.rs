@@ -0,0 +1,213 @@
+use crate::server::types::Register;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why the server couldn't handle a request, sent to the client in place of the response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "error")]
+pub enum ServerError {
+    UnknownWorkspace {
+        name: String,
+    },
+    RegisterUnbound {
+        register: Register,
+    },
+    NameInUse {
+        name: String,
+    },
+    /// The register doesn't point where a conditional request expected, `current` is where it
+    /// points instead.
+    Conflict {
+        register: Register,
+        current: Option<String>,
+    },
+    /// The state moved on from the generation a request was conditional on.
+    Stale {
+        expected: u64,
+        current: u64,
+    },
+    /// The register is locked, and the request changing it wasn't forced.
+    RegisterLocked {
+        register: Register,
+    },
+    /// Talking to hyprland failed.
+    HyprIpc {
+        message: String,
+    },
+    /// The request couldn't be parsed.
+    Protocol {
+        message: String,
+    },
+    /// The request conflicts with the declared state, or there is nothing to do.
+    Rejected {
+        message: String,
+    },
+    /// The request would change something, but came in on the read-only socket.
+    ReadOnly,
+    /// The connecting user isn't in the allowed uids, `uid` is missing if it couldn't be told.
+    PermissionDenied {
+        uid: Option<u32>,
+    },
+    /// The server already has as many clients as it accepts.
+    TooManyConnections {
+        max: usize,
+    },
+    /// Something broke inside the server, like writing the event log.
+    Internal {
+        message: String,
+    },
+}
+
+impl ServerError {
+    pub fn hypr(err: anyhow::Error) -> Self {
+        Self::HyprIpc {
+            message: format!("{err:#}"),
+        }
+    }
+
+    pub fn protocol(err: anyhow::Error) -> Self {
+        Self::Protocol {
+            message: format!("{err:#}"),
+        }
+    }
+
+    pub fn rejected(message: impl Into<String>) -> Self {
+        Self::Rejected {
+            message: message.into(),
+        }
+    }
+
+    /// Name of the variant, as used on the wire.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::UnknownWorkspace { .. } => "unknown_workspace",
+            Self::RegisterUnbound { .. } => "register_unbound",
+            Self::NameInUse { .. } => "name_in_use",
+            Self::Conflict { .. } => "conflict",
+            Self::Stale { .. } => "stale",
+            Self::RegisterLocked { .. } => "register_locked",
+            Self::HyprIpc { .. } => "hypr_ipc",
+            Self::Protocol { .. } => "protocol",
+            Self::Rejected { .. } => "rejected",
+            Self::ReadOnly => "read_only",
+            Self::PermissionDenied { .. } => "permission_denied",
+            Self::TooManyConnections { .. } => "too_many_connections",
+            Self::Internal { .. } => "internal",
+        }
+    }
+
+    /// Exit code of the cli when a request fails with this error, see `ws-mgr --help`.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::UnknownWorkspace { .. } | Self::RegisterUnbound { .. } => 4,
+            Self::Conflict { .. } | Self::Stale { .. } => 6,
+            Self::HyprIpc { .. } => 5,
+            Self::NameInUse { .. }
+            | Self::RegisterLocked { .. }
+            | Self::Protocol { .. }
+            | Self::Rejected { .. }
+            | Self::ReadOnly
+            | Self::PermissionDenied { .. }
+            | Self::TooManyConnections { .. }
+            | Self::Internal { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownWorkspace { name } => {
+                write!(f, "{name} doesn't point to any valid workspace")
+            }
+            Self::RegisterUnbound { register } => {
+                write!(f, "register {register} does not point to any workspace")
+            }
+            Self::NameInUse { name } => write!(f, "name {name} already in use"),
+            Self::Conflict {
+                register,
+                current: Some(current),
+            } => write!(f, "register {register} points to {current} instead"),
+            Self::Conflict {
+                register,
+                current: None,
+            } => write!(f, "register {register} is unbound instead"),
+            Self::Stale { expected, current } => write!(
+                f,
+                "expected the state at generation {expected}, but it is at {current}"
+            ),
+            Self::RegisterLocked { register } => {
+                write!(
+                    f,
+                    "register {register} is locked, unlock it or force the change"
+                )
+            }
+            Self::HyprIpc { message } => write!(f, "hyprland: {message}"),
+            Self::Protocol { message } => write!(f, "invalid request: {message}"),
+            Self::ReadOnly => {
+                f.write_str("this socket is read-only, it only accepts read, subscribe and ping")
+            }
+            Self::PermissionDenied { uid: Some(uid) } => {
+                write!(f, "uid {uid} isn't allowed to connect")
+            }
+            Self::PermissionDenied { uid: None } => {
+                f.write_str("unknown users aren't allowed to connect")
+            }
+            Self::TooManyConnections { max } => {
+                write!(
+                    f,
+                    "too many connections, the server accepts at most {max} clients"
+                )
+            }
+            Self::Rejected { message } | Self::Internal { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+impl From<anyhow::Error> for ServerError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast() {
+            Ok(err) => err,
+            Err(err) => Self::Internal {
+                message: format!("{err:#}"),
+            },
+        }
+    }
+}
+
+/// A [`ServerError`] as sent to the client, with the id the server logged the request under.
+///
+/// Connections rejected before sending anything get their error without an id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    #[serde(flatten)]
+    pub error: ServerError,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+}
+
+impl Failure {
+    pub fn new(error: ServerError, request_id: u64) -> Self {
+        Self {
+            request_id: Some(request_id),
+            error,
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        self.error.exit_code()
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.request_id {
+            Some(request_id) => write!(f, "{} (request {request_id})", self.error),
+            None => self.error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
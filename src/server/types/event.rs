@@ -0,0 +1,84 @@
+use crate::server::types::{DynamicRule, ImportMode, Register, Snapshot, WorkspaceSettings};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A single mutation of the server state.
+///
+/// The state held by the server is nothing but the result of applying every
+/// recorded event in order, so anything that changes it has to be expressed here.
+/// Undoing is an event as well: `Reverted { seq }` marks the event `seq` as no longer in effect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    Created {
+        name: Arc<str>,
+        /// Settings the workspace starts out with, when created from a template.
+        #[serde(default, skip_serializing_if = "WorkspaceSettings::is_default")]
+        settings: WorkspaceSettings,
+    },
+    Bound {
+        name: Arc<str>,
+        register: Register,
+        /// When the binding gets undone again, in milliseconds since the unix epoch.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires: Option<u64>,
+    },
+    /// `register` follows the workspace `rule` picks from now on.
+    BoundDynamic {
+        register: Register,
+        rule: DynamicRule,
+    },
+    Unbound {
+        register: Register,
+    },
+    Locked {
+        register: Register,
+    },
+    Unlocked {
+        register: Register,
+    },
+    /// The group `group` consists of `members` from now on, in that order.
+    GroupCreated {
+        group: Arc<str>,
+        members: Vec<Arc<str>>,
+    },
+    GroupRemoved {
+        group: Arc<str>,
+    },
+    /// Full reads only list the workspaces of `context` from now on, besides those without
+    /// one, or all of them without a context.
+    ContextSwitched {
+        context: Option<Arc<str>>,
+    },
+    /// The workspace `name` gets moved back to `monitor` whenever it ends up elsewhere.
+    Pinned {
+        name: Arc<str>,
+        monitor: Arc<str>,
+    },
+    Unpinned {
+        name: Arc<str>,
+    },
+    Configured {
+        name: Arc<str>,
+        settings: WorkspaceSettings,
+    },
+    /// The workspace is gone, along with all registers pointing to it.
+    Removed {
+        name: Arc<str>,
+    },
+    Imported {
+        mode: ImportMode,
+        state: Snapshot,
+    },
+    Reverted {
+        seq: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub seq: u64,
+    /// Milliseconds since the unix epoch.
+    pub time: u64,
+    pub event: Event,
+}
@@ -0,0 +1,13 @@
+use crate::server::types::Register;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Answer to a `GotoOrBind` whose register was unbound, a bound register is just gone to.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GotoOrBindResponse {
+    /// Nothing was bound, since binding wasn't asked for or no workspace is focused.
+    Unbound { register: Register },
+    /// The register was bound to the focused workspace `name`.
+    Bound { register: Register, name: Arc<str> },
+}
@@ -0,0 +1,43 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Workspace names shared by every map of the state, so each name is allocated once however
+/// many maps and events refer to it.
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: BTreeSet<Arc<str>>,
+}
+
+impl Interner {
+    /// The shared name equal to `name`, which `name` itself becomes if there is none yet.
+    pub fn intern(&mut self, name: &Arc<str>) -> Arc<str> {
+        match self.names.get(&**name) {
+            Some(shared) => Arc::clone(shared),
+            None => {
+                self.names.insert(Arc::clone(name));
+                Arc::clone(name)
+            }
+        }
+    }
+
+    /// The shared name equal to `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&Arc<str>> {
+        self.names.get(name)
+    }
+
+    /// Stop sharing `name`, whoever holds it keeps it.
+    pub fn release(&mut self, name: &str) {
+        self.names.remove(name);
+    }
+
+    pub fn clear(&mut self) {
+        self.names.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
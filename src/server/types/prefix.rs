@@ -0,0 +1,29 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// What the next `Goto` does with its register instead of going there, once a `Prefix` is
+/// pending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefixAction {
+    /// Move the focused window to the workspace of the register.
+    Moveto,
+    /// Bring the workspace of the register to the focused monitor.
+    Fetch,
+    /// Bind the register to the focused workspace.
+    Bind,
+    Unbind,
+}
+
+/// A prefix waiting for the `Goto` that completes it.
+#[derive(Debug, Clone, Copy)]
+pub struct Prefix {
+    pub action: PrefixAction,
+    /// When the prefix lapses, in milliseconds since the unix epoch.
+    pub expires: u64,
+}
+
+impl Prefix {
+    /// Milliseconds a prefix waits unless `prefix_timeout` is configured.
+    pub const DEFAULT_TIMEOUT: u64 = 1500;
+}
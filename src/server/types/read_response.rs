@@ -1,7 +1,67 @@
+use crate::server::types::{Register, WorkspaceSettings};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
 
+/// Answer to a `Read`, the maps are generic so the server can send them without collecting.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReadResponse<W, R> {
     pub workspaces: W,
     pub registers: R,
+    /// Generation of the state read, see [`super::Tagged`].
+    #[serde(default)]
+    pub generation: u64,
+    /// Dispatches the connection queued for hyprland but didn't flush yet, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_dispatches: Option<Vec<String>>,
+    /// The workspace hyprland focuses, if it could be told.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_workspace: Option<Arc<str>>,
+    /// The lowest register pointing to the active workspace, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_register: Option<Register>,
+    /// Windows open on each of the workspaces, once the server knows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows: Option<BTreeMap<Arc<str>, usize>>,
+    /// The context switched to, whose workspaces are the only ones a full read lists besides
+    /// those without a context.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Arc<str>>,
+    /// What bars show for registers in place of their numbers, see [`super::Snapshot::glyphs`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyphs: BTreeMap<Register, String>,
+}
+
+/// A [`ReadResponse`] as clients receive it.
+pub type OwnedReadResponse =
+    ReadResponse<BTreeMap<Arc<str>, WorkspaceSettings>, BTreeMap<Register, Arc<str>>>;
+
+/// A single message of a streamed [`ReadResponse`], each sent as its own frame.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadChunk {
+    Workspace {
+        name: Arc<str>,
+        settings: WorkspaceSettings,
+    },
+    Register {
+        register: Register,
+        name: Arc<str>,
+    },
+    /// The rest of the response, ending the stream.
+    End {
+        #[serde(default)]
+        generation: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pending_dispatches: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        active_workspace: Option<Arc<str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        active_register: Option<Register>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        windows: Option<BTreeMap<Arc<str>, usize>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        context: Option<Arc<str>>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        glyphs: BTreeMap<Register, String>,
+    },
 }
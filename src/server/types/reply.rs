@@ -0,0 +1,32 @@
+use crate::server::types::ServerError;
+use serde::{Deserialize, Serialize};
+
+/// Answer to a [`super::Tagged`] request.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Reply {
+    /// The response that would have been sent for the untagged request, `null` if none.
+    Ok {
+        id: u64,
+        request_id: u64,
+        /// Generation of the state once the request was handled, see [`super::Tagged`].
+        generation: u64,
+        ok: serde_json::Value,
+    },
+    Err {
+        id: u64,
+        request_id: u64,
+        generation: u64,
+        #[serde(flatten)]
+        error: ServerError,
+    },
+}
+
+impl Reply {
+    pub fn error(&self) -> Option<&ServerError> {
+        match self {
+            Self::Ok { .. } => None,
+            Self::Err { error, .. } => Some(error),
+        }
+    }
+}
@@ -1,17 +1,404 @@
+use crate::server::types::{DynamicRule, ImportMode, PrefixAction, Snapshot, WorkspaceSettings};
 use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 
-pub use self::workspace::Workspace;
+pub use self::workspace::{ParseWorkspaceError, Register, Workspace};
 
 mod workspace;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Request<'a> {
-    Create { name: &'a str },
-    Bind { name: &'a str, register: u8 },
-    Unbind { register: u8 },
-    Goto { register: u8 },
-    Moveto { register: u8 },
-    Read { workspace: Option<Workspace<'a>> },
+    Create {
+        name: &'a str,
+    },
+    /// Create a workspace with the settings of the template configured as `template`, and run
+    /// its commands on it.
+    CreateFrom {
+        name: &'a str,
+        template: &'a str,
+    },
+    /// Fails with [`super::ServerError::RegisterLocked`] on a locked register, unless
+    /// `force` is set, and so do `BindDynamic` and `Unbind`.
+    ///
+    /// Fails with [`super::ServerError::UnknownWorkspace`] unless `name` is managed, open in
+    /// hyprland or focused, or `force` is set to create it.
+    ///
+    /// With a `ttl`, the register gets unbound again after that many seconds.
+    Bind {
+        name: &'a str,
+        register: Register,
+        #[serde(default)]
+        force: bool,
+        ttl: Option<u32>,
+    },
+    /// Bind `register` like `Bind`, but only if it points to the workspace `expected` right
+    /// now, or to none if that is `None`, failing with [`super::ServerError::Conflict`]
+    /// otherwise.
+    ///
+    /// Checks `name` and the lock of `register` like `Bind`, `force` skips both.
+    BindIf {
+        name: &'a str,
+        register: Register,
+        expected: Option<&'a str>,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Have `register` follow the workspace `rule` picks as the focus changes.
+    BindDynamic {
+        register: Register,
+        rule: DynamicRule,
+        #[serde(default)]
+        force: bool,
+    },
+    Unbind {
+        register: Register,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Keep `register` pointing where it does, bound or not, until it gets unlocked.
+    Lock {
+        register: Register,
+    },
+    Unlock {
+        register: Register,
+    },
+    /// Group the workspaces `members`, creating those that don't exist, replacing the group
+    /// if there already is one named `group`.
+    GroupCreate {
+        group: &'a str,
+        #[serde(borrow)]
+        members: Vec<&'a str>,
+    },
+    GroupRemove {
+        group: &'a str,
+    },
+    /// Go to the member of `group` after the focused one, or to the first if the focused
+    /// workspace isn't a member.
+    GroupGoto {
+        group: &'a str,
+    },
+    /// Answered with the members of every group by group name, or of just `group`.
+    GroupRead {
+        group: Option<&'a str>,
+    },
+    /// Leave the workspaces of other contexts than `context`, and their registers, out of full
+    /// reads and group gotos, or none without a context. Nothing about them changes otherwise.
+    SwitchContext {
+        context: Option<&'a str>,
+    },
+    /// Pin the workspace `register` points to to `monitor`, or to the focused one, moving it
+    /// back there whenever it gets moved elsewhere.
+    PinMonitor {
+        register: Register,
+        monitor: Option<&'a str>,
+    },
+    UnpinMonitor {
+        register: Register,
+    },
+    /// Replace the settings of a workspace, pushing them to hyprland as workspace rules.
+    Configure {
+        name: &'a str,
+        settings: WorkspaceSettings,
+    },
+    /// Move every window on `from` over to `to`, removing `from` afterwards if `delete` is set.
+    Merge {
+        from: Workspace<'a>,
+        to: Workspace<'a>,
+        #[serde(default)]
+        delete: bool,
+    },
+    /// Go to the workspace of the project directory `path`, creating it and binding it to
+    /// `register` as needed, and open a terminal there if `terminal` is set.
+    OpenProject {
+        path: &'a str,
+        register: Option<Register>,
+        #[serde(default)]
+        terminal: bool,
+    },
+    Goto {
+        register: Register,
+    },
+    /// Go to the register of the `n`th most recently focused workspace any register points to,
+    /// counting from 1 for the focused one, so 2 toggles between the last two.
+    GotoMru {
+        n: usize,
+    },
+    Moveto {
+        register: Register,
+    },
+    /// Queue the hyprland dispatcher `raw` with its arguments, like `movefocus l`, to go out in
+    /// the same batch as the other dispatches of the connection.
+    Dispatch {
+        raw: &'a str,
+    },
+    /// Go to the workspace hyprland selects with `selector`, like `e+1` or `previous`, see
+    /// [`crate::hypr::Workspace::parse`].
+    HyprGoto {
+        selector: &'a str,
+    },
+    /// Move the focused window to the workspace hyprland selects with `selector`.
+    HyprMoveto {
+        selector: &'a str,
+    },
+    /// Bring the workspace `register` points to over to the focused monitor and go to it,
+    /// instead of going to whichever monitor it is on.
+    Fetch {
+        register: Register,
+    },
+    /// Move the focused window to the workspace `register` points to like `Moveto`, and bring
+    /// that workspace over to the focused monitor in the same batch.
+    MovetoHere {
+        register: Register,
+    },
+    /// Have the next `Goto` do `action` with its register instead of going there, unless it
+    /// comes in after the `prefix_timeout`.
+    ///
+    /// The keybind of a prefix and those for goto make two-key sequences like `<leader> 3`.
+    Prefix {
+        action: PrefixAction,
+    },
+    /// Go to the workspace `register` points to like `Goto`, but answer an unbound register
+    /// with a [`super::GotoOrBindResponse`] instead of failing, binding it to the focused
+    /// workspace first if `bind` is set.
+    GotoOrBind {
+        register: Register,
+        #[serde(default)]
+        bind: bool,
+    },
+    /// Answered with a [`super::ReadResponse`], or with `stream` set, a [`super::ReadChunk`]
+    /// per workspace and register followed by [`super::ReadChunk::End`].
+    ///
+    /// A full read can be narrowed to the `registers` in a range and the workspaces they point
+    /// to.
+    Read {
+        workspace: Option<Workspace<'a>>,
+        #[serde(default)]
+        stream: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        registers: Option<RangeInclusive<Register>>,
+    },
+    /// Remove every empty workspace no register points to, answered with their names.
+    Gc,
+    /// Revert the newest change still in effect, unless the declared state in the config
+    /// calls for it.
+    Undo,
+    /// Load the declared state from the config again, and bring the state in line with it.
+    Reload,
+    History {
+        limit: Option<usize>,
+    },
+    Export,
+    Import {
+        mode: ImportMode,
+        state: Snapshot,
+    },
+    Subscribe,
+    Stats,
+    /// Answered with counters in the prometheus text exposition format.
+    Metrics,
+    /// Answered with the last `lines` log records of the server, all it kept if `None`.
+    Logs {
+        lines: Option<usize>,
+    },
+    /// Answered with `"pong"`, to check whether the server is up.
+    Ping,
     Flush,
+    /// Answered with the batch queued for hyprland so far, which is dropped instead of sent.
+    Preview,
+    /// Sent by a newly started server to take over from the running one.
+    Handoff,
+    /// Handle all requests in order, stopping at the first one failing.
+    ///
+    /// Requests handled before the failing one stay in effect.
+    Batch(#[serde(borrow)] Vec<Request<'a>>),
+}
+
+impl Request<'_> {
+    /// Name of the variant, as used on the wire.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Create { .. } => "create",
+            Self::CreateFrom { .. } => "create_from",
+            Self::Bind { .. } => "bind",
+            Self::BindIf { .. } => "bind_if",
+            Self::BindDynamic { .. } => "bind_dynamic",
+            Self::Unbind { .. } => "unbind",
+            Self::Lock { .. } => "lock",
+            Self::Unlock { .. } => "unlock",
+            Self::GroupCreate { .. } => "group_create",
+            Self::GroupRemove { .. } => "group_remove",
+            Self::GroupGoto { .. } => "group_goto",
+            Self::GroupRead { .. } => "group_read",
+            Self::SwitchContext { .. } => "switch_context",
+            Self::PinMonitor { .. } => "pin_monitor",
+            Self::UnpinMonitor { .. } => "unpin_monitor",
+            Self::Configure { .. } => "configure",
+            Self::Merge { .. } => "merge",
+            Self::OpenProject { .. } => "open_project",
+            Self::Goto { .. } => "goto",
+            Self::GotoMru { .. } => "goto_mru",
+            Self::Moveto { .. } => "moveto",
+            Self::Dispatch { .. } => "dispatch",
+            Self::HyprGoto { .. } => "hypr_goto",
+            Self::HyprMoveto { .. } => "hypr_moveto",
+            Self::Fetch { .. } => "fetch",
+            Self::MovetoHere { .. } => "moveto_here",
+            Self::Prefix { .. } => "prefix",
+            Self::GotoOrBind { .. } => "goto_or_bind",
+            Self::Read { .. } => "read",
+            Self::Gc => "gc",
+            Self::Undo => "undo",
+            Self::Reload => "reload",
+            Self::History { .. } => "history",
+            Self::Export => "export",
+            Self::Import { .. } => "import",
+            Self::Subscribe => "subscribe",
+            Self::Stats => "stats",
+            Self::Metrics => "metrics",
+            Self::Logs { .. } => "logs",
+            Self::Ping => "ping",
+            Self::Flush => "flush",
+            Self::Preview => "preview",
+            Self::Handoff => "handoff",
+            Self::Batch(_) => "batch",
+        }
+    }
+
+    /// How urgently the request has to be served.
+    ///
+    /// A batch navigates if any of its requests does, and is bookkeeping only if all are.
+    pub fn priority(&self) -> Priority {
+        if let Self::Batch(requests) = self {
+            let mut priorities = requests.iter().map(Self::priority);
+            return match priorities
+                .clone()
+                .any(|priority| priority == Priority::Navigation)
+            {
+                true => Priority::Navigation,
+                false if priorities.all(|priority| priority == Priority::Bookkeeping) => {
+                    Priority::Bookkeeping
+                }
+                false => Priority::Other,
+            };
+        }
+        match self {
+            Self::Goto { .. }
+            | Self::GotoMru { .. }
+            | Self::Moveto { .. }
+            | Self::HyprGoto { .. }
+            | Self::HyprMoveto { .. }
+            | Self::Fetch { .. }
+            | Self::MovetoHere { .. }
+            | Self::Prefix { .. }
+            | Self::GotoOrBind { .. }
+            | Self::GroupGoto { .. } => Priority::Navigation,
+            // Subscriptions last for as long as the client is connected, they aren't limited.
+            Self::Read { .. }
+            | Self::GroupRead { .. }
+            | Self::History { .. }
+            | Self::Export
+            | Self::Stats
+            | Self::Metrics
+            | Self::Logs { .. } => Priority::Bookkeeping,
+            _ => Priority::Other,
+        }
+    }
+
+    /// Whether the request changes the state or drives the compositor.
+    pub fn is_mutating(&self) -> bool {
+        if let Self::Batch(requests) = self {
+            return requests.iter().any(Self::is_mutating);
+        }
+        matches!(
+            self,
+            Self::Create { .. }
+                | Self::CreateFrom { .. }
+                | Self::Bind { .. }
+                | Self::BindIf { .. }
+                | Self::BindDynamic { .. }
+                | Self::Unbind { .. }
+                | Self::Lock { .. }
+                | Self::Unlock { .. }
+                | Self::GroupCreate { .. }
+                | Self::GroupRemove { .. }
+                | Self::GroupGoto { .. }
+                | Self::SwitchContext { .. }
+                | Self::PinMonitor { .. }
+                | Self::UnpinMonitor { .. }
+                | Self::Configure { .. }
+                | Self::Merge { .. }
+                | Self::OpenProject { .. }
+                | Self::Goto { .. }
+                | Self::GotoMru { .. }
+                | Self::Moveto { .. }
+                | Self::Dispatch { .. }
+                | Self::HyprGoto { .. }
+                | Self::HyprMoveto { .. }
+                | Self::Fetch { .. }
+                | Self::MovetoHere { .. }
+                | Self::Prefix { .. }
+                | Self::GotoOrBind { .. }
+                | Self::Gc
+                | Self::Undo
+                | Self::Reload
+                | Self::Import { .. }
+        )
+    }
+
+    /// Whether the request only observes the state, the read-only socket accepts nothing else.
+    ///
+    /// `Flush` is let through since the cli ends every request with one, and a read-only
+    /// connection never has any dispatches queued for it to send.
+    pub fn is_read_only(&self) -> bool {
+        if let Self::Batch(requests) = self {
+            return requests.iter().all(Self::is_read_only);
+        }
+        matches!(
+            self,
+            Self::Read { .. } | Self::GroupRead { .. } | Self::Subscribe | Self::Ping | Self::Flush
+        )
+    }
+}
+
+/// Classes of requests, so keybinds stay responsive while bars poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Requests bound to keys, like `Goto`, which never wait for bookkeeping.
+    Navigation,
+    /// Everything else.
+    Other,
+    /// Requests observing the state, like `Read`, which only so many are served at once.
+    Bookkeeping,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Navigation => "navigation",
+            Priority::Other => "other",
+            Priority::Bookkeeping => "bookkeeping",
+        }
+    }
+}
+
+/// A pipelined request, answered with a [`super::Reply`] carrying the same `id`.
+///
+/// Clients sending these may queue up any number of requests without waiting for replies, the
+/// server sends all queued dispatches to hyprland as one batch once it caught up.
+///
+/// Replies carry the `generation` of the state, which grows with every change. A mutating
+/// request with `if_generation` fails with [`super::ServerError::Stale`] unless the state is
+/// still at that generation when it starts and when it commits its first change, so
+/// front-ends can refresh instead of overwriting changes they haven't seen.
+///
+/// Reads and snapshots carry the generation they show as well, so a front-end can make its
+/// changes conditional on what it displays.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tagged<'a> {
+    pub id: u64,
+    #[serde(borrow)]
+    pub request: Request<'a>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_generation: Option<u64>,
 }
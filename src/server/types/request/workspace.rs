@@ -4,19 +4,67 @@ use std::{
     marker::PhantomData,
 };
 
+/// A slot a workspace can be bound to.
+///
+/// Registers used to be u8, state persisted back then reads the same.
+pub type Register = u16;
+
+/// A workspace named directly or through the register pointing to it.
+///
+/// On the wire registers are numbers and names are strings, so a workspace named `7` stays
+/// apart from register 7. In text, see [`Workspace::parse`].
 #[derive(Debug, Clone, Copy)]
 pub enum Workspace<'a> {
-    Register(u8),
+    Register(Register),
     Workspace(&'a str),
 }
 
+impl<'a> Workspace<'a> {
+    pub const REGISTER_PREFIX: &'static str = "reg:";
+    pub const NAME_PREFIX: &'static str = "name:";
+
+    /// Parse `reg:7` as a register and `name:7` as a name, anything else is a register if it
+    /// is a number and a name otherwise.
+    pub fn parse(s: &'a str) -> Result<Self, ParseWorkspaceError> {
+        if let Some(register) = s.strip_prefix(Self::REGISTER_PREFIX) {
+            return register
+                .parse()
+                .map(Workspace::Register)
+                .map_err(|_| ParseWorkspaceError(register.to_owned()));
+        }
+        if let Some(name) = s.strip_prefix(Self::NAME_PREFIX) {
+            return Ok(Workspace::Workspace(name));
+        }
+        Ok(s.parse()
+            .map(Workspace::Register)
+            .unwrap_or(Workspace::Workspace(s)))
+    }
+}
+
+/// A `reg:` prefix followed by something other than a register.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWorkspaceError(String);
+
+impl Display for ParseWorkspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} isn't a register, registers go from 0 to {}",
+            self.0,
+            Register::MAX
+        )
+    }
+}
+
+impl std::error::Error for ParseWorkspaceError {}
+
 impl Serialize for Workspace<'_> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         match self {
-            Workspace::Register(register) => serializer.serialize_u8(*register),
+            Workspace::Register(register) => serializer.serialize_u16(*register),
             Workspace::Workspace(workspace) => serializer.serialize_str(workspace),
         }
     }
@@ -37,14 +85,17 @@ impl<'de: 'a, 'a> Visitor<'de> for WorkspaceVisitor<'a> {
     type Value = Workspace<'a>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a u8 or borrowed str")
+        formatter.write_str("a register or borrowed str")
     }
 
-    fn visit_u8<E>(self, v: u8) -> Result<Self::Value, E>
+    // Self-describing formats like json hand over every unsigned number as a u64.
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        Ok(Workspace::Register(v))
+        Register::try_from(v)
+            .map(Workspace::Register)
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
     }
 
     fn visit_borrowed_str<E>(self, v: &'a str) -> Result<Self::Value, E>
@@ -55,11 +106,12 @@ impl<'de: 'a, 'a> Visitor<'de> for WorkspaceVisitor<'a> {
     }
 }
 
+/// The unambiguous form [`Workspace::parse`] reads back.
 impl Display for Workspace<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Workspace::Register(register) => write!(f, "register:{register}"),
-            Workspace::Workspace(workspace) => write!(f, "workspace:{workspace}"),
+            Workspace::Register(register) => write!(f, "{}{register}", Self::REGISTER_PREFIX),
+            Workspace::Workspace(workspace) => write!(f, "{}{workspace}", Self::NAME_PREFIX),
         }
     }
 }
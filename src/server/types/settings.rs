@@ -0,0 +1,118 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Rules hyprland applies to a workspace, see [`WorkspaceSettings::rule`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceSettings {
+    /// Monitor the workspace opens on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+    /// Gaps between windows, in pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gaps_in: Option<u32>,
+    /// Gaps between windows and monitor edges, in pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gaps_out: Option<u32>,
+    /// Keep the workspace open even without windows, and spare it from garbage collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistent: Option<bool>,
+    /// Layout hyprland tiles the workspace with, like `dwindle` or `master`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Where the master layout puts the master area.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub master_orientation: Option<MasterOrientation>,
+    /// Directory of the project the workspace belongs to, hyprland doesn't know about it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// A scratchpad, which is a special workspace in hyprland that goto toggles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub special: Option<bool>,
+    /// Shown by bars in place of the name, like a nerd font glyph.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// Shown by bars in place of the name, as readable text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Context the workspace belongs to, like `work`, which bars only show while switched to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// A window on the workspace asks for attention. Hyprland's `urgent` event sets it until
+    /// the workspace is focused without saving it, configuring it saves it like any setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urgent: Option<bool>,
+    /// `windowrulev2` rules for the windows on the workspace, like `float, class:^(mpv)$`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub windowrules: Vec<String>,
+}
+
+impl WorkspaceSettings {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The value of a `workspace` keyword applying the settings to `name`, `None` if there is
+    /// nothing to apply.
+    pub fn rule(&self, name: &str) -> Option<String> {
+        let mut rules = Vec::new();
+        if let Some(monitor) = &self.monitor {
+            rules.push(format!("monitor:{monitor}"));
+        }
+        if let Some(gaps_in) = self.gaps_in {
+            rules.push(format!("gapsin:{gaps_in}"));
+        }
+        if let Some(gaps_out) = self.gaps_out {
+            rules.push(format!("gapsout:{gaps_out}"));
+        }
+        if let Some(persistent) = self.persistent {
+            rules.push(format!("persistent:{persistent}"));
+        }
+        if let Some(layout) = &self.layout {
+            rules.push(format!("layout:{layout}"));
+        }
+        if let Some(orientation) = self.master_orientation {
+            rules.push(format!("layoutopt:orientation:{}", orientation.as_str()));
+        }
+
+        (!rules.is_empty()).then(|| format!("name:{name}, {}", rules.join(", ")))
+    }
+
+    /// A window rule hyprland would split in two, since it splits batches on `;`.
+    pub fn unsendable_rule(&self) -> Option<&str> {
+        self.windowrules
+            .iter()
+            .map(String::as_str)
+            .find(|rule| rule.contains(';'))
+    }
+
+    /// The values of `windowrulev2` keywords limiting each window rule to windows on `name`.
+    pub fn window_rules<'a>(&'a self, name: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.windowrules
+            .iter()
+            .map(move |rule| format!("{rule}, workspace:name:{name}"))
+    }
+}
+
+/// Side of the monitor the master area of the master layout is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum MasterOrientation {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// In the middle, with the other windows on both sides.
+    Center,
+}
+
+impl MasterOrientation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::Center => "center",
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::server::types::{DynamicRule, Register, WorkspaceSettings};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+/// The complete server state, as produced by `Export` and accepted by `Import`.
+///
+/// Left out is only what the log doesn't keep either: the focus, window counts and urgency
+/// hyprland reports again, a pending prefix, and the usage counters saved to their own file.
+///
+/// The format is versioned, so documents kept in dotfiles stay importable. Older versions are
+/// accepted, with what they lack left empty.
+/// Maps are ordered to keep exported documents diffable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub workspaces: BTreeMap<Arc<str>, WorkspaceSettings>,
+    pub registers: BTreeMap<Register, Arc<str>>,
+    /// Registers following the focus instead of pointing to a fixed workspace.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dynamic: BTreeMap<Register, DynamicRule>,
+    /// Registers only a forced request can bind or unbind.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub locked: BTreeSet<Register>,
+    /// When bindings given a ttl get unbound, in milliseconds since the unix epoch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub expiring: BTreeMap<Register, u64>,
+    /// Context full reads are filtered by, if one was switched to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Arc<str>>,
+    /// Members of every group, by group name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<Arc<str>, Vec<Arc<str>>>,
+    /// Monitors workspaces get moved back to, by workspace name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pinned: BTreeMap<Arc<str>, Arc<str>>,
+    /// The glyphs of registers from the config, for bars to show. Only for display, importing
+    /// ignores them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyphs: BTreeMap<Register, String>,
+    /// Workspaces hyprland reported urgent, only sent to subscribers for bars to show.
+    /// Exports leave them out and importing ignores them.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub urgent: BTreeSet<Arc<str>>,
+    /// Generation of the state when it was exported, for requests made on top of it to pass
+    /// as `if_generation`. Importing ignores it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+}
+
+impl Snapshot {
+    /// Version 2 added dynamic, locked and expiring registers, the context, groups and pinned
+    /// monitors.
+    pub const VERSION: u32 = 2;
+
+    pub fn check_version(&self) -> Result<()> {
+        if !(1..=Self::VERSION).contains(&self.version) {
+            return Err(anyhow!(
+                "unsupported snapshot version {}, expected at most {}",
+                self.version,
+                Self::VERSION
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the workspace `name` is urgent, configured so or reported by hyprland.
+    pub fn is_urgent(&self, name: &str) -> bool {
+        self.urgent.contains(name)
+            || self
+                .workspaces
+                .get(name)
+                .is_some_and(|settings| settings.urgent == Some(true))
+    }
+
+    /// Everything that would have to change to turn `self` into `other`.
+    pub fn diff<'a>(&'a self, other: &'a Snapshot) -> Vec<Change<'a>> {
+        let mut changes = Vec::new();
+
+        for (name, settings) in &self.workspaces {
+            match other.workspaces.get(name) {
+                None => changes.push(Change::WorkspaceRemoved(name)),
+                Some(other) if other != settings => changes.push(Change::WorkspaceChanged(name)),
+                Some(_) => {}
+            }
+        }
+        for name in other.workspaces.keys() {
+            if !self.workspaces.contains_key(name) {
+                changes.push(Change::WorkspaceAdded(name));
+            }
+        }
+
+        for (&register, name) in &self.registers {
+            match other.registers.get(&register) {
+                None => changes.push(Change::RegisterRemoved { register, name }),
+                Some(to) if to != name => changes.push(Change::RegisterChanged {
+                    register,
+                    from: name,
+                    to,
+                }),
+                Some(_) => {}
+            }
+        }
+        for (&register, name) in &other.registers {
+            if !self.registers.contains_key(&register) {
+                changes.push(Change::RegisterAdded { register, name });
+            }
+        }
+
+        changes
+    }
+}
+
+/// A single difference between two snapshots, as found by [`Snapshot::diff`].
+#[derive(Debug, Clone, Copy)]
+pub enum Change<'a> {
+    WorkspaceAdded(&'a str),
+    WorkspaceRemoved(&'a str),
+    WorkspaceChanged(&'a str),
+    RegisterAdded {
+        register: Register,
+        name: &'a str,
+    },
+    RegisterRemoved {
+        register: Register,
+        name: &'a str,
+    },
+    RegisterChanged {
+        register: Register,
+        from: &'a str,
+        to: &'a str,
+    },
+}
+
+impl Display for Change<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::WorkspaceAdded(name) => write!(f, "+ workspace {name}"),
+            Change::WorkspaceRemoved(name) => write!(f, "- workspace {name}"),
+            Change::WorkspaceChanged(name) => write!(f, "~ workspace {name}: settings differ"),
+            Change::RegisterAdded { register, name } => write!(f, "+ register {register} → {name}"),
+            Change::RegisterRemoved { register, name } => {
+                write!(f, "- register {register} → {name}")
+            }
+            Change::RegisterChanged { register, from, to } => {
+                write!(f, "~ register {register}: {from} → {to}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Add to the current state, overwriting conflicting entries.
+    Merge,
+    /// Throw away the current state.
+    Replace,
+}
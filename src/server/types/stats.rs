@@ -0,0 +1,22 @@
+use crate::server::types::{SubscriberStats, WorkspaceUsage};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Stats {
+    pub connections: ConnectionStats,
+    pub subscribers: Vec<SubscriberStats>,
+    /// Counters of every workspace used since they were started.
+    #[serde(default)]
+    pub usage: BTreeMap<Arc<str>, WorkspaceUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    pub active: usize,
+    pub max: usize,
+    /// Connections turned away because `max` clients were connected already.
+    pub rejected: u64,
+    /// Connections closed for idling, taking too long to send a request or not reading.
+    pub reaped: u64,
+}
@@ -0,0 +1,44 @@
+use crate::server::types::{Record, Snapshot};
+use serde::{Deserialize, Serialize};
+
+/// Messages streamed to a client after `Subscribe`.
+///
+/// A subscription always starts with a snapshot. Every later snapshot replaces all state
+/// received so far and is sent whenever the subscriber lagged behind too far to receive the
+/// individual records.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Update {
+    Snapshot {
+        /// Sequence number of the last record included in the snapshot.
+        seq: Option<u64>,
+        state: Snapshot,
+    },
+    Record(Record),
+}
+
+/// What to do with a subscriber whose queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LagPolicy {
+    /// Drop the oldest queued record to make room.
+    #[default]
+    DropOldest,
+    /// Close the connection.
+    Disconnect,
+    /// Drop the whole queue and send a snapshot instead.
+    CoalesceToSnapshot,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscriberStats {
+    pub id: u64,
+    pub policy: LagPolicy,
+    pub queued: usize,
+    pub max_queued: usize,
+    /// Number of published records not yet handed to the client.
+    pub lag: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub coalesced: u64,
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// How much a workspace got used, see [`Usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceUsage {
+    /// Gotos going to it through a register.
+    pub gotos: u64,
+    /// Milliseconds it was focused for.
+    pub focused_ms: u64,
+}
+
+/// Usage of every workspace since the counters were started.
+///
+/// Focus time is counted from the focus changes hyprland reports, as timed by the server
+/// when they arrive, the time of the workspace focused right now is counted up to `now`
+/// whenever the counters are read.
+#[derive(Debug, Default)]
+pub struct Usage {
+    workspaces: BTreeMap<Arc<str>, WorkspaceUsage>,
+    /// The workspace focused since then, in milliseconds since the unix epoch.
+    focused: Option<(Arc<str>, u64)>,
+}
+
+impl Usage {
+    /// Start counting from `workspaces`, as [`Usage::counters`] returned them before.
+    pub fn restore(workspaces: BTreeMap<Arc<str>, WorkspaceUsage>) -> Self {
+        Self {
+            workspaces,
+            focused: None,
+        }
+    }
+
+    pub fn went_to(&mut self, name: &Arc<str>) {
+        self.workspaces.entry(Arc::clone(name)).or_default().gotos += 1;
+    }
+
+    /// Record that `name` got focused at `now`, which ends the focus of the previous one.
+    pub fn focused(&mut self, name: &Arc<str>, now: u64) {
+        self.settle(now);
+        self.focused = Some((Arc::clone(name), now));
+    }
+
+    /// The counters of every workspace used so far, with the current focus counted until
+    /// `now`.
+    pub fn counters(&mut self, now: u64) -> &BTreeMap<Arc<str>, WorkspaceUsage> {
+        self.settle(now);
+        &self.workspaces
+    }
+
+    /// Add the focus time of the focused workspace up to `now`, and count on from there.
+    fn settle(&mut self, now: u64) {
+        if let Some((name, since)) = &mut self.focused {
+            let usage = self.workspaces.entry(Arc::clone(name)).or_default();
+            usage.focused_ms += now.saturating_sub(*since);
+            *since = now;
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// The workspace of every window hyprland has open, kept up to date from its events so
+/// window counts don't need a `clients` query.
+///
+/// Addresses are kept without the `0x` that queries give but events leave out. Every update
+/// is idempotent, events arriving again after a [`Windows::seed`] that already saw their
+/// effect don't skew the counts.
+#[derive(Debug, Default)]
+pub struct Windows {
+    workspaces: HashMap<Arc<str>, Arc<str>>,
+    counts: BTreeMap<Arc<str>, usize>,
+    seeded: bool,
+}
+
+impl Windows {
+    /// Start over from the windows hyprland reported, as `(address, workspace)` pairs.
+    pub fn seed<'a>(&mut self, windows: impl IntoIterator<Item = (&'a str, &'a str)>) {
+        self.workspaces.clear();
+        self.counts.clear();
+        for (address, workspace) in windows {
+            self.opened(address, workspace);
+        }
+        self.seeded = true;
+    }
+
+    /// Record the window at `address` on `workspace`, moving it there if it was known.
+    pub fn opened(&mut self, address: &str, workspace: &str) {
+        self.closed(address);
+
+        let workspace = match self.counts.get_key_value(workspace) {
+            Some((workspace, _)) => Arc::clone(workspace),
+            None => Arc::from(workspace),
+        };
+        *self.counts.entry(Arc::clone(&workspace)).or_default() += 1;
+        self.workspaces
+            .insert(address.trim_start_matches("0x").into(), workspace);
+    }
+
+    pub fn closed(&mut self, address: &str) {
+        let Some(workspace) = self.workspaces.remove(address.trim_start_matches("0x")) else {
+            return;
+        };
+        if let Some(count) = self.counts.get_mut(&workspace) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&workspace);
+            }
+        }
+    }
+
+    /// Windows open on the workspace named `workspace`, `None` until seeded.
+    pub fn count(&self, workspace: &str) -> Option<usize> {
+        self.seeded
+            .then(|| self.counts.get(workspace).copied().unwrap_or(0))
+    }
+
+    /// Window counts of the workspaces named in `names`, `None` until seeded.
+    pub fn counts<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a Arc<str>>,
+    ) -> Option<BTreeMap<Arc<str>, usize>> {
+        self.seeded.then(|| {
+            names
+                .into_iter()
+                .map(|name| (Arc::clone(name), self.count(name).unwrap_or(0)))
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use crate::server::{
+    types::{Usage, WorkspaceUsage},
+    Server,
+};
+use anyhow::Result;
+use std::{collections::BTreeMap, path::Path, sync::Arc, time::Duration};
+use tokio::{fs, time};
+use tracing::{instrument, warn};
+
+impl Server {
+    pub const USAGE: &'static str = "usage.json";
+    /// How often the usage counters get saved, they lose at most that much when the server
+    /// gets killed.
+    const USAGE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// The counters saved to `path` before, starting over if there are none or they are
+    /// unreadable.
+    pub(super) async fn load_usage(path: &Path) -> Usage {
+        let saved = match fs::read(path).await {
+            Ok(saved) => saved,
+            Err(_) => return Usage::default(),
+        };
+        match serde_json::from_slice::<BTreeMap<Arc<str>, WorkspaceUsage>>(&saved) {
+            Ok(workspaces) => Usage::restore(workspaces),
+            Err(err) => {
+                warn!(?err, "ignoring unreadable usage counters");
+                Usage::default()
+            }
+        }
+    }
+
+    /// Write the usage counters to the state dir, if the server has one.
+    #[instrument(skip(self), err)]
+    pub(super) async fn save_usage(&self) -> Result<()> {
+        let Some(path) = &self.usage_file else {
+            return Ok(());
+        };
+        let counters = {
+            let mut lock = self.inner.write().await;
+            let now = lock.log.now();
+            serde_json::to_vec(lock.usage.counters(now))?
+        };
+
+        // Written aside first, so being killed midway doesn't lose the previous counters.
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, counters).await?;
+        fs::rename(&partial, path).await?;
+        Ok(())
+    }
+
+    /// Save the usage counters every [`Server::USAGE_SAVE_INTERVAL`].
+    pub(super) async fn persist_usage(self: Arc<Self>) {
+        let mut interval = time::interval(Self::USAGE_SAVE_INTERVAL);
+        // Nothing was counted yet on the first tick, which completes right away.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let _ = self.save_usage().await;
+        }
+    }
+}
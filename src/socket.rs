@@ -1,19 +1,123 @@
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
 use std::{
-    fmt::{self, Write},
-    path::Path,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    future::poll_fn,
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
     str::from_utf8,
+    task::Poll,
+    time::Duration,
 };
 use tokio::{
-    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
     net::UnixStream,
+    time,
 };
 
+/// How messages are delimited on the wire.
+///
+/// Newline delimited json is what the first clients spoke, but can't carry payloads containing
+/// newlines, like raw hyprland replies. Length prefixed frames, a `@{len}\n` header followed by
+/// `len` bytes of payload, can carry anything. Since json never starts with `@`, the framing
+/// of every message is detected on reading, and the server answers in the framing of the
+/// last request.
+///
+/// Length prefixed responses end with an empty frame, so a client can tell a server that is
+/// done from one that hung up halfway, newline delimited ones simply end with the connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    #[default]
+    Lines,
+    LengthPrefixed,
+}
+
+const FRAME_TAG: u8 = b'@';
+/// Longest a frame header can be, `@` and the digits of any `usize` with the newline.
+const MAX_HEADER_LEN: u64 = 22;
+pub const MAX_MSG_LEN: usize = 16 * 1024 * 1024;
+
+/// The other side sent something that isn't a message, after which the stream can't be made
+/// sense of anymore.
+#[derive(Debug)]
+pub struct InvalidMsg(String);
+
+impl Display for InvalidMsg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for InvalidMsg {}
+
+/// How long connecting waits for the server to accept, so a hung server doesn't hang the cli
+/// along with it.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Nothing could be sent to the server, because there is none or it doesn't accept
+/// connections.
+#[derive(Debug)]
+pub enum Unreachable {
+    /// Nothing listens on the socket.
+    NotRunning { socket: PathBuf },
+    /// The server didn't accept within [`CONNECT_TIMEOUT`].
+    TimedOut { socket: PathBuf },
+}
+
+impl Unreachable {
+    /// Make sense of `err` from connecting to `socket`, which is left as is unless it means
+    /// that no server is running.
+    pub fn from_io(err: io::Error, socket: &Path) -> anyhow::Error {
+        match err.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => Self::NotRunning {
+                socket: socket.to_owned(),
+            }
+            .into(),
+            _ => anyhow::Error::new(err).context(format!(
+                "can't connect to the server at {}",
+                socket.display()
+            )),
+        }
+    }
+
+    /// Exit code of the cli, see `ws-mgr --help`.
+    pub fn exit_code(&self) -> u8 {
+        3
+    }
+}
+
+impl Display for Unreachable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRunning { socket } => write!(
+                f,
+                "no server is running at {}, start it with `ws-mgr server` or have \
+                 `ws-mgr init` start it when needed",
+                socket.display()
+            ),
+            Self::TimedOut { socket } => write!(
+                f,
+                "the server at {} didn't accept the connection within {}s, it may be hung",
+                socket.display(),
+                CONNECT_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+impl Error for Unreachable {}
+
 pub struct Socket {
     pub inner: BufStream<UnixStream>,
     pub read_buf: Vec<u8>,
     pub write_buf: Vec<u8>,
+    framing: Framing,
+    /// Longest message accepted, in bytes without the delimiting newline.
+    max_msg: usize,
+    /// Whether the other side ended what it sent with [`Socket::write_end`].
+    ended: bool,
 }
 
 impl Socket {
@@ -22,28 +126,138 @@ impl Socket {
             inner: BufStream::new(socket),
             read_buf: Default::default(),
             write_buf: Default::default(),
+            framing: Framing::default(),
+            max_msg: MAX_MSG_LEN,
+            ended: false,
         }
     }
 
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    pub fn set_max_msg(&mut self, max_msg: usize) {
+        self.max_msg = max_msg;
+    }
+
+    /// Connect to the server at `path`, failing with [`Unreachable`] if there is none or it
+    /// doesn't accept in time.
     pub async fn connect(path: &Path) -> Result<Self> {
-        let socket = UnixStream::connect(path).await?;
+        let socket = time::timeout(CONNECT_TIMEOUT, UnixStream::connect(path))
+            .await
+            .map_err(|_| Unreachable::TimedOut {
+                socket: path.to_owned(),
+            })?
+            .map_err(|err| Unreachable::from_io(err, path))?;
         Ok(Self::from_unixstream(socket))
     }
 
+    /// Read the next message into `read_buf`, returns `false` once the other side is done,
+    /// either by hanging up or by an end frame.
+    ///
+    /// Fails with [`InvalidMsg`] on a message longer than allowed or a malformed frame header,
+    /// without reading more than that of it.
     pub async fn fetch_msg(&mut self) -> Result<bool> {
         self.read_buf.clear();
-        self.inner.read_until(b'\n', &mut self.read_buf).await?;
+        if self.inner.fill_buf().await?.first() != Some(&FRAME_TAG) {
+            let limit = self.max_msg as u64 + 1;
+            (&mut self.inner)
+                .take(limit)
+                .read_until(b'\n', &mut self.read_buf)
+                .await?;
+            if self.max_msg < self.read_buf.len() && self.read_buf.last() != Some(&b'\n') {
+                return Err(InvalidMsg(format!("message exceeds {} bytes", self.max_msg)).into());
+            }
+            if !self.read_buf.is_empty() {
+                self.framing = Framing::Lines;
+            }
+            return Ok(!self.read_buf.is_empty());
+        }
+
+        (&mut self.inner)
+            .take(MAX_HEADER_LEN)
+            .read_until(b'\n', &mut self.read_buf)
+            .await?;
+        let len: usize = from_utf8(&self.read_buf[1..])
+            .ok()
+            .filter(|header| header.ends_with('\n'))
+            .and_then(|header| header.trim_end().parse().ok())
+            .ok_or_else(|| {
+                InvalidMsg(format!(
+                    "invalid frame header {:?}",
+                    String::from_utf8_lossy(&self.read_buf)
+                ))
+            })?;
+        if self.max_msg < len {
+            return Err(InvalidMsg(format!(
+                "frame of {len} bytes exceeds {} bytes",
+                self.max_msg
+            ))
+            .into());
+        }
+
+        self.read_buf.clear();
+        if len == 0 {
+            self.ended = true;
+            return Ok(false);
+        }
+        self.read_buf.resize(len, 0);
+        self.inner
+            .read_exact(&mut self.read_buf)
+            .await
+            .context("connection closed in the middle of a frame")?;
+        self.framing = Framing::LengthPrefixed;
+
+        Ok(true)
+    }
+
+    /// Fetch the next message, returning its payload without the trailing newline.
+    pub async fn next_msg(&mut self) -> Result<Option<&[u8]>> {
+        if !self.fetch_msg().await? {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.read_buf.strip_suffix(b"\n").unwrap_or(&self.read_buf),
+        ))
+    }
 
-        Ok(!self.read_buf.is_empty())
+    /// Whether the next message already arrived, without waiting for it.
+    pub async fn has_pending(&mut self) -> bool {
+        poll_fn(|cx| {
+            Poll::Ready(matches!(
+                Pin::new(&mut self.inner).poll_fill_buf(cx),
+                Poll::Ready(Ok(buf)) if !buf.is_empty()
+            ))
+        })
+        .await
+    }
+
+    /// Fail unless the other side ended what it sent with [`Socket::write_end`], instead of
+    /// hanging up halfway through it.
+    pub fn ensure_ended(&self) -> Result<()> {
+        match self.ended || self.framing == Framing::Lines {
+            true => Ok(()),
+            false => Err(anyhow!("the server hung up before finishing its response")),
+        }
     }
 
     pub fn msg(&self) -> Result<&str> {
         from_utf8(&self.read_buf).map_err(Into::into)
     }
 
-    pub async fn read_all(&mut self) -> Result<&[u8]> {
-        self.inner.read_to_end(&mut self.read_buf).await?;
-        Ok(&self.read_buf)
+    /// Throw away everything the other side sends until it is done, without keeping any.
+    pub async fn drain(&mut self) -> Result<()> {
+        io::copy(&mut self.inner, &mut io::sink()).await?;
+        Ok(())
     }
 
     pub async fn flush(&mut self) -> Result<()> {
@@ -55,25 +269,33 @@ impl Socket {
 }
 
 impl Socket {
-    pub fn read_msg<'a, T: Deserialize<'a>>(&'a self) -> Result<T> {
-        let mut de = serde_json::Deserializer::from_slice(&self.read_buf);
-        let msg = Deserialize::deserialize(&mut de)?;
-        de.end()?;
-
-        Ok(msg)
+    pub fn write_msg<T: Serialize + ?Sized>(&mut self, msg: &T) -> Result<()> {
+        match self.framing {
+            Framing::Lines => {
+                serde_json::to_writer(&mut self.write_buf, msg)?;
+                self.write_buf.push(b'\n');
+            }
+            Framing::LengthPrefixed => self.write_raw(&serde_json::to_vec(msg)?),
+        }
+        Ok(())
     }
 
-    pub fn write_msg<T: Serialize>(&mut self, msg: &T) -> Result<()> {
-        let mut se = serde_json::Serializer::new(&mut self.write_buf);
-        Serialize::serialize(msg, &mut se)?;
-        self.write_buf.push(b'\n');
-        Ok(())
+    /// Queue the end of everything sent, an empty frame if length prefixed and nothing
+    /// otherwise, see [`Framing`].
+    pub fn write_end(&mut self) {
+        if let Framing::LengthPrefixed = self.framing {
+            self.write_buf.extend_from_slice(b"@0\n");
+        }
     }
-}
 
-impl Write for Socket {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.write_buf.extend_from_slice(s.as_bytes());
-        Ok(())
+    /// Queue `payload` as a single message, which is sent as is if newline delimited.
+    pub fn write_raw(&mut self, payload: &[u8]) {
+        if payload.is_empty() {
+            return;
+        }
+        if let Framing::LengthPrefixed = self.framing {
+            writeln!(self.write_buf, "@{}", payload.len()).expect("writing to vec never fails");
+        }
+        self.write_buf.extend_from_slice(payload);
     }
 }
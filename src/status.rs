@@ -0,0 +1,181 @@
+use crate::server::types::{OwnedReadResponse, Register};
+use anyhow::{anyhow, Result};
+
+/// A status line template like `{registers:|} {active:[*]}`, rendered from a `Read`.
+///
+/// Text is copied as is, `{{` and `}}` stand for literal braces. Placeholders are
+/// `{field}` or `{field:arg}`:
+/// - `registers`, `glyphs` (the glyphs of the registers from the config, or their numbers),
+///   `bindings` (`1:mail`), `workspaces` and `urgent` (the registers of urgent workspaces)
+///   list the state, `arg` is put between the entries and defaults to a space
+/// - `active` and `active_register` are the focused workspace and its register, and
+///   `active_icon` and `active_label` its settings, `active_windows` its window count and
+///   `active_glyph` the glyph of its register. `arg` is a pattern with `*` standing for the
+///   value and defaults to `*`, nothing is rendered without a value
+/// - `context` is the context switched to, with `arg` a pattern as for `active`
+#[derive(Debug)]
+pub struct Template<'a> {
+    parts: Vec<Part<'a>>,
+}
+
+#[derive(Debug)]
+enum Part<'a> {
+    Text(&'a str),
+    Brace(char),
+    List(List, &'a str),
+    Value(Value, &'a str),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum List {
+    Registers,
+    Glyphs,
+    Bindings,
+    Workspaces,
+    Urgent,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Active,
+    ActiveRegister,
+    ActiveIcon,
+    ActiveLabel,
+    ActiveWindows,
+    ActiveGlyph,
+    Context,
+}
+
+impl<'a> Template<'a> {
+    pub fn parse(template: &'a str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(at) = rest.find(['{', '}']) {
+            if at > 0 {
+                parts.push(Part::Text(&rest[..at]));
+            }
+            let brace = rest[at..].chars().next().expect("found a brace");
+            rest = &rest[at + 1..];
+
+            if let Some(escaped) = rest.strip_prefix(brace) {
+                parts.push(Part::Brace(brace));
+                rest = escaped;
+                continue;
+            }
+            if brace == '}' {
+                return Err(anyhow!("unmatched `}}`, write `}}}}` for a literal one"));
+            }
+
+            let end = rest
+                .find('}')
+                .ok_or_else(|| anyhow!("unterminated placeholder {{{rest}"))?;
+            parts.push(Self::placeholder(&rest[..end])?);
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest));
+        }
+
+        Ok(Self { parts })
+    }
+
+    fn placeholder(placeholder: &'a str) -> Result<Part<'a>> {
+        let (field, arg) = match placeholder.split_once(':') {
+            Some((field, arg)) => (field, Some(arg)),
+            None => (placeholder, None),
+        };
+        let list = |list| Part::List(list, arg.unwrap_or(" "));
+        let value = |value| Part::Value(value, arg.unwrap_or("*"));
+        Ok(match field {
+            "registers" => list(List::Registers),
+            "glyphs" => list(List::Glyphs),
+            "bindings" => list(List::Bindings),
+            "workspaces" => list(List::Workspaces),
+            "urgent" => list(List::Urgent),
+            "active" => value(Value::Active),
+            "active_register" => value(Value::ActiveRegister),
+            "active_icon" => value(Value::ActiveIcon),
+            "active_label" => value(Value::ActiveLabel),
+            "active_windows" => value(Value::ActiveWindows),
+            "active_glyph" => value(Value::ActiveGlyph),
+            "context" => value(Value::Context),
+            _ => return Err(anyhow!("unknown placeholder {{{placeholder}}}")),
+        })
+    }
+
+    pub fn render(&self, state: &OwnedReadResponse) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match *part {
+                Part::Text(text) => out.push_str(text),
+                Part::Brace(brace) => out.push(brace),
+                Part::List(list, separator) => {
+                    let entries: Vec<String> = match list {
+                        List::Registers => {
+                            state.registers.keys().map(ToString::to_string).collect()
+                        }
+                        List::Glyphs => state
+                            .registers
+                            .keys()
+                            .map(|register| glyph(state, *register))
+                            .collect(),
+                        List::Bindings => state
+                            .registers
+                            .iter()
+                            .map(|(register, name)| format!("{register}:{name}"))
+                            .collect(),
+                        List::Workspaces => {
+                            state.workspaces.keys().map(ToString::to_string).collect()
+                        }
+                        List::Urgent => state
+                            .registers
+                            .iter()
+                            .filter(|(_, name)| {
+                                state
+                                    .workspaces
+                                    .get(&**name)
+                                    .is_some_and(|settings| settings.urgent == Some(true))
+                            })
+                            .map(|(register, _)| register.to_string())
+                            .collect(),
+                    };
+                    out.push_str(&entries.join(separator));
+                }
+                Part::Value(value, pattern) => {
+                    let settings = state
+                        .active_workspace
+                        .as_ref()
+                        .and_then(|active| state.workspaces.get(active));
+                    let value = match value {
+                        Value::Active => state.active_workspace.as_deref().map(str::to_owned),
+                        Value::ActiveRegister => state.active_register.map(|r| r.to_string()),
+                        Value::ActiveIcon => settings.and_then(|settings| settings.icon.clone()),
+                        Value::ActiveLabel => settings.and_then(|settings| settings.label.clone()),
+                        Value::ActiveWindows => state
+                            .active_workspace
+                            .as_ref()
+                            .zip(state.windows.as_ref())
+                            .and_then(|(active, windows)| windows.get(active))
+                            .map(ToString::to_string),
+                        Value::ActiveGlyph => {
+                            state.active_register.map(|register| glyph(state, register))
+                        }
+                        Value::Context => state.context.as_deref().map(str::to_owned),
+                    };
+                    if let Some(value) = value {
+                        out.push_str(&pattern.replace('*', &value));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The glyph the config gives `register`, or its number.
+fn glyph(state: &OwnedReadResponse, register: Register) -> String {
+    match state.glyphs.get(&register) {
+        Some(glyph) => glyph.clone(),
+        None => register.to_string(),
+    }
+}
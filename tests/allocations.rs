@@ -0,0 +1,63 @@
+//! Checks that the hot paths of request handling don't allocate, counting every allocation
+//! made on the thread handling the request.
+
+use hypr_workspace_manager::server::{
+    simulation::{Handler, Simulation, Step},
+    types::Request,
+};
+use serde_json::json;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Allocations made while parsing and handling `request`, after handling it once to warm up
+/// buffers.
+async fn allocations(handler: &mut Handler, request: &str) -> usize {
+    handler
+        .handle(serde_json::from_str(request).unwrap())
+        .await
+        .unwrap();
+
+    let before = ALLOCATIONS.with(Cell::get);
+    let parsed: Request = serde_json::from_str(request).unwrap();
+    handler.handle(parsed).await.unwrap();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[tokio::test]
+async fn register_requests_dont_allocate() {
+    let mut simulation = Simulation::default();
+    let bind = json!({"bind": {"name": "mail", "register": 1, "force": true}});
+    simulation.step(Step::Request(bind)).await.unwrap();
+    let mut handler = simulation.handler().unwrap();
+
+    for request in [r#"{"goto":{"register":1}}"#, r#"{"moveto":{"register":1}}"#] {
+        assert_eq!(allocations(&mut handler, request).await, 0, "{request}");
+    }
+}
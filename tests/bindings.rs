@@ -0,0 +1,116 @@
+//! Checks that the registers of each workspace stay in sync with the workspace of each register.
+
+use hypr_workspace_manager::server::types::{Bindings, Register};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+const STEPS: u64 = 5000;
+const NAMES: [&str; 4] = ["mail", "chat", "code", "music"];
+
+/// Xorshift, reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    fn register(&mut self) -> Register {
+        self.below(8) as Register
+    }
+
+    fn name(&mut self) -> &'static str {
+        NAMES[self.below(NAMES.len() as u64) as usize]
+    }
+}
+
+/// Every workspace with the registers bound to it, worked out from the map by register.
+fn expected(bindings: &Bindings) -> BTreeMap<&str, BTreeSet<Register>> {
+    let mut expected = BTreeMap::<_, BTreeSet<_>>::new();
+    for (register, name) in bindings.iter() {
+        expected.entry(&**name).or_default().insert(*register);
+    }
+    expected
+}
+
+#[test]
+fn index_follows_every_change() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut bindings = Bindings::default();
+    let mut model = BTreeMap::<Register, &str>::new();
+
+    for step in 0..STEPS {
+        match rng.below(10) {
+            0..=5 => {
+                let (register, name) = (rng.register(), rng.name());
+                let previous = bindings.insert(register, Arc::from(name));
+                assert_eq!(previous.as_deref(), model.insert(register, name), "{step}");
+            }
+            6..=7 => {
+                let register = rng.register();
+                let removed = bindings.remove(&register);
+                assert_eq!(removed.as_deref(), model.remove(&register), "{step}");
+            }
+            8 => {
+                let name = rng.name();
+                let removed = bindings.remove_workspace(name);
+                let bound: BTreeSet<_> = model
+                    .iter()
+                    .filter(|(_, bound)| **bound == name)
+                    .map(|(register, _)| *register)
+                    .collect();
+                model.retain(|_, bound| *bound != name);
+                assert_eq!(removed, bound, "{step}");
+            }
+            _ => {
+                if rng.below(20) == 0 {
+                    bindings.clear();
+                    model.clear();
+                }
+            }
+        }
+
+        let registers: BTreeMap<_, _> = bindings.iter().map(|(r, n)| (*r, &**n)).collect();
+        assert_eq!(registers, model, "{step}");
+        let expected = expected(&bindings);
+        for name in NAMES {
+            let of: BTreeSet<_> = bindings.of(name).collect();
+            assert_eq!(
+                of,
+                expected.get(name).cloned().unwrap_or_default(),
+                "{step}"
+            );
+            assert_eq!(
+                bindings.is_bound(name),
+                expected.contains_key(name),
+                "{step}"
+            );
+        }
+    }
+}
+
+#[test]
+fn rebinding_moves_the_register() {
+    let mut bindings = Bindings::default();
+    bindings.insert(1, Arc::from("mail"));
+    bindings.insert(2, Arc::from("mail"));
+    assert_eq!(
+        bindings.insert(1, Arc::from("chat")).as_deref(),
+        Some("mail")
+    );
+    assert_eq!(bindings.of("mail").collect::<Vec<_>>(), [2]);
+    assert_eq!(bindings.of("chat").collect::<Vec<_>>(), [1]);
+
+    assert_eq!(
+        bindings.insert(2, Arc::from("mail")).as_deref(),
+        Some("mail")
+    );
+    assert_eq!(bindings.of("mail").collect::<Vec<_>>(), [2]);
+    bindings.remove(&2);
+    assert!(!bindings.is_bound("mail"));
+}
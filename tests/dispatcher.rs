@@ -0,0 +1,106 @@
+//! Sends batches through the dispatcher to a fake hyprland socket.
+
+use hypr_workspace_manager::hypr::{Dispatcher, Hypr, Workspace};
+use std::{path::PathBuf, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixListener,
+    sync::mpsc,
+    time,
+};
+
+/// A hyprland socket taking its time to answer each batch with `ok`, so others queue up
+/// meanwhile, along with the batches it received.
+fn slow_hypr(name: &str) -> (PathBuf, mpsc::UnboundedReceiver<String>) {
+    let path = std::env::temp_dir().join(format!("ws-mgr-{name}-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    let (received, batches) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let len = socket.read(&mut request).await.unwrap();
+            let _ = received.send(String::from_utf8_lossy(&request[..len]).into_owned());
+            time::sleep(Duration::from_millis(50)).await;
+            let _ = socket.write_all(b"ok").await;
+        }
+    });
+    (path, batches)
+}
+
+/// Batches queued while another one is sent go out together, unless they wait for a reply.
+#[tokio::test]
+async fn queued_batches_get_merged() {
+    let (path, mut batches) = slow_hypr("merge");
+    let dispatcher = Dispatcher::spawn(&path);
+    let mut hyprs: Vec<_> = ["a", "b", "c", "d"]
+        .into_iter()
+        .map(|dispatch| {
+            let mut hypr = Hypr::dispatched(dispatcher.clone());
+            hypr.dispatch(dispatch);
+            hypr
+        })
+        .collect();
+
+    let mut reply = Vec::new();
+    let [a, b, c, d] = &mut hyprs[..] else {
+        unreachable!()
+    };
+    let (a, b, c, d) = tokio::join!(
+        a.flush(Some(&mut reply)),
+        b.flush(None),
+        c.flush(None),
+        d.flush(None)
+    );
+    a.unwrap();
+    b.unwrap();
+    c.unwrap();
+    d.unwrap();
+
+    assert_eq!(reply, b"ok");
+    assert_eq!(batches.recv().await.unwrap(), "[[BATCH]]/dispatch a;");
+    assert_eq!(
+        batches.recv().await.unwrap(),
+        "[[BATCH]]/dispatch b;/dispatch c;/dispatch d;"
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+/// A batch switching workspaces is sent before others that queued up earlier.
+#[tokio::test]
+async fn navigation_skips_ahead() {
+    let (path, mut batches) = slow_hypr("priority");
+    let dispatcher = Dispatcher::spawn(&path);
+    let mut first = Hypr::dispatched(dispatcher.clone());
+    first.dispatch("a");
+    let mut bookkeeping = Hypr::dispatched(dispatcher.clone());
+    bookkeeping.keyword("workspace", "name:mail, gapsin:4");
+    let mut navigation = Hypr::dispatched(dispatcher.clone());
+    navigation.go_to(Workspace::Name("mail"));
+
+    let (mut a, mut b, mut c) = (Vec::new(), Vec::new(), Vec::new());
+    let (first, (bookkeeping, navigation)) = tokio::join!(first.flush(Some(&mut a)), async {
+        // Only queue up once the first batch is on its way.
+        time::sleep(Duration::from_millis(10)).await;
+        tokio::join!(
+            bookkeeping.flush(Some(&mut b)),
+            navigation.flush(Some(&mut c)),
+        )
+    });
+    first.unwrap();
+    bookkeeping.unwrap();
+    navigation.unwrap();
+
+    assert_eq!(batches.recv().await.unwrap(), "[[BATCH]]/dispatch a;");
+    assert_eq!(
+        batches.recv().await.unwrap(),
+        "[[BATCH]]/dispatch workspace name:mail;"
+    );
+    assert_eq!(
+        batches.recv().await.unwrap(),
+        "[[BATCH]]/keyword workspace name:mail, gapsin:4;"
+    );
+    let _ = std::fs::remove_file(&path);
+}
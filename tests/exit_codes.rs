@@ -0,0 +1,82 @@
+//! Checks the exit codes the cli promises in `--help`, running it against sockets no server
+//! listens on, which must fail right away.
+
+use hypr_workspace_manager::server::types::Failure;
+use std::{
+    fs,
+    os::unix::net::UnixListener,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+/// A path in the temp dir nothing else uses, with nothing there yet.
+fn socket(name: &str) -> PathBuf {
+    let socket = std::env::temp_dir().join(format!("ws-mgr-{}-{name}.sock", process::id()));
+    let _ = fs::remove_file(&socket);
+    socket
+}
+
+fn run(socket: &Path, args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_hypr-workspace-manager"))
+        .args(args)
+        .env("WS_MGR_SOCKET", socket)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn missing_socket() {
+    let socket = socket("missing");
+
+    for args in [&["ping"][..], &["g3"]] {
+        let (code, stderr) = run(&socket, args);
+        assert_eq!(code, Some(3), "{args:?}: {stderr}");
+        assert!(stderr.contains("no server is running at"), "{stderr}");
+    }
+}
+
+#[test]
+fn stale_socket() {
+    let socket = socket("stale");
+    // The file stays behind once the listener is gone, like after a crash.
+    drop(UnixListener::bind(&socket).unwrap());
+
+    let (code, stderr) = run(&socket, &["ping"]);
+    assert_eq!(code, Some(3), "{stderr}");
+    assert!(stderr.contains("`ws-mgr server`"), "{stderr}");
+    fs::remove_file(&socket).unwrap();
+}
+
+#[test]
+fn invalid_arguments() {
+    let socket = socket("usage");
+
+    for args in [
+        &["bind", "dev"][..],
+        &["bind", "dev", "x"],
+        &["no-such-command"],
+    ] {
+        let (code, stderr) = run(&socket, args);
+        assert_eq!(code, Some(2), "{args:?}: {stderr}");
+    }
+}
+
+#[test]
+fn server_errors() {
+    for (failure, code) in [
+        (r#"{"error":"register_unbound","register":2}"#, 4),
+        (r#"{"error":"unknown_workspace","name":"dev"}"#, 4),
+        (r#"{"error":"hypr_ipc","message":"gone"}"#, 5),
+        (r#"{"error":"conflict","register":2,"current":null}"#, 6),
+        (r#"{"error":"stale","expected":1,"current":2}"#, 6),
+        (r#"{"error":"rejected","message":"no"}"#, 1),
+    ] {
+        let failure: Failure = serde_json::from_str(failure).unwrap();
+        assert_eq!(failure.exit_code(), code, "{failure:?}");
+    }
+}
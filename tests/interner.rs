@@ -0,0 +1,31 @@
+//! Checks that workspace names are shared instead of copied.
+
+use hypr_workspace_manager::server::types::Interner;
+use std::sync::Arc;
+
+#[test]
+fn equal_names_share_the_first_allocation() {
+    let mut names = Interner::default();
+    let first: Arc<str> = Arc::from("mail");
+    let again: Arc<str> = Arc::from("mail");
+
+    let interned = names.intern(&first);
+    assert!(Arc::ptr_eq(&interned, &first));
+    assert!(Arc::ptr_eq(&names.intern(&again), &first));
+    assert!(Arc::ptr_eq(names.get("mail").unwrap(), &first));
+    assert!(names.get("chat").is_none());
+    assert_eq!(names.len(), 1);
+}
+
+#[test]
+fn released_names_are_interned_anew() {
+    let mut names = Interner::default();
+    let first = names.intern(&Arc::from("mail"));
+    names.release("mail");
+    assert!(names.is_empty());
+
+    let again: Arc<str> = Arc::from("mail");
+    assert!(Arc::ptr_eq(&names.intern(&again), &again));
+    assert!(!Arc::ptr_eq(&again, &first));
+    assert_eq!(&*first, "mail");
+}
@@ -0,0 +1,74 @@
+//! Feeds the server connections of made up junk, seeded like the property tests, and checks a
+//! client sending it can't take the server down or wedge it.
+
+use hypr_workspace_manager::server::simulation::Simulation;
+use serde_json::json;
+use std::{fmt::Write, time::Duration};
+use tokio::time::timeout;
+
+const CASES: u64 = 300;
+const MAX_MESSAGE: usize = 64;
+
+/// Xorshift, good enough to make up junk and reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// Bytes that mean something to the framing or to json, mixed with arbitrary ones.
+fn junk(rng: &mut Rng) -> Vec<u8> {
+    const BYTES: &[u8] = b"@\n\n0179{}[]\":,\\ x";
+    let len = rng.below(4 * MAX_MESSAGE as u64) as usize;
+    (0..len)
+        .map(|_| match rng.below(4) {
+            0 => rng.next() as u8,
+            _ => BYTES[rng.below(BYTES.len() as u64) as usize],
+        })
+        .collect()
+}
+
+fn step(script: &mut String, step: serde_json::Value) {
+    writeln!(script, "{step}").unwrap();
+}
+
+#[tokio::test]
+async fn junk_never_takes_the_server_down() {
+    let mut script = String::new();
+    step(
+        &mut script,
+        json!({"config": format!("[connections]\nmax_message = {MAX_MESSAGE}\n")}),
+    );
+    for seed in 0..CASES {
+        step(&mut script, json!({"raw": junk(&mut Rng::new(seed))}));
+    }
+    // Far more than allowed, which mustn't be read in full.
+    step(&mut script, json!({"raw": vec![b'x'; 100_000]}));
+    step(&mut script, json!({"request": "ping"}));
+
+    let transcript = timeout(Duration::from_secs(30), Simulation::default().run(&script))
+        .await
+        .expect("the server got wedged")
+        .unwrap();
+    assert!(
+        transcript.ends_with(
+            "> \"ping\"\n< \"pong\"\n= {\"version\":2,\"workspaces\":{},\"registers\":{},\"generation\":0}\n"
+        ),
+        "{}",
+        &transcript[transcript.len().saturating_sub(500)..]
+    );
+    assert!(transcript.contains(&format!("message exceeds {MAX_MESSAGE} bytes")));
+}
@@ -0,0 +1,47 @@
+//! Renders the manual, which has to cover every command and request.
+
+use clap::CommandFactory;
+use hypr_workspace_manager::{
+    cli::Cli,
+    man::{self, Style},
+    schema,
+};
+
+#[test]
+fn covers_every_command_and_request() {
+    let protocol = schema::protocol();
+    for style in [Style::Roff, Style::Plain] {
+        let page = man::render(&Cli::command(), &protocol, style);
+        for command in Cli::command().get_subcommands() {
+            let name = command.get_name().replace('-', r"\-");
+            let name = match style {
+                Style::Roff => format!(".SS {name}\n"),
+                Style::Plain => format!("\n    {}\n", command.get_name()),
+            };
+            assert!(
+                page.contains(&name),
+                "{style:?} misses {}",
+                command.get_name()
+            );
+        }
+        for request in protocol["requests"].as_object().unwrap().keys() {
+            assert!(
+                page.contains(request.as_str()),
+                "{style:?} misses {request}"
+            );
+        }
+        assert!(page.contains("EXIT STATUS"));
+    }
+}
+
+#[test]
+fn roff_only_uses_known_macros() {
+    let page = man::render(&Cli::command(), &schema::protocol(), Style::Roff);
+    for line in page.lines().filter(|line| line.starts_with(['.', '\''])) {
+        let request = line.split_whitespace().next().unwrap();
+        assert!(
+            [".TH", ".SH", ".SS", ".PP", ".TP", ".sp"].contains(&request),
+            "{line:?}"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! Checks what the cli prints with `--quiet` and `--verbose`.
+
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hypr-workspace-manager"))
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn quiet_prints_nothing() {
+    let loud = run(&["schema"]);
+    assert!(loud.status.success());
+    assert!(!loud.stdout.is_empty());
+
+    for args in [&["-q", "schema"], &["schema", "--quiet"]] {
+        let quiet = run(args);
+        assert!(quiet.status.success(), "{args:?}");
+        assert!(quiet.stdout.is_empty(), "{args:?}");
+        assert!(quiet.stderr.is_empty(), "{args:?}");
+    }
+}
+
+#[test]
+fn quiet_and_verbose_conflict() {
+    assert_eq!(run(&["-q", "-v", "schema"]).status.code(), Some(2));
+}
@@ -0,0 +1,398 @@
+//! Property tests for the protocol, run against requests made up by a seeded generator.
+//!
+//! A failing case is reported with the seed it was generated from, so it can be replayed.
+
+use hypr_workspace_manager::{
+    hypr,
+    server::types::{
+        DynamicRule, ImportMode, MasterOrientation, Request, Snapshot, Tagged, Workspace,
+        WorkspaceSettings,
+    },
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+const CASES: u64 = 2000;
+
+/// Xorshift, good enough to make up requests and reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn chance(&mut self) -> bool {
+        self.below(2) == 0
+    }
+
+    fn maybe<T>(&mut self, value: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        self.chance().then(|| value(self))
+    }
+
+    /// A name as users would give it: numbers, spaces, `:`, non-ascii.
+    ///
+    /// Quotes, backslashes and control characters are left out, json escapes them and escaped
+    /// strings can't be borrowed from the request.
+    fn name(&mut self) -> String {
+        const CHARS: &[char] = &[
+            'a', 'z', 'Q', '0', '3', '7', ' ', ':', '-', '/', 'é', '漢', '🦀',
+        ];
+        match self.below(4) {
+            // Names that look like registers.
+            0 => self.below(70_000).to_string(),
+            _ => (0..self.below(12))
+                .map(|_| CHARS[self.below(CHARS.len() as u64) as usize])
+                .collect(),
+        }
+    }
+}
+
+fn workspace<'a>(rng: &mut Rng, names: &'a [String]) -> Workspace<'a> {
+    match rng.chance() {
+        true => Workspace::Register(rng.next() as u16),
+        false => Workspace::Workspace(&names[rng.below(names.len() as u64) as usize]),
+    }
+}
+
+fn settings(rng: &mut Rng) -> WorkspaceSettings {
+    WorkspaceSettings {
+        monitor: rng.maybe(Rng::name),
+        gaps_in: rng.maybe(|rng| rng.next() as u32),
+        gaps_out: rng.maybe(|rng| rng.next() as u32),
+        persistent: rng.maybe(Rng::chance),
+        layout: rng.maybe(Rng::name),
+        master_orientation: rng.maybe(|rng| match rng.below(5) {
+            0 => MasterOrientation::Left,
+            1 => MasterOrientation::Right,
+            2 => MasterOrientation::Top,
+            3 => MasterOrientation::Bottom,
+            _ => MasterOrientation::Center,
+        }),
+        cwd: rng.maybe(Rng::name),
+        special: rng.maybe(Rng::chance),
+        icon: rng.maybe(Rng::name),
+        label: rng.maybe(Rng::name),
+        context: rng.maybe(Rng::name),
+        urgent: rng.maybe(Rng::chance),
+        windowrules: (0..rng.below(3)).map(|_| rng.name()).collect(),
+    }
+}
+
+fn request<'a>(rng: &mut Rng, names: &'a [String], depth: u32) -> Request<'a> {
+    let name = |rng: &mut Rng| -> &'a str { &names[rng.below(names.len() as u64) as usize] };
+    let variants = if depth == 0 { 38 } else { 37 };
+    match rng.below(variants) {
+        0 => Request::Create { name: name(rng) },
+        1 => Request::CreateFrom {
+            name: name(rng),
+            template: name(rng),
+        },
+        2 => Request::Bind {
+            name: name(rng),
+            register: rng.next() as u16,
+            force: rng.chance(),
+            ttl: rng.maybe(|rng| rng.next() as u32),
+        },
+        3 => Request::BindDynamic {
+            register: rng.next() as u16,
+            rule: match rng.chance() {
+                true => DynamicRule::Current,
+                false => DynamicRule::Previous,
+            },
+            force: rng.chance(),
+        },
+        4 => Request::Unbind {
+            register: rng.next() as u16,
+            force: rng.chance(),
+        },
+        5 => Request::Configure {
+            name: name(rng),
+            settings: settings(rng),
+        },
+        6 => Request::Merge {
+            from: workspace(rng, names),
+            to: workspace(rng, names),
+            delete: rng.chance(),
+        },
+        7 => Request::OpenProject {
+            path: name(rng),
+            register: rng.maybe(|rng| rng.next() as u16),
+            terminal: rng.chance(),
+        },
+        8 => Request::Goto {
+            register: rng.next() as u16,
+        },
+        9 => Request::Moveto {
+            register: rng.next() as u16,
+        },
+        10 => Request::Read {
+            workspace: rng.maybe(|rng| workspace(rng, names)),
+            stream: rng.chance(),
+            registers: rng.maybe(|rng| {
+                let start = rng.next() as u16;
+                start..=start.saturating_add(rng.next() as u16 % 10)
+            }),
+        },
+        11 => Request::Gc,
+        12 => Request::Undo,
+        13 => Request::History {
+            limit: rng.maybe(|rng| rng.next() as usize),
+        },
+        14 => Request::Export,
+        15 => Request::Import {
+            mode: match rng.chance() {
+                true => ImportMode::Merge,
+                false => ImportMode::Replace,
+            },
+            state: Snapshot {
+                version: Snapshot::VERSION,
+                workspaces: (0..rng.below(4))
+                    .map(|_| (Arc::from(name(rng)), settings(rng)))
+                    .collect(),
+                registers: (0..rng.below(4))
+                    .map(|_| (rng.next() as u16, Arc::from(name(rng))))
+                    .collect(),
+                dynamic: (0..rng.below(3))
+                    .map(|_| {
+                        let rule = match rng.chance() {
+                            true => DynamicRule::Current,
+                            false => DynamicRule::Previous,
+                        };
+                        (rng.next() as u16, rule)
+                    })
+                    .collect(),
+                locked: (0..rng.below(3)).map(|_| rng.next() as u16).collect(),
+                expiring: (0..rng.below(3))
+                    .map(|_| (rng.next() as u16, rng.next()))
+                    .collect(),
+                context: rng.maybe(|rng| Arc::from(name(rng))),
+                groups: (0..rng.below(3))
+                    .map(|_| {
+                        let members = (0..rng.below(3)).map(|_| Arc::from(name(rng))).collect();
+                        (Arc::from(name(rng)), members)
+                    })
+                    .collect(),
+                pinned: (0..rng.below(3))
+                    .map(|_| (Arc::from(name(rng)), Arc::from(name(rng))))
+                    .collect(),
+                glyphs: (0..rng.below(3))
+                    .map(|_| (rng.next() as u16, name(rng).to_owned()))
+                    .collect(),
+                urgent: (0..rng.below(3)).map(|_| Arc::from(name(rng))).collect(),
+                generation: rng.maybe(|rng| rng.next()),
+            },
+        },
+        16 => Request::Subscribe,
+        17 => Request::Stats,
+        18 => Request::Metrics,
+        19 => Request::Logs {
+            lines: rng.maybe(|rng| rng.next() as usize),
+        },
+        20 => Request::Ping,
+        21 => Request::Flush,
+        22 => Request::Preview,
+        23 => Request::Handoff,
+        24 => Request::Reload,
+        25 => Request::GotoMru {
+            n: rng.next() as usize,
+        },
+        26 => Request::Lock {
+            register: rng.next() as u16,
+        },
+        27 => Request::Unlock {
+            register: rng.next() as u16,
+        },
+        28 => Request::BindIf {
+            name: name(rng),
+            register: rng.next() as u16,
+            expected: rng.maybe(|rng| name(rng)),
+            force: rng.chance(),
+        },
+        29 => Request::MovetoHere {
+            register: rng.next() as u16,
+        },
+        30 => Request::PinMonitor {
+            register: rng.next() as u16,
+            monitor: rng.maybe(|rng| name(rng)),
+        },
+        31 => Request::UnpinMonitor {
+            register: rng.next() as u16,
+        },
+        32 => Request::GroupCreate {
+            group: name(rng),
+            members: (0..rng.below(4)).map(|_| name(rng)).collect(),
+        },
+        33 => Request::GroupRemove { group: name(rng) },
+        34 => Request::GroupGoto { group: name(rng) },
+        35 => Request::GroupRead {
+            group: rng.maybe(|rng| name(rng)),
+        },
+        36 => Request::SwitchContext {
+            context: rng.maybe(|rng| name(rng)),
+        },
+        // Batches don't nest, the server rejects them anyway.
+        _ => Request::Batch(
+            (0..rng.below(5))
+                .map(|_| request(rng, names, depth + 1))
+                .collect(),
+        ),
+    }
+}
+
+/// Check `property` against a request generated from every seed up to [`CASES`].
+fn for_all(property: impl Fn(&Request, &str)) {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let names: Vec<_> = (0..4).map(|_| rng.name()).collect();
+        let request = request(&mut rng, &names, 0);
+        let json = serde_json::to_string(&request).unwrap();
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| property(&request, &json)));
+        if res.is_err() {
+            panic!("property failed for seed {seed}: {json}");
+        }
+    }
+}
+
+#[test]
+fn requests_round_trip() {
+    for_all(|_, json| {
+        let parsed: Request = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    });
+}
+
+#[test]
+fn tagged_requests_round_trip() {
+    for_all(|request, json| {
+        let json = serde_json::to_string(&Tagged {
+            id: 7,
+            request: serde_json::from_str(&serde_json::to_string(request).unwrap()).unwrap(),
+            if_generation: (json.len() % 2 == 0).then_some(42),
+        })
+        .unwrap();
+        let parsed: Tagged = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.id, 7);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    });
+}
+
+/// Registers are numbers and names are strings on the wire, even names made of digits.
+#[test]
+fn workspaces_keep_registers_and_names_apart() {
+    for register in 0..=u16::MAX {
+        let json = serde_json::to_string(&Workspace::Register(register)).unwrap();
+        let parsed: Workspace = serde_json::from_str(&json).unwrap();
+        assert!(
+            matches!(parsed, Workspace::Register(r) if r == register),
+            "{json}"
+        );
+
+        let name = register.to_string();
+        let json = serde_json::to_string(&Workspace::Workspace(&name)).unwrap();
+        let parsed: Workspace = serde_json::from_str(&json).unwrap();
+        assert!(
+            matches!(parsed, Workspace::Workspace(n) if n == name),
+            "{json}"
+        );
+    }
+
+    for json in ["65536", "-1", "1.5", "null", "[]", "{}", "\"a\\\"b\""] {
+        assert!(serde_json::from_str::<Workspace>(json).is_err(), "{json}");
+    }
+}
+
+/// The text form of a workspace parses back to the same workspace, names that look like
+/// registers or prefixes included.
+#[test]
+fn workspaces_parse_from_their_text_form() {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let names = [rng.name(), format!("reg:{}", rng.name())];
+        let workspace = workspace(&mut rng, &names);
+
+        let text = workspace.to_string();
+        let parsed = Workspace::parse(&text).unwrap();
+        assert_eq!(
+            serde_json::to_string(&parsed).unwrap(),
+            serde_json::to_string(&workspace).unwrap(),
+            "seed {seed}: {text}"
+        );
+    }
+
+    assert!(matches!(Workspace::parse("7"), Ok(Workspace::Register(7))));
+    assert!(matches!(
+        Workspace::parse("name:7"),
+        Ok(Workspace::Workspace("7"))
+    ));
+    assert!(matches!(
+        Workspace::parse("mail"),
+        Ok(Workspace::Workspace("mail"))
+    ));
+    assert!(Workspace::parse("reg:mail").is_err());
+}
+
+/// Whatever garbage comes in, parsing fails instead of panicking.
+#[test]
+fn mangled_requests_dont_panic() {
+    const JUNK: &[u8] = b"{}[]\":,0123456789-.e\\ nultrfase";
+
+    for_all(|_, json| {
+        let mut rng = Rng::new(json.len() as u64);
+        let mut bytes = json.as_bytes().to_vec();
+        for _ in 0..=rng.below(4) {
+            let at = rng.below(bytes.len() as u64 + 1) as usize;
+            let junk = JUNK[rng.below(JUNK.len() as u64) as usize];
+            match rng.below(3) {
+                0 if at < bytes.len() => bytes[at] = junk,
+                1 if at < bytes.len() => drop(bytes.remove(at)),
+                _ => bytes.insert(at, junk),
+            }
+        }
+
+        let _ = serde_json::from_slice::<Request>(&bytes);
+        let _ = serde_json::from_slice::<Tagged>(&bytes);
+    });
+}
+
+/// Hyprland workspace selectors parse back from how they are sent to hyprland.
+#[test]
+fn hypr_selectors_parse_from_their_text_form() {
+    for selector in [
+        "3",
+        "+1",
+        "-2",
+        "m+1",
+        "m-1",
+        "r+2",
+        "e+1",
+        "e-1",
+        "previous",
+        "empty",
+        "name:mail",
+        "name:7",
+        "special",
+        "special:scratch",
+    ] {
+        let parsed = hypr::Workspace::parse(selector).unwrap();
+        assert_eq!(parsed.to_string(), selector);
+    }
+
+    for selector in ["", "0", "m1", "e", "x+1", "name:a;/dispatch exit", "1.5"] {
+        assert!(hypr::Workspace::parse(selector).is_none(), "{selector}");
+    }
+}
@@ -0,0 +1,10 @@
+//! Builds the protocol description, which parses the example of every request it describes
+//! against the actual definition of the requests.
+
+use hypr_workspace_manager::schema;
+
+#[test]
+fn examples_are_valid_requests() {
+    let protocol = schema::protocol();
+    assert!(protocol["requests"]["ping"]["read_only"].as_bool().unwrap());
+}
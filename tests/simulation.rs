@@ -0,0 +1,44 @@
+//! Replays every script in `tests/scenarios` and compares the transcript with the `.out` file
+//! next to it.
+//!
+//! With the `chaos` feature, the scripts in `tests/resilience` injecting faults are replayed as
+//! well.
+
+use hypr_workspace_manager::server::simulation::Simulation;
+use std::{fs, path::Path, time::Duration};
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn scenarios() {
+    replay("tests/scenarios").await;
+}
+
+#[cfg(feature = "chaos")]
+#[tokio::test]
+async fn resilience() {
+    replay("tests/resilience").await;
+}
+
+async fn replay(dir: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut scripts: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    scripts.sort();
+
+    for script in scripts {
+        // A server deadlocking on its own state would otherwise hang the test forever.
+        let transcript = timeout(
+            Duration::from_secs(10),
+            Simulation::default().run(&fs::read_to_string(&script).unwrap()),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("{} deadlocked", script.display()))
+        .unwrap();
+        let expected = fs::read_to_string(script.with_extension("out")).unwrap();
+
+        assert_eq!(transcript, expected, "{}", script.display());
+    }
+}
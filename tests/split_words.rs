@@ -0,0 +1,48 @@
+//! Checks how agent lines and aliases are split into words.
+
+use hypr_workspace_manager::cli::split_words;
+
+fn words(line: &str) -> Vec<String> {
+    split_words(line).unwrap()
+}
+
+#[test]
+fn whitespace_splits_outside_quotes() {
+    assert_eq!(words("  goto\t1 \n"), ["goto", "1"]);
+    assert_eq!(
+        words(r#"bind "my workspace" 2"#),
+        ["bind", "my workspace", "2"]
+    );
+    assert_eq!(
+        words(r#"create my" "workspace"#),
+        ["create", "my workspace"]
+    );
+    assert_eq!(words(r#"read """#), ["read", ""]);
+    assert!(words("   ").is_empty());
+}
+
+#[test]
+fn backslashes_take_the_next_character() {
+    assert_eq!(words(r#"create \"dev\""#), ["create", r#""dev""#]);
+    assert_eq!(words(r"create a\\b"), ["create", r"a\b"]);
+    assert_eq!(words(r"create my\ workspace"), ["create", "my workspace"]);
+    assert_eq!(
+        words(r#"create "say \"hi\" \\""#),
+        ["create", r#"say "hi" \"#]
+    );
+}
+
+#[test]
+fn unterminated_quote_fails() {
+    let err = split_words(r#"create "my workspace"#).unwrap_err();
+    assert!(err.to_string().contains("unterminated quote"), "{err}");
+    // The escaped quote doesn't close the one before.
+    assert!(split_words(r#"create "my\""#).is_err());
+}
+
+#[test]
+fn trailing_backslash_fails() {
+    let err = split_words(r"create dev\").unwrap_err();
+    assert!(err.to_string().contains("middle of an escape"), "{err}");
+    assert!(split_words(r#"create "dev\"#).is_err());
+}
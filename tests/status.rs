@@ -0,0 +1,55 @@
+//! Renders status line templates against a fixed state.
+
+use hypr_workspace_manager::{server::types::OwnedReadResponse, status::Template};
+
+fn state() -> OwnedReadResponse {
+    serde_json::from_str(
+        r#"{
+            "workspaces": {"chat": {}, "mail": {"icon": "M", "urgent": true}, "music": {}},
+            "registers": {"1": "mail", "2": "chat", "5": "mail"},
+            "active_workspace": "mail",
+            "active_register": 1,
+            "windows": {"chat": 0, "mail": 3, "music": 1},
+            "context": "work",
+            "glyphs": {"1": "●", "2": "○"}
+        }"#,
+    )
+    .unwrap()
+}
+
+fn render(template: &str) -> String {
+    Template::parse(template).unwrap().render(&state())
+}
+
+#[test]
+fn placeholders_render_the_state() {
+    assert_eq!(render("{registers:|} {active:[*]}"), "1|2|5 [mail]");
+    assert_eq!(render("{bindings:, }"), "1:mail, 2:chat, 5:mail");
+    assert_eq!(render("{workspaces}"), "chat mail music");
+    assert_eq!(render("{urgent:,}"), "1,5");
+    assert_eq!(
+        render("{active}: {active_windows:* windows}"),
+        "mail: 3 windows"
+    );
+    assert_eq!(render("ws {active_register}: {active}"), "ws 1: mail");
+    assert_eq!(render("{{{active}}}"), "{mail}");
+    assert_eq!(render("{active_icon:* }{active_label}{active}"), "M mail");
+    assert_eq!(render("{context:(*)}"), "(work)");
+    assert_eq!(render("{glyphs} [{active_glyph}]"), "● ○ 5 [●]");
+}
+
+#[test]
+fn missing_values_render_nothing() {
+    let mut state = state();
+    state.active_workspace = None;
+    state.active_register = None;
+    let template = Template::parse("<{active:[*]}{active_register:#*}{active_windows}>").unwrap();
+    assert_eq!(template.render(&state), "<>");
+}
+
+#[test]
+fn malformed_templates_are_rejected() {
+    for template in ["{active", "active}", "{nope}", "{registers"] {
+        assert!(Template::parse(template).is_err(), "{template}");
+    }
+}
@@ -0,0 +1,126 @@
+//! Checks what each lag policy does with a subscriber that fell behind.
+
+use hypr_workspace_manager::{
+    config::{Config, SubscriptionConfig},
+    server::{
+        subscription::{Fanout, Pending},
+        types::{Event, LagPolicy, Record},
+    },
+};
+use std::sync::Arc;
+
+fn fanout(policy: LagPolicy) -> Fanout {
+    Fanout::new(SubscriptionConfig {
+        capacity: 2,
+        policy,
+        ..Default::default()
+    })
+}
+
+fn record(seq: u64) -> Record {
+    Record {
+        seq,
+        time: 0,
+        event: Event::Created {
+            name: Arc::from(format!("ws{seq}")),
+            settings: Default::default(),
+        },
+    }
+}
+
+fn seqs(pending: Option<Pending>) -> Vec<u64> {
+    match pending {
+        Some(Pending::Records(records)) => records.iter().map(|record| record.seq).collect(),
+        other => panic!("expected records, got {other:?}"),
+    }
+}
+
+#[test]
+fn capacity_below_one_is_rejected() {
+    let err = toml::from_str::<Config>("[subscriptions]\ncapacity = 0\n").unwrap_err();
+    assert!(
+        err.to_string().contains("capacity must be at least 1"),
+        "{err}"
+    );
+    let config = toml::from_str::<Config>("[subscriptions]\ncapacity = 1\n").unwrap();
+    assert_eq!(config.subscriptions.capacity, 1);
+}
+
+#[test]
+fn drop_oldest_keeps_the_newest_records() {
+    let fanout = fanout(LagPolicy::DropOldest);
+    let subscriber = fanout.subscribe();
+    subscriber.snapshot_sent(None);
+    for seq in 0..5 {
+        fanout.publish(&record(seq));
+    }
+
+    let stats = subscriber.stats();
+    assert_eq!((stats.queued, stats.dropped, stats.lag), (2, 3, 5));
+    assert_eq!(seqs(subscriber.try_next()), [3, 4]);
+    assert!(subscriber.try_next().is_none());
+    assert_eq!(subscriber.stats().lag, 0);
+}
+
+#[test]
+fn drop_oldest_snapshot_after_overflow_skips_what_it_covers() {
+    let fanout = fanout(LagPolicy::DropOldest);
+    let subscriber = fanout.subscribe();
+    subscriber.snapshot_sent(None);
+    for seq in 0..4 {
+        fanout.publish(&record(seq));
+    }
+
+    fanout.refresh();
+    assert!(matches!(subscriber.try_next(), Some(Pending::Snapshot)));
+    // Published while the snapshot is pending, so it is part of it.
+    fanout.publish(&record(4));
+    subscriber.snapshot_sent(Some(4));
+    assert!(subscriber.try_next().is_none());
+    assert_eq!(subscriber.stats().lag, 0);
+
+    fanout.publish(&record(5));
+    assert_eq!(seqs(subscriber.try_next()), [5]);
+}
+
+#[tokio::test]
+async fn disconnect_closes_the_subscriber() {
+    let fanout = fanout(LagPolicy::Disconnect);
+    let subscriber = fanout.subscribe();
+    let other = fanout.subscribe();
+    subscriber.snapshot_sent(None);
+    other.snapshot_sent(None);
+    for seq in 0..2 {
+        fanout.publish(&record(seq));
+    }
+    assert_eq!(seqs(other.try_next()), [0, 1]);
+
+    fanout.publish(&record(2));
+    subscriber.disconnected().await;
+    assert!(subscriber.try_next().is_none());
+    assert!(subscriber.next().await.is_none());
+    assert_eq!(subscriber.stats().queued, 0);
+    // Only the one that kept up is left.
+    let ids: Vec<_> = fanout.stats().iter().map(|stats| stats.id).collect();
+    assert_eq!(ids, [other.stats().id]);
+    assert_eq!(seqs(other.try_next()), [2]);
+}
+
+#[test]
+fn coalesce_replaces_the_queue_with_a_snapshot() {
+    let fanout = fanout(LagPolicy::CoalesceToSnapshot);
+    let subscriber = fanout.subscribe();
+    subscriber.snapshot_sent(None);
+    for seq in 0..4 {
+        fanout.publish(&record(seq));
+    }
+
+    let stats = subscriber.stats();
+    assert_eq!((stats.queued, stats.dropped, stats.coalesced), (0, 2, 1));
+    assert!(matches!(subscriber.try_next(), Some(Pending::Snapshot)));
+    subscriber.snapshot_sent(Some(3));
+
+    fanout.publish(&record(4));
+    assert_eq!(seqs(subscriber.try_next()), [4]);
+    assert_eq!(subscriber.stats().lag, 0);
+}